@@ -0,0 +1,45 @@
+/*
+ * C interface of the whep-srt gateway (src/ffi.rs).
+ * Link against libwhep_srt.so, built with `cargo build --release`.
+ */
+#ifndef WHEP_SRT_H
+#define WHEP_SRT_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct WhepSrtGateway WhepSrtGateway;
+
+/* Called from a background thread with a JSON encoded lifecycle event */
+typedef void (*WhepSrtEventCallback)(const char *payload, void *user_data);
+
+/* Routes the gateway's log output to stderr, filtered by RUST_LOG (default info) */
+void whep_srt_init_logging(void);
+
+/*
+ * Creates a gateway from command line style arguments, excluding the program name.
+ * callback may be NULL. Returns NULL on invalid arguments or pipeline errors.
+ */
+WhepSrtGateway *whep_srt_create(int argc, const char *const *argv, WhepSrtEventCallback callback,
+                                void *user_data);
+
+/* Starts streaming on a background thread. Returns 0 on success */
+int whep_srt_start(WhepSrtGateway *gateway);
+
+/* Stops streaming and waits for shutdown. Returns 0 if the gateway ran without errors */
+int whep_srt_stop(WhepSrtGateway *gateway);
+
+/* Current status, statistics and process resource usage as JSON, free with whep_srt_free_string */
+char *whep_srt_stats(const WhepSrtGateway *gateway);
+
+void whep_srt_free_string(char *s);
+
+/* Stops the gateway if needed and releases it */
+void whep_srt_destroy(WhepSrtGateway *gateway);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* WHEP_SRT_H */
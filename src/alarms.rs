@@ -0,0 +1,259 @@
+use tracing::{info, warn};
+
+use crate::stats::Snapshot;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmKind {
+    SrtRetransmit,
+    WebRtcLoss,
+    Rtt,
+}
+
+impl AlarmKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlarmKind::SrtRetransmit => "srt-retransmit",
+            AlarmKind::WebRtcLoss => "webrtc-loss",
+            AlarmKind::Rtt => "rtt",
+        }
+    }
+}
+
+/// An alarm being raised or cleared
+#[derive(Debug, Clone)]
+pub struct AlarmChange {
+    pub kind: AlarmKind,
+    pub raised: bool,
+    pub value: f64,
+    pub threshold: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Thresholds {
+    pub srt_retransmit_pct: Option<f64>,
+    pub webrtc_loss_pct: Option<f64>,
+    pub rtt_ms: Option<f64>,
+}
+
+impl Thresholds {
+    pub fn is_empty(&self) -> bool {
+        self.srt_retransmit_pct.is_none() && self.webrtc_loss_pct.is_none() && self.rtt_ms.is_none()
+    }
+}
+
+/// Compares consecutive statistics snapshots against the thresholds and tracks which alarms are active
+pub struct Alarms {
+    thresholds: Thresholds,
+    previous: Option<Snapshot>,
+    active: Vec<AlarmKind>,
+}
+
+impl Alarms {
+    pub fn new(thresholds: Thresholds) -> Self {
+        Self {
+            thresholds,
+            previous: None,
+            active: Vec::new(),
+        }
+    }
+
+    pub fn evaluate(&mut self, snapshot: &Snapshot) -> Vec<AlarmChange> {
+        let mut changes = Vec::new();
+
+        if let Some(previous) = self.previous.take() {
+            //ratios are computed over the poll interval, not over the whole session
+            let retransmit_pct = match (&previous.srt, &snapshot.srt) {
+                (Some(prev), Some(cur)) => percentage(
+                    cur.packets_retransmitted()
+                        .saturating_sub(prev.packets_retransmitted()),
+                    cur.packets_sent().saturating_sub(prev.packets_sent()),
+                ),
+                _ => None,
+            };
+
+            let loss_pct = match (&previous.webrtc, &snapshot.webrtc) {
+                (Some(prev), Some(cur)) => {
+                    let lost = cur.packets_lost().saturating_sub(prev.packets_lost());
+                    let received = cur
+                        .packets_received()
+                        .saturating_sub(prev.packets_received());
+                    percentage(lost, lost + received)
+                }
+                _ => None,
+            };
+
+            let rtt_ms = snapshot.srt.as_ref().and_then(|srt| srt.max_rtt_ms());
+
+            let thresholds = self.thresholds.clone();
+            self.check(
+                AlarmKind::SrtRetransmit,
+                retransmit_pct,
+                thresholds.srt_retransmit_pct,
+                &mut changes,
+            );
+            self.check(
+                AlarmKind::WebRtcLoss,
+                loss_pct,
+                thresholds.webrtc_loss_pct,
+                &mut changes,
+            );
+            self.check(AlarmKind::Rtt, rtt_ms, thresholds.rtt_ms, &mut changes);
+        }
+
+        self.previous = Some(snapshot.clone());
+        changes
+    }
+
+    fn check(
+        &mut self,
+        kind: AlarmKind,
+        value: Option<f64>,
+        threshold: Option<f64>,
+        changes: &mut Vec<AlarmChange>,
+    ) {
+        let (Some(value), Some(threshold)) = (value, threshold) else {
+            return;
+        };
+
+        let active = self.active.contains(&kind);
+
+        if value > threshold && !active {
+            warn!(
+                "ALARM {}: {value:.2} exceeds threshold {threshold:.2}",
+                kind.as_str()
+            );
+            self.active.push(kind);
+        } else if value <= threshold && active {
+            info!(
+                "alarm {} cleared: {value:.2} is back within threshold {threshold:.2}",
+                kind.as_str()
+            );
+            self.active.retain(|k| *k != kind);
+        } else {
+            return;
+        }
+
+        changes.push(AlarmChange {
+            kind,
+            raised: !active,
+            value,
+            threshold,
+        });
+    }
+}
+
+fn percentage(part: u64, total: u64) -> Option<f64> {
+    if total == 0 {
+        return None;
+    }
+
+    Some(part as f64 * 100.0 / total as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::{InboundRtpStats, SrtSocketStats, SrtStats, WebRtcStats};
+
+    fn snapshot(sent: u64, retransmitted: u64, rtt_ms: f64, received: u64, lost: u64) -> Snapshot {
+        Snapshot {
+            srt: Some(SrtStats {
+                sockets: vec![SrtSocketStats {
+                    packets_sent: sent,
+                    packets_retransmitted: retransmitted,
+                    rtt_ms,
+                    ..Default::default()
+                }],
+            }),
+            webrtc: Some(WebRtcStats {
+                inbound: vec![InboundRtpStats {
+                    packets_received: received,
+                    packets_lost: lost,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn changes(changes: &[AlarmChange]) -> Vec<(AlarmKind, bool)> {
+        changes
+            .iter()
+            .map(|change| (change.kind, change.raised))
+            .collect()
+    }
+
+    #[test]
+    fn raises_and_clears() {
+        let mut alarms = Alarms::new(Thresholds {
+            srt_retransmit_pct: Some(5.0),
+            webrtc_loss_pct: Some(2.0),
+            rtt_ms: Some(200.0),
+        });
+
+        // nothing to compare the first snapshot with
+        assert!(alarms.evaluate(&snapshot(0, 0, 300.0, 0, 0)).is_empty());
+
+        let raised = alarms.evaluate(&snapshot(1000, 100, 300.0, 1000, 50));
+        assert_eq!(
+            changes(&raised),
+            [
+                (AlarmKind::SrtRetransmit, true),
+                (AlarmKind::WebRtcLoss, true),
+                (AlarmKind::Rtt, true),
+            ]
+        );
+        // 50 lost of the 1050 expected
+        assert!((raised[1].value - 50.0 * 100.0 / 1050.0).abs() < 1e-9);
+
+        // still above, nothing changes
+        assert!(
+            alarms
+                .evaluate(&snapshot(2000, 200, 250.0, 2000, 100))
+                .is_empty()
+        );
+
+        let cleared = alarms.evaluate(&snapshot(3000, 210, 100.0, 3000, 100));
+        assert_eq!(
+            changes(&cleared),
+            [
+                (AlarmKind::SrtRetransmit, false),
+                (AlarmKind::WebRtcLoss, false),
+                (AlarmKind::Rtt, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn rates_are_over_the_interval() {
+        let mut alarms = Alarms::new(Thresholds {
+            srt_retransmit_pct: Some(5.0),
+            ..Default::default()
+        });
+
+        alarms.evaluate(&snapshot(0, 0, 0.0, 0, 0));
+        alarms.evaluate(&snapshot(1000, 200, 0.0, 0, 0));
+        // still 10% over the session, but none since the previous snapshot
+        let cleared = alarms.evaluate(&snapshot(2000, 200, 0.0, 0, 0));
+        assert_eq!(changes(&cleared), [(AlarmKind::SrtRetransmit, false)]);
+        assert_eq!(cleared[0].value, 0.0);
+    }
+
+    #[test]
+    fn skips_what_cannot_be_measured() {
+        let mut alarms = Alarms::new(Thresholds {
+            srt_retransmit_pct: Some(5.0),
+            webrtc_loss_pct: Some(2.0),
+            rtt_ms: None,
+        });
+
+        // nothing sent or received in between, and no SRT leg at all
+        alarms.evaluate(&snapshot(1000, 100, 0.0, 1000, 100));
+        assert!(
+            alarms
+                .evaluate(&snapshot(1000, 100, 0.0, 1000, 100))
+                .is_empty()
+        );
+        assert!(alarms.evaluate(&Snapshot::default()).is_empty());
+    }
+}
@@ -0,0 +1,585 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches};
+use serde_json::{Value, json};
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{error, info, warn};
+
+use crate::args::Args;
+use crate::error::Error;
+use crate::gateway::Gateway;
+use crate::process::ProcessSampler;
+use crate::stats::StatsSampler;
+
+/// Options a create request can't set: they read or write files on the gateway, take gst-launch fragments, or
+/// change the whole process. The environment and the command line can still set them.
+const LOCAL_OPTIONS: &[&str] = &[
+    "pipeline_template",
+    "audio_filter",
+    "video_filter",
+    "record_path",
+    "dump_audio",
+    "save_sdp",
+    "dot_debug",
+    "gst_debug",
+    "api_listen",
+    "api_token",
+    "self_test",
+    "tui",
+];
+
+/// A response body other than json
+struct Document {
+    content_type: &'static str,
+    data: Vec<u8>,
+}
+
+struct Stream {
+    gateway: Gateway,
+    runner: JoinHandle<Result<(), Error>>,
+    /// Track bitrates in `GET /streams/{id}` are since the previous request for this stream
+    stats: Mutex<StatsSampler>,
+}
+
+/// HTTP control API managing any number of gateways, each identified by an id. With a token, every request has to
+/// carry it as `Authorization: Bearer <token>`.
+///
+/// - `GET /streams` lists the streams and their status
+/// - `POST /streams` creates and starts a stream from `{"id", "input_url", "output_url", "args": [..]}`
+/// - `GET /streams/{id}` returns status and statistics of one stream
+/// - `DELETE /streams/{id}` stops and removes a stream
+/// - `GET /streams/{id}/health` returns liveness and readiness, 200 when the stream is ready and 503 otherwise
+/// - `GET|PUT /streams/{id}/volume` reads or changes the master volume, `{"volume": 0.8}`
+/// - `GET|PUT /streams/{id}/bitrate` reads or changes the encoder bitrates in bit/s, `{"audio": 96000, "video": 2000000}`
+/// - `GET|PUT /streams/{id}/output` reads or switches the SRT destination, `{"output_url": "srt://.."}`
+/// - `POST /streams/{id}/pause` pauses the output, `{"slate": true}` sends silence instead of nothing
+/// - `POST /streams/{id}/resume` resumes a paused output
+/// - `GET /streams/{id}/graph` returns the pipeline as a Graphviz .dot document, `?format=svg` renders it with `dot`
+/// - `GET /process` returns CPU, memory and thread usage of the whole process
+#[derive(Clone)]
+pub struct Api {
+    server: Arc<Server>,
+    streams: Arc<Mutex<HashMap<String, Stream>>>,
+    next_id: Arc<AtomicU64>,
+    token: Option<Arc<str>>,
+    /// CPU usage in `GET /process` is since the previous request
+    process: Arc<Mutex<ProcessSampler>>,
+}
+
+impl Api {
+    pub fn bind(address: &str, token: Option<String>) -> Result<Self, Error> {
+        let server = Server::http(address)
+            .map_err(|err| Error::Config(format!("could not listen on {address}: {err}")))?;
+
+        info!("control api listening on http://{address}");
+        if token.is_none() {
+            warn!(
+                "the control api has no --api-token, anyone who can reach {address} can control the gateway"
+            );
+        }
+
+        Ok(Self {
+            server: Arc::new(server),
+            streams: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(1)),
+            token: token.map(Arc::from),
+            process: Arc::default(),
+        })
+    }
+
+    /// Builds a gateway and starts it on its own thread. Returns the id of the new stream.
+    pub fn create(&self, id: Option<String>, args: Args) -> Result<String, Error> {
+        let id = id.unwrap_or_else(|| self.next_id.fetch_add(1, Ordering::Relaxed).to_string());
+
+        if self.streams.lock().unwrap().contains_key(&id) {
+            return Err(Error::Config(format!("stream '{id}' already exists")));
+        }
+
+        // everything the stream logs, also from its streaming threads, is within this span
+        let span = tracing::info_span!("stream", id = %id);
+        let gateway = span.in_scope(|| Gateway::new(args))?;
+
+        let runner = gateway.clone();
+        let stream_id = id.clone();
+        let runner = std::thread::Builder::new()
+            .name(format!("stream-{id}"))
+            .spawn(move || {
+                let _entered = span.enter();
+                let result = runner.run();
+                if let Err(err) = &result {
+                    error!("stream '{stream_id}' ended: {err}");
+                }
+                result
+            })
+            .map_err(|err| Error::Pipeline(format!("could not spawn stream thread: {err}")))?;
+
+        info!("stream '{id}' created");
+        self.streams.lock().unwrap().insert(
+            id.clone(),
+            Stream {
+                gateway,
+                runner,
+                stats: Mutex::default(),
+            },
+        );
+
+        Ok(id)
+    }
+
+    /// Stops a stream and waits for its pipeline to shut down. Returns false for unknown ids.
+    pub fn destroy(&self, id: &str) -> bool {
+        let Some(stream) = self.streams.lock().unwrap().remove(id) else {
+            return false;
+        };
+
+        stream.gateway.stop();
+        let _ = stream.runner.join();
+
+        info!("stream '{id}' destroyed");
+        true
+    }
+
+    /// Destroys every stream, used on process shutdown
+    pub fn shutdown(&self) {
+        let ids: Vec<String> = self.streams.lock().unwrap().keys().cloned().collect();
+
+        for id in ids {
+            self.destroy(&id);
+        }
+    }
+
+    /// Serves requests on the calling thread, forever
+    pub fn serve(&self) {
+        for mut request in self.server.incoming_requests() {
+            if !self.authorized(&request) {
+                respond(request, 401, json!({ "error": "unauthorized" }));
+                continue;
+            }
+            match self.graph(&request) {
+                Some(Ok(document)) => respond_document(request, document),
+                Some(Err((status, body))) => respond(request, status, body),
+                None => {
+                    let (status, body) = self.handle(&mut request);
+                    respond(request, status, body);
+                }
+            }
+        }
+    }
+
+    /// Whether the request carries the --api-token, always without one
+    fn authorized(&self, request: &Request) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+
+        request
+            .headers()
+            .iter()
+            .filter(|header| header.field.equiv("Authorization"))
+            .filter_map(|header| header.value.as_str().strip_prefix("Bearer "))
+            .any(|presented| constant_time_eq(presented.trim().as_bytes(), token.as_bytes()))
+    }
+
+    /// `GET /streams/{id}/graph`, the one request not answered with json unless it fails. None for the others.
+    fn graph(&self, request: &Request) -> Option<Result<Document, (u16, Value)>> {
+        let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let (Method::Get, ["streams", id, "graph"]) = (request.method(), segments.as_slice())
+        else {
+            return None;
+        };
+
+        let Some(dot) = self
+            .streams
+            .lock()
+            .unwrap()
+            .get(*id)
+            .map(|stream| stream.gateway.graph())
+        else {
+            return Some(Err(not_found()));
+        };
+
+        let format = query
+            .split('&')
+            .find_map(|parameter| parameter.strip_prefix("format="))
+            .unwrap_or("dot");
+        Some(match format {
+            "dot" => Ok(Document {
+                content_type: "text/vnd.graphviz",
+                data: dot.into_bytes(),
+            }),
+            "svg" => render_svg(&dot)
+                .map(|svg| Document {
+                    content_type: "image/svg+xml",
+                    data: svg,
+                })
+                .map_err(|err| (500, json!({ "error": err }))),
+            _ => Err((
+                400,
+                json!({ "error": format!("unknown format '{format}', expected dot or svg") }),
+            )),
+        })
+    }
+
+    fn handle(&self, request: &mut Request) -> (u16, Value) {
+        let path = request
+            .url()
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        match (request.method(), segments.as_slice()) {
+            (Method::Get, ["streams"]) => {
+                let streams = self.streams.lock().unwrap();
+                let list: Vec<Value> = streams
+                    .iter()
+                    .map(|(id, stream)| json!({ "id": id, "status": stream.gateway.status().to_json() }))
+                    .collect();
+
+                (200, Value::from(list))
+            }
+            (Method::Post, ["streams"]) => {
+                let body = match read_json(request) {
+                    Ok(body) => body,
+                    Err(err) => return (400, json!({ "error": err })),
+                };
+
+                let id = body["id"].as_str().map(String::from);
+                let result = stream_args(&body).and_then(|args| self.create(id, args));
+
+                match result {
+                    Ok(id) => (201, json!({ "id": id })),
+                    Err(err) => (400, json!({ "error": err.to_string() })),
+                }
+            }
+            (Method::Get, ["streams", id]) => {
+                let streams = self.streams.lock().unwrap();
+                match streams.get(*id) {
+                    Some(stream) => (
+                        200,
+                        json!({
+                            "id": id,
+                            "status": stream.gateway.status().to_json(),
+                            "health": stream.gateway.health().to_json(),
+                            "stats": stream.stats.lock().unwrap().collect(stream.gateway.pipeline()).to_json(),
+                            "streaming_threads": stream.gateway.streaming_threads(),
+                            "queues": stream.gateway.queues().to_json(),
+                            "drift": stream.gateway.drift().to_json(),
+                            "startup": stream.gateway.startup().to_json(),
+                        }),
+                    ),
+                    None => not_found(),
+                }
+            }
+            (Method::Delete, ["streams", id]) => {
+                if self.destroy(id) {
+                    (200, json!({ "id": id }))
+                } else {
+                    not_found()
+                }
+            }
+            (Method::Get, ["streams", id, "health"]) => {
+                let streams = self.streams.lock().unwrap();
+                match streams.get(*id) {
+                    Some(stream) => {
+                        let health = stream.gateway.health();
+                        let code = if health.ready() { 200 } else { 503 };
+                        (code, health.to_json())
+                    }
+                    None => not_found(),
+                }
+            }
+            (Method::Get, ["streams", id, "volume"]) => {
+                let streams = self.streams.lock().unwrap();
+                match streams.get(*id) {
+                    Some(stream) => (200, json!({ "volume": stream.gateway.volume() })),
+                    None => not_found(),
+                }
+            }
+            (Method::Put, ["streams", id, "volume"]) => {
+                let body = match read_json(request) {
+                    Ok(body) => body,
+                    Err(err) => return (400, json!({ "error": err })),
+                };
+                let Some(volume) = body["volume"].as_f64() else {
+                    return (400, json!({ "error": "'volume' must be a number" }));
+                };
+
+                let streams = self.streams.lock().unwrap();
+                match streams
+                    .get(*id)
+                    .map(|stream| stream.gateway.set_volume(volume))
+                {
+                    Some(Ok(())) => (200, json!({ "volume": volume })),
+                    Some(Err(err)) => (400, json!({ "error": err.to_string() })),
+                    None => not_found(),
+                }
+            }
+            (Method::Get, ["streams", id, "bitrate"]) => {
+                let streams = self.streams.lock().unwrap();
+                match streams.get(*id) {
+                    Some(stream) => (200, bitrates(&stream.gateway)),
+                    None => not_found(),
+                }
+            }
+            (Method::Put, ["streams", id, "bitrate"]) => {
+                let body = match read_json(request) {
+                    Ok(body) => body,
+                    Err(err) => return (400, json!({ "error": err })),
+                };
+                let bitrate = |media: &str| match body.get(media) {
+                    None => Ok(None),
+                    Some(bitrate) => bitrate
+                        .as_u64()
+                        .and_then(|bitrate| u32::try_from(bitrate).ok())
+                        .map(Some)
+                        .ok_or_else(|| format!("'{media}' must be a bitrate in bit/s")),
+                };
+                let (audio, video) = match (bitrate("audio"), bitrate("video")) {
+                    (Ok(audio), Ok(video)) => (audio, video),
+                    (Err(err), _) | (_, Err(err)) => return (400, json!({ "error": err })),
+                };
+                if audio.is_none() && video.is_none() {
+                    return (
+                        400,
+                        json!({ "error": "'audio' or 'video' bitrate missing" }),
+                    );
+                }
+
+                let streams = self.streams.lock().unwrap();
+                let Some(stream) = streams.get(*id) else {
+                    return not_found();
+                };
+                let result = audio
+                    .map_or(Ok(()), |bitrate| stream.gateway.set_audio_bitrate(bitrate))
+                    .and_then(|()| {
+                        video.map_or(Ok(()), |bitrate| stream.gateway.set_video_bitrate(bitrate))
+                    });
+                match result {
+                    Ok(()) => (200, bitrates(&stream.gateway)),
+                    Err(err) => (400, json!({ "error": err.to_string() })),
+                }
+            }
+            (Method::Get, ["streams", id, "output"]) => {
+                let streams = self.streams.lock().unwrap();
+                match streams.get(*id) {
+                    Some(stream) => (200, json!({ "output_url": stream.gateway.output_url() })),
+                    None => not_found(),
+                }
+            }
+            (Method::Put, ["streams", id, "output"]) => {
+                let body = match read_json(request) {
+                    Ok(body) => body,
+                    Err(err) => return (400, json!({ "error": err })),
+                };
+                let Some(output_url) = body["output_url"].as_str() else {
+                    return (400, json!({ "error": "'output_url' must be a string" }));
+                };
+
+                let streams = self.streams.lock().unwrap();
+                match streams
+                    .get(*id)
+                    .map(|stream| stream.gateway.set_output_url(output_url))
+                {
+                    Some(Ok(())) => (200, json!({ "output_url": output_url })),
+                    Some(Err(err)) => (400, json!({ "error": err.to_string() })),
+                    None => not_found(),
+                }
+            }
+            (Method::Post, ["streams", id, "pause"]) => {
+                let body = match read_json(request) {
+                    Ok(body) => body,
+                    Err(err) => return (400, json!({ "error": err })),
+                };
+                let slate = match body.get("slate") {
+                    None => false,
+                    Some(slate) => match slate.as_bool() {
+                        Some(slate) => slate,
+                        None => return (400, json!({ "error": "'slate' must be a boolean" })),
+                    },
+                };
+
+                let streams = self.streams.lock().unwrap();
+                match streams.get(*id).map(|stream| stream.gateway.pause(slate)) {
+                    Some(Ok(())) => (200, json!({ "paused": true, "slate": slate })),
+                    Some(Err(err)) => (400, json!({ "error": err.to_string() })),
+                    None => not_found(),
+                }
+            }
+            (Method::Post, ["streams", id, "resume"]) => {
+                let streams = self.streams.lock().unwrap();
+                match streams.get(*id).map(|stream| stream.gateway.resume()) {
+                    Some(true) => (200, json!({ "paused": false })),
+                    Some(false) => (409, json!({ "error": "stream is not paused" })),
+                    None => not_found(),
+                }
+            }
+            (Method::Get, ["process"]) => {
+                let streams = self.streams.lock().unwrap();
+                let threads: HashMap<&String, usize> = streams
+                    .iter()
+                    .map(|(id, stream)| (id, stream.gateway.streaming_threads()))
+                    .collect();
+
+                (
+                    200,
+                    json!({
+                        "process": self.process.lock().unwrap().sample().map(|process| process.to_json()),
+                        "streaming_threads": threads,
+                    }),
+                )
+            }
+            _ => not_found(),
+        }
+    }
+}
+
+/// Turns a create request into the same `Args` the command line would produce
+fn stream_args(body: &Value) -> Result<Args, Error> {
+    let mut cli = vec![String::from("whep-srt")];
+
+    if let Some(args) = body.get("args") {
+        let args = args
+            .as_array()
+            .ok_or_else(|| Error::Config(String::from("'args' must be an array of strings")))?;
+        for arg in args {
+            let arg = arg
+                .as_str()
+                .ok_or_else(|| Error::Config(String::from("'args' must be an array of strings")))?;
+            cli.push(arg.to_string());
+        }
+    }
+    if let Some(input_url) = body["input_url"].as_str() {
+        cli.extend([String::from("--input-url"), input_url.to_string()]);
+    }
+    if let Some(output_url) = body["output_url"].as_str() {
+        cli.extend([String::from("--output-url"), output_url.to_string()]);
+    }
+
+    let matches = Args::command()
+        .try_get_matches_from(cli)
+        .map_err(|err| Error::Config(err.to_string()))?;
+    if let Some(command) = matches.subcommand_name() {
+        return Err(Error::Config(format!(
+            "'{command}' can't be used in a create request"
+        )));
+    }
+    // the environment's defaults are the operator's
+    let requested = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    // only ids that are there, tui depends on a feature
+    if let Some(id) = matches
+        .ids()
+        .map(|id| id.as_str())
+        .find(|id| LOCAL_OPTIONS.contains(id) && requested(id))
+    {
+        return Err(Error::Config(format!(
+            "--{} can't be set through the api",
+            id.replace('_', "-")
+        )));
+    }
+
+    let args = Args::from_arg_matches(&matches).map_err(|err| Error::Config(err.to_string()))?;
+    if requested("output_url") && args.output_url.starts_with("cmaf+file:") {
+        return Err(Error::Config(String::from(
+            "cmaf+file outputs can't be created through the api",
+        )));
+    }
+    if requested("teletext")
+        && args
+            .teletext
+            .as_deref()
+            .is_some_and(|teletext| teletext.starts_with("file:"))
+    {
+        return Err(Error::Config(String::from(
+            "--teletext can't read a file when set through the api",
+        )));
+    }
+    Ok(args)
+}
+
+/// Compares without returning early, so the time taken doesn't tell how much of a token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn read_json(request: &mut Request) -> Result<Value, String> {
+    let mut body = String::new();
+    request
+        .as_reader()
+        .read_to_string(&mut body)
+        .map_err(|err| err.to_string())?;
+
+    if body.trim().is_empty() {
+        return Ok(json!({}));
+    }
+
+    serde_json::from_str(&body).map_err(|err| format!("invalid json: {err}"))
+}
+
+/// Renders a .dot document with Graphviz, which has to be installed
+fn render_svg(dot: &str) -> Result<Vec<u8>, String> {
+    let mut child = Command::new("dot")
+        .arg("-Tsvg")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("could not run Graphviz 'dot', is it installed? {err}"))?;
+
+    // written from another thread, dot may start writing before it has read everything
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let dot = dot.to_string();
+    let writer = std::thread::spawn(move || stdin.write_all(dot.as_bytes()));
+
+    let output = child
+        .wait_with_output()
+        .map_err(|err| format!("could not run Graphviz 'dot': {err}"))?;
+    let _ = writer.join();
+
+    if !output.status.success() {
+        return Err(format!(
+            "Graphviz 'dot' failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+/// The current encoder bitrates of a stream, null where there is no encoder
+fn bitrates(gateway: &Gateway) -> Value {
+    json!({ "audio": gateway.audio_bitrate(), "video": gateway.video_bitrate() })
+}
+
+fn not_found() -> (u16, Value) {
+    (404, json!({ "error": "not found" }))
+}
+
+fn respond(request: Request, status: u16, body: Value) {
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+        .expect("could not create content-type header");
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(header);
+
+    if let Err(err) = request.respond(response) {
+        warn!("could not send api response: {err}");
+    }
+}
+
+fn respond_document(request: Request, document: Document) {
+    let header = Header::from_bytes(&b"Content-Type"[..], document.content_type.as_bytes())
+        .expect("could not create content-type header");
+    let response = Response::from_data(document.data).with_header(header);
+
+    if let Err(err) = request.respond(response) {
+        warn!("could not send api response: {err}");
+    }
+}
@@ -0,0 +1,201 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+use gstreamer::{self as gst, PadProbeData, PadProbeReturn, PadProbeType};
+use serde_json::{Value, json};
+use tracing::info;
+
+use crate::args::{Args, BenchArgs};
+use crate::error::Error;
+use crate::gateway::Gateway;
+use crate::process::ProcessSampler;
+
+/// Outcome of a benchmark run
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub streams: usize,
+    pub duration: Duration,
+    /// Muxed output per stream, in bits per second
+    pub stream_bitrates: Vec<f64>,
+    /// Average over the run, None where process usage is not available
+    pub cpu_percent: Option<f64>,
+    pub peak_rss_bytes: Option<u64>,
+}
+
+impl BenchReport {
+    pub fn total_bitrate(&self) -> f64 {
+        self.stream_bitrates.iter().sum()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let min = self
+            .stream_bitrates
+            .iter()
+            .copied()
+            .fold(f64::INFINITY, f64::min);
+
+        json!({
+            "streams": self.streams,
+            "duration_secs": self.duration.as_secs_f64(),
+            "total_bitrate": self.total_bitrate(),
+            "min_stream_bitrate": min.is_finite().then_some(min),
+            "stream_bitrates": self.stream_bitrates,
+            "cpu_percent": self.cpu_percent,
+            "peak_rss_bytes": self.peak_rss_bytes,
+        })
+    }
+}
+
+struct BenchStream {
+    gateway: Gateway,
+    bytes: Arc<AtomicU64>,
+}
+
+/// Runs `bench.streams` test source pipelines side by side for `bench.duration` seconds.
+/// Every other option in `args` (bitrate cap, thread nice, ...) applies to each pipeline.
+pub fn run(args: &Args, bench: &BenchArgs) -> Result<BenchReport, Error> {
+    let mut streams = Vec::new();
+
+    for i in 0..bench.streams {
+        let port = bench.base_port.checked_add(i).ok_or_else(|| {
+            Error::Config(format!(
+                "{} streams do not fit above port {}",
+                bench.streams, bench.base_port
+            ))
+        })?;
+
+        let mut stream_args = args.clone();
+        stream_args.command = None;
+        stream_args.input_url = None;
+        stream_args.output_url = format!("srt://127.0.0.1:{port}?mode=listener");
+
+        let gateway = Gateway::test_source(stream_args)?;
+        let output = gateway
+            .pipeline()
+            .by_name("output")
+            .expect("could not get srt output sink");
+        let bytes = count_bytes(&output);
+
+        streams.push(BenchStream { gateway, bytes });
+    }
+
+    info!(
+        "bench: running {} streams for {}s",
+        streams.len(),
+        bench.duration
+    );
+
+    // the first sample only sets the baseline for cpu usage
+    let mut sampler = ProcessSampler::default();
+    sampler.sample();
+
+    let runners: Vec<_> = streams
+        .iter()
+        .map(|stream| {
+            let gateway = stream.gateway.clone();
+            std::thread::spawn(move || gateway.run())
+        })
+        .collect();
+
+    let started = Instant::now();
+    let mut cpu_samples = Vec::new();
+    let mut peak_rss_bytes = None;
+    let mut previous_bytes = 0;
+
+    while started.elapsed() < Duration::from_secs(bench.duration) {
+        std::thread::sleep(Duration::from_secs(1));
+
+        if runners.iter().any(|runner| runner.is_finished()) {
+            break;
+        }
+
+        let bytes: u64 = streams
+            .iter()
+            .map(|s| s.bytes.load(Ordering::Relaxed))
+            .sum();
+        let usage = sampler.sample();
+
+        if let Some(usage) = &usage {
+            cpu_samples.extend(usage.cpu_percent);
+            peak_rss_bytes = peak_rss_bytes.max(Some(usage.rss_bytes));
+        }
+
+        info!(
+            "bench: {:.0} kbit/s total, cpu {}, rss {}",
+            (bytes - previous_bytes) as f64 * 8.0 / 1000.0,
+            usage
+                .as_ref()
+                .and_then(|usage| usage.cpu_percent)
+                .map_or(String::from("n/a"), |cpu| format!("{cpu:.1}%")),
+            usage.map_or(String::from("n/a"), |usage| format!(
+                "{} MiB",
+                usage.rss_bytes / 1024 / 1024
+            )),
+        );
+        previous_bytes = bytes;
+    }
+
+    let duration = started.elapsed();
+
+    for stream in &streams {
+        stream.gateway.stop();
+    }
+
+    let mut result = Ok(());
+    for runner in runners {
+        let ended = runner
+            .join()
+            .unwrap_or_else(|_| Err(Error::Pipeline(String::from("bench stream panicked"))));
+        if result.is_ok() {
+            result = ended;
+        }
+    }
+    result?;
+
+    let report = BenchReport {
+        streams: streams.len(),
+        duration,
+        stream_bitrates: streams
+            .iter()
+            .map(|s| s.bytes.load(Ordering::Relaxed) as f64 * 8.0 / duration.as_secs_f64())
+            .collect(),
+        cpu_percent: (!cpu_samples.is_empty())
+            .then(|| cpu_samples.iter().sum::<f64>() / cpu_samples.len() as f64),
+        peak_rss_bytes,
+    };
+
+    info!(
+        "bench: {} streams sustained {:.0} kbit/s total over {:.0}s",
+        report.streams,
+        report.total_bitrate() / 1000.0,
+        duration.as_secs_f64()
+    );
+
+    Ok(report)
+}
+
+/// Counts the bytes arriving at a sink element, e.g. the muxed transport stream handed to srtsink
+pub(crate) fn count_bytes(sink: &gst::Element) -> Arc<AtomicU64> {
+    let bytes = Arc::new(AtomicU64::new(0));
+    let bytes_clone = bytes.clone();
+
+    let sink_pad = sink.static_pad("sink").expect("could not get sink pad");
+
+    sink_pad.add_probe(
+        PadProbeType::BUFFER | PadProbeType::BUFFER_LIST,
+        move |_, probe_info| {
+            let size = match &probe_info.data {
+                Some(PadProbeData::Buffer(buffer)) => buffer.size(),
+                Some(PadProbeData::BufferList(list)) => list.calculate_size(),
+                _ => 0,
+            };
+            bytes_clone.fetch_add(size as u64, Ordering::Relaxed);
+
+            PadProbeReturn::Ok
+        },
+    );
+
+    bytes
+}
@@ -0,0 +1,304 @@
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use tracing::{debug, info, warn};
+
+/// Sample rate of the mono audio sent for transcription, what whisper models take
+pub const SAMPLE_RATE: u32 = 16000;
+
+/// Audio transcribed at once. Longer chunks transcribe better, shorter ones show up sooner.
+pub const CHUNK: Duration = Duration::from_secs(4);
+
+/// A caption is taken off the screen when no new one followed it for this long
+const CLEAR_AFTER: Duration = Duration::from_secs(6);
+
+/// Chunks quieter than this, in dBFS, are not transcribed, speech models make up words in silence
+const SILENCE_DB: f64 = -50.0;
+
+/// Teletext page of the subtitles, 888 by convention
+const MAGAZINE: u8 = 8;
+const PAGE: u8 = 0x88;
+
+/// Characters in a subtitle row, between the box and double height control codes
+const ROW_WIDTH: usize = 35;
+
+/// The next PES to send on the teletext PID instead of stuffing, set as captions come and go
+pub type PendingPes = Arc<Mutex<Option<Vec<u8>>>>;
+
+/// Client of an OpenAI compatible speech-to-text endpoint, e.g. `/v1/audio/transcriptions` of a whisper server
+pub struct Transcriber {
+    url: String,
+    model: String,
+    token: Option<String>,
+}
+
+impl Transcriber {
+    pub fn new(url: String, model: String, token: Option<String>) -> Self {
+        Self { url, model, token }
+    }
+
+    /// The text spoken in 16 kHz mono audio
+    pub fn transcribe(&self, samples: &[i16]) -> Result<String, String> {
+        let boundary = "whep-srt-captions";
+        let mut body = Vec::new();
+        for (name, value) in [("model", self.model.as_str()), ("response_format", "json")] {
+            body.extend(
+                format!(
+                    "--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+                )
+                .as_bytes(),
+            );
+        }
+        body.extend(
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"audio.wav\"\r\nContent-Type: audio/wav\r\n\r\n"
+            )
+            .as_bytes(),
+        );
+        body.extend(wav(samples, SAMPLE_RATE));
+        body.extend(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+        let mut request = ureq::post(&self.url).timeout(CHUNK * 2).set(
+            "Content-Type",
+            &format!("multipart/form-data; boundary={boundary}"),
+        );
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Bearer {token}"));
+        }
+
+        let response = request
+            .send_bytes(&body)
+            .map_err(|err| err.to_string())?
+            .into_string()
+            .map_err(|err| err.to_string())?;
+        let response: Value =
+            serde_json::from_str(&response).map_err(|err| format!("invalid json: {err}"))?;
+        response["text"]
+            .as_str()
+            .map(|text| text.trim().to_string())
+            .ok_or_else(|| String::from("no text in the response"))
+    }
+}
+
+/// Transcribes the chunks of audio on a thread of its own, and puts the subtitle page of each transcript in
+/// `pending`, followed by an empty page once it has been shown long enough
+pub fn spawn(transcriber: Transcriber, chunks: mpsc::Receiver<Vec<i16>>, pending: PendingPes) {
+    std::thread::Builder::new()
+        .name("captions".into())
+        .spawn(move || {
+            let mut shown: Option<Instant> = None;
+            loop {
+                match chunks.recv_timeout(CHUNK) {
+                    Ok(chunk) if level_db(&chunk) < SILENCE_DB => debug!("no speech to caption"),
+                    Ok(chunk) => match transcriber.transcribe(&chunk) {
+                        Ok(text) if text.is_empty() => (),
+                        Ok(text) => {
+                            info!("caption: {text}");
+                            *pending.lock().unwrap() = Some(subtitle_pes(Some(&text)));
+                            shown = Some(Instant::now());
+                        }
+                        Err(err) => warn!("transcription failed: {err}"),
+                    },
+                    Err(mpsc::RecvTimeoutError::Timeout) => (),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+
+                if shown.is_some_and(|at| at.elapsed() >= CLEAR_AFTER) {
+                    *pending.lock().unwrap() = Some(subtitle_pes(None));
+                    shown = None;
+                }
+            }
+        })
+        .expect("could not spawn captions thread");
+}
+
+/// RMS level of the samples in dBFS
+fn level_db(samples: &[i16]) -> f64 {
+    if samples.is_empty() {
+        return f64::NEG_INFINITY;
+    }
+    let power = samples
+        .iter()
+        .map(|&s| (s as f64 / i16::MAX as f64).powi(2))
+        .sum::<f64>()
+        / samples.len() as f64;
+    10.0 * power.log10()
+}
+
+/// 16 bit mono PCM in a WAV file
+pub fn wav(samples: &[i16], rate: u32) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend(b"RIFF");
+    wav.extend((36 + data_len).to_le_bytes());
+    wav.extend(b"WAVEfmt ");
+    wav.extend(16u32.to_le_bytes());
+    // PCM, one channel
+    wav.extend(1u16.to_le_bytes());
+    wav.extend(1u16.to_le_bytes());
+    wav.extend(rate.to_le_bytes());
+    wav.extend((rate * 2).to_le_bytes());
+    wav.extend(2u16.to_le_bytes());
+    wav.extend(16u16.to_le_bytes());
+    wav.extend(b"data");
+    wav.extend(data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend(sample.to_le_bytes());
+    }
+    wav
+}
+
+/// Teletext Hamming 8/4 code words of the nibbles 0-15
+const HAMMING_8_4: [u8; 16] = [
+    0x15, 0x02, 0x49, 0x5e, 0x64, 0x73, 0x38, 0x2f, 0xd0, 0xc7, 0x8c, 0x9b, 0xa1, 0xb6, 0xfd, 0xea,
+];
+
+/// EN 300 472 PES payload of the subtitle page with `text`, or of an empty page to clear the screen. Like the
+/// generated stuffing, a data identifier and three data units: the page header, then the text in up to two
+/// double height rows, with stuffing for the rows there is no text for.
+pub fn subtitle_pes(text: Option<&str>) -> Vec<u8> {
+    let mut header = [0u8; 40];
+    // page 88, subcode 0
+    header[0] = hamming(PAGE & 0x0f);
+    header[1] = hamming(PAGE >> 4);
+    header[2] = hamming(0);
+    // C4, erase page
+    header[3] = hamming(0b1000);
+    header[4] = hamming(0);
+    // C6, subtitle
+    header[5] = hamming(0b1000);
+    // C7 suppress header, C8 update indicator
+    header[6] = hamming(0b0011);
+    // C11 magazine serial, C12-C14 English character set
+    header[7] = hamming(0b0001);
+    for byte in &mut header[8..] {
+        *byte = parity(b' ');
+    }
+
+    let mut payload = vec![0x10];
+    payload.extend(data_unit(0, &header));
+
+    let lines = text.map(|text| wrap(text, ROW_WIDTH)).unwrap_or_default();
+    // the last line on row 22, above it the one before on row 20
+    let rows = [20, 22];
+    for (&row, line) in rows[2 - lines.len()..].iter().zip(&lines) {
+        payload.extend(data_unit(row, &subtitle_row(line)));
+    }
+    for _ in lines.len()..2 {
+        payload.extend([0xff, 0x2c]);
+        payload.extend([0xff; 44]);
+    }
+    payload
+}
+
+/// One data unit of EBU teletext subtitle data, its bytes in the bit order they are transmitted in
+fn data_unit(packet: u8, data: &[u8; 40]) -> [u8; 46] {
+    let mut unit = [0u8; 46];
+    // data unit id and length, field parity set and no line offset, the framing code
+    unit[..4].copy_from_slice(&[0x03, 0x2c, 0xe0, 0xe4]);
+    // magazine 8 is sent as 0
+    unit[4] = hamming((MAGAZINE & 0x07) | ((packet & 1) << 3)).reverse_bits();
+    unit[5] = hamming(packet >> 1).reverse_bits();
+    for (byte, &data) in unit[6..].iter_mut().zip(data) {
+        *byte = data.reverse_bits();
+    }
+    unit
+}
+
+/// A subtitle row: centered text, double height, in a box so the decoder shows it over the video
+fn subtitle_row(line: &str) -> [u8; 40] {
+    let text: Vec<u8> = line
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if !"#$@[\\]^_`{|}~".contains(c) => c as u8,
+            _ => b'?',
+        })
+        .collect();
+
+    let mut row = [parity(b' '); 40];
+    let start = (ROW_WIDTH - text.len()) / 2;
+    // double height, then start box twice, as decoders expect it
+    row[start..start + 3].copy_from_slice(&[0x0d, 0x0b, 0x0b].map(parity));
+    for (i, &c) in text.iter().enumerate() {
+        row[start + 3 + i] = parity(c);
+    }
+    // end box twice
+    let end = start + 3 + text.len();
+    row[end..end + 2].copy_from_slice(&[0x0a, 0x0a].map(parity));
+    row
+}
+
+/// The last two lines of at most `width` characters the words of `text` wrap into
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        let word: String = word.chars().take(width).collect();
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= width => {
+                line.push(' ');
+                line.push_str(&word);
+            }
+            _ => lines.push(word),
+        }
+    }
+    let skip = lines.len().saturating_sub(2);
+    lines.split_off(skip)
+}
+
+fn hamming(nibble: u8) -> u8 {
+    HAMMING_8_4[(nibble & 0x0f) as usize]
+}
+
+/// A 7 bit character with odd parity in the eighth bit
+fn parity(c: u8) -> u8 {
+    let c = c & 0x7f;
+    if c.count_ones().is_multiple_of(2) {
+        c | 0x80
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_into_the_last_two_lines() {
+        assert!(wrap("", ROW_WIDTH).is_empty());
+        assert_eq!(wrap("  hello   world ", 11), ["hello world"]);
+        assert_eq!(
+            wrap("the quick brown fox jumps over the lazy dog", 12),
+            ["jumps over", "the lazy dog"]
+        );
+        // words longer than a line are cut
+        assert_eq!(
+            wrap("a supercalifragilistic word", 10),
+            ["supercalif", "word"]
+        );
+        // characters, not bytes
+        assert_eq!(wrap("ça va très bien", 9), ["ça va", "très bien"]);
+    }
+
+    #[test]
+    fn subtitle_rows() {
+        let row = subtitle_row("Hi");
+        // centered in the 35 characters
+        let start = 16;
+        assert_eq!(row[start..start + 3], [0x0d, 0x0b, 0x0b].map(parity));
+        assert_eq!(row[start + 3..start + 5], [b'H', b'i'].map(parity));
+        assert_eq!(row[start + 5..start + 7], [0x0a, 0x0a].map(parity));
+        assert!(row[..start].iter().all(|&c| c == parity(b' ')));
+        assert!(row[start + 7..].iter().all(|&c| c == parity(b' ')));
+        assert!(row.iter().all(|c| c.count_ones() % 2 == 1));
+
+        // characters the teletext national sets replace
+        assert_eq!(subtitle_row("é#[")[19..22], [b'?'; 3].map(parity));
+
+        // a full line fills the row
+        let row = subtitle_row(&"x".repeat(ROW_WIDTH));
+        assert_eq!(row[..3], [0x0d, 0x0b, 0x0b].map(parity));
+        assert_eq!(row[38..], [0x0a, 0x0a].map(parity));
+    }
+}
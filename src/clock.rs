@@ -0,0 +1,115 @@
+use std::fmt;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use gst::prelude::*;
+use gstreamer as gst;
+use gstreamer_net as gst_net;
+use tracing::info;
+
+use crate::error::{Category, Error};
+
+const NTP_PORT: u16 = 123;
+
+/// Network clock the pipeline clock follows, --ntp-server or --ptp-domain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReferenceClock {
+    /// HOST[:PORT] of an NTP server
+    Ntp(String),
+    /// IEEE 1588 domain, 0-255
+    Ptp(u32),
+}
+
+impl fmt::Display for ReferenceClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReferenceClock::Ntp(server) => write!(f, "NTP server {server}"),
+            ReferenceClock::Ptp(domain) => write!(f, "PTP domain {domain}"),
+        }
+    }
+}
+
+/// Slaves the pipeline to `reference`, waiting up to `timeout` for it to synchronize. The base time is 0, so the
+/// running time, and the PCR and PTS the muxer derives from it, is the time of the reference clock, the same in
+/// every gateway following it.
+pub fn slave(
+    pipeline: &gst::Pipeline,
+    reference: &ReferenceClock,
+    timeout: Duration,
+) -> Result<(), Error> {
+    let clock: gst::Clock = match reference {
+        ReferenceClock::Ntp(server) => {
+            let address = resolve(server)?;
+            gst_net::NtpClock::new(
+                Some("ntp_clock"),
+                &address.ip().to_string(),
+                address.port().into(),
+                gst::ClockTime::ZERO,
+            )
+            .upcast()
+        }
+        ReferenceClock::Ptp(domain) => {
+            init_ptp()?;
+            gst_net::PtpClock::new(Some("ptp_clock"), *domain)
+                .map_err(|err| Error::Pipeline(format!("could not create a PTP clock: {err}")))?
+                .upcast()
+        }
+    };
+
+    info!(
+        "waiting up to {}s for the {reference} to synchronize",
+        timeout.as_secs()
+    );
+    clock
+        .wait_for_sync(gst::ClockTime::from_seconds(timeout.as_secs()))
+        .map_err(|_| Error::Element {
+            element: None,
+            code: String::from("ClockSync"),
+            category: Category::Network,
+            message: format!(
+                "the {reference} clock did not synchronize within {}s",
+                timeout.as_secs()
+            ),
+        })?;
+    info!("pipeline clock slaved to the {reference}");
+
+    pipeline.use_clock(Some(&clock));
+    // kept at 0 through state changes
+    pipeline.set_start_time(gst::ClockTime::NONE);
+    pipeline.set_base_time(gst::ClockTime::ZERO);
+    Ok(())
+}
+
+/// The address of HOST[:PORT], port 123 by default. Resolved here, the NTP clock only takes an IP address.
+fn resolve(server: &str) -> Result<SocketAddr, Error> {
+    if let Ok(ip) = server.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, NTP_PORT));
+    }
+    // a host name or IPv4 address has a colon only in front of the port
+    let with_port = if server.contains(':') {
+        server.to_string()
+    } else {
+        format!("{server}:{NTP_PORT}")
+    };
+
+    with_port
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addresses| addresses.next())
+        .ok_or_else(|| Error::Config(format!("could not resolve NTP server '{server}'")))
+}
+
+/// Starts the PTP helper process, once per process for every gateway
+fn init_ptp() -> Result<(), Error> {
+    static INIT: OnceLock<Result<(), String>> = OnceLock::new();
+
+    INIT.get_or_init(|| {
+        gst_net::PtpClock::init(None, &[])
+            // kept for the life of the process
+            .map(std::mem::forget)
+            .map_err(|err| format!("could not initialize PTP: {err}"))
+    })
+    .clone()
+    .map_err(Error::Pipeline)
+}
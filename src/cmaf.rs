@@ -0,0 +1,456 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tracing::{debug, warn};
+
+use crate::error::Error;
+use crate::output::CmafDestination;
+
+/// Segments that left the playlist are deleted this many segments later, for players still fetching them
+const EXPIRED_SEGMENTS_KEPT: usize = 2;
+/// Time allowed for a whole file PUT to an HTTP ingest
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A CMAF output: one rendition per track, each with its own init segments, media segments and HLS playlist
+/// below `<rendition>/`, and a master playlist listing the renditions that started. Cloning gives another handle
+/// to the same presentation.
+#[derive(Clone)]
+pub struct Presentation {
+    target: Target,
+    playlist_length: usize,
+    /// Name and bandwidth of the renditions with an init segment, for the master playlist
+    started: Arc<Mutex<Vec<(String, u32)>>>,
+}
+
+#[derive(Clone)]
+enum Target {
+    Directory(PathBuf),
+    Http {
+        base: String,
+        /// Whole files, PUT in order on a thread of their own
+        uploads: Sender<(String, Vec<u8>)>,
+    },
+}
+
+impl Presentation {
+    pub fn new(destination: CmafDestination, playlist_length: usize) -> Result<Self, Error> {
+        let target = match destination {
+            CmafDestination::Directory(directory) => {
+                fs::create_dir_all(&directory).map_err(|err| {
+                    Error::Config(format!(
+                        "could not create CMAF output directory {}: {err}",
+                        directory.display()
+                    ))
+                })?;
+                Target::Directory(directory)
+            }
+            CmafDestination::Http(base) => Target::Http {
+                uploads: spawn_uploader(base.clone()),
+                base,
+            },
+        };
+
+        Ok(Self {
+            target,
+            playlist_length,
+            started: Arc::default(),
+        })
+    }
+
+    /// A track of the presentation, written below `name/`. `bandwidth` in bits per second goes into the master
+    /// playlist.
+    pub fn rendition(&self, name: &str, bandwidth: u32) -> Result<Rendition, Error> {
+        if let Target::Directory(directory) = &self.target {
+            let directory = directory.join(name);
+            fs::create_dir_all(&directory).map_err(|err| {
+                Error::Config(format!(
+                    "could not create CMAF output directory {}: {err}",
+                    directory.display()
+                ))
+            })?;
+        }
+
+        Ok(Rendition {
+            presentation: self.clone(),
+            name: name.to_string(),
+            bandwidth,
+            init: 0,
+            next_segment: 1,
+            current: None,
+            segments: VecDeque::new(),
+            expired: VecDeque::new(),
+            discontinuity_sequence: 0,
+            ended: false,
+        })
+    }
+
+    fn start(&self, name: &str, bandwidth: u32) -> Result<(), String> {
+        let master = {
+            let mut started = self.started.lock().unwrap();
+            if started.iter().any(|(started, _)| started == name) {
+                return Ok(());
+            }
+            started.push((name.to_string(), bandwidth));
+            master_playlist(&started)
+        };
+        self.put("master.m3u8", master.into_bytes())
+    }
+
+    /// Writes a whole file, replacing what was there
+    fn put(&self, path: &str, data: Vec<u8>) -> Result<(), String> {
+        match &self.target {
+            Target::Directory(directory) => {
+                // through a temporary file, so nobody reads half a playlist
+                let target = directory.join(path);
+                let temporary = directory.join(format!("{path}.tmp"));
+                fs::write(&temporary, data)
+                    .and_then(|_| fs::rename(&temporary, &target))
+                    .map_err(|err| format!("could not write {}: {err}", target.display()))
+            }
+            Target::Http { uploads, .. } => uploads
+                .send((path.to_string(), data))
+                .map_err(|_| String::from("the CMAF upload thread is gone")),
+        }
+    }
+
+    /// Opens a file that is written chunk by chunk
+    fn open(&self, path: &str) -> Result<Upload, String> {
+        match &self.target {
+            Target::Directory(directory) => {
+                let target = directory.join(path);
+                File::create(&target)
+                    .map(Upload::File)
+                    .map_err(|err| format!("could not create {}: {err}", target.display()))
+            }
+            Target::Http { base, .. } => {
+                let url = format!("{base}/{path}");
+                let (chunks, receiver) = mpsc::channel();
+                std::thread::Builder::new()
+                    .name("cmaf-segment".into())
+                    .spawn(move || {
+                        if let Err(err) = ureq::put(&url)
+                            .set("Content-Type", content_type(&url))
+                            .send(ChunkReader::new(receiver))
+                        {
+                            warn!("could not upload {url}: {err}");
+                        }
+                    })
+                    .map_err(|err| format!("could not spawn CMAF upload thread: {err}"))?;
+                Ok(Upload::Http(chunks))
+            }
+        }
+    }
+
+    /// Removes a segment that left the playlist, best effort
+    fn delete(&self, path: &str) {
+        let result = match &self.target {
+            Target::Directory(directory) => {
+                fs::remove_file(directory.join(path)).map_err(|err| err.to_string())
+            }
+            Target::Http { base, .. } => ureq::delete(&format!("{base}/{path}"))
+                .timeout(UPLOAD_TIMEOUT)
+                .call()
+                .map(|_| ())
+                .map_err(|err| err.to_string()),
+        };
+        if let Err(err) = result {
+            debug!("could not delete CMAF segment {path}: {err}");
+        }
+    }
+}
+
+/// One track of a presentation. Fed with the output of its muxer: init segments, and the chunks of the media
+/// segments as they are produced.
+pub struct Rendition {
+    presentation: Presentation,
+    name: String,
+    bandwidth: u32,
+    /// Number of the current init segment, 0 before the first
+    init: u32,
+    next_segment: u64,
+    current: Option<OpenSegment>,
+    segments: VecDeque<Segment>,
+    /// Numbers of the segments that left the playlist, not deleted yet
+    expired: VecDeque<u64>,
+    discontinuity_sequence: u64,
+    ended: bool,
+}
+
+struct Segment {
+    number: u64,
+    init: u32,
+    duration: Duration,
+}
+
+struct OpenSegment {
+    segment: Segment,
+    start: Option<Duration>,
+    upload: Upload,
+}
+
+impl Rendition {
+    /// A new init segment, at the start and whenever the track's format changes. Starts a new media segment.
+    pub fn init(&mut self, data: Vec<u8>) -> Result<(), String> {
+        self.finish_segment()?;
+
+        self.init += 1;
+        self.presentation
+            .put(&format!("{}/init_{}.mp4", self.name, self.init), data)?;
+        self.presentation.start(&self.name, self.bandwidth)
+    }
+
+    /// A chunk of media, `starts_segment` for the first chunk of a segment. `pts` is the time of its first sample,
+    /// segment durations are taken from these where known.
+    pub fn chunk(
+        &mut self,
+        data: &[u8],
+        starts_segment: bool,
+        pts: Option<Duration>,
+        duration: Option<Duration>,
+    ) -> Result<(), String> {
+        // nothing can be played without an init segment
+        if self.init == 0 {
+            return Ok(());
+        }
+
+        if starts_segment || self.current.is_none() {
+            if let (Some(current), Some(pts)) = (&mut self.current, pts)
+                && let Some(start) = current.start
+            {
+                current.segment.duration = pts.saturating_sub(start);
+            }
+            self.finish_segment()?;
+
+            let number = self.next_segment;
+            self.next_segment += 1;
+            let upload =
+                self.presentation
+                    .open(&format!("{}/{}", self.name, segment_file(number)))?;
+            self.current = Some(OpenSegment {
+                segment: Segment {
+                    number,
+                    init: self.init,
+                    duration: Duration::ZERO,
+                },
+                start: pts,
+                upload,
+            });
+        }
+
+        let current = self.current.as_mut().expect("a segment is open");
+        current.segment.duration += duration.unwrap_or_default();
+        current.upload.write(data)
+    }
+
+    /// Completes the open segment and marks the playlist as ended, at the end of the stream
+    pub fn finish(&mut self) -> Result<(), String> {
+        self.ended = true;
+        if self.current.is_some() {
+            self.finish_segment()
+        } else {
+            self.write_playlist()
+        }
+    }
+
+    fn finish_segment(&mut self) -> Result<(), String> {
+        let Some(current) = self.current.take() else {
+            return Ok(());
+        };
+        current.upload.finish()?;
+        self.segments.push_back(current.segment);
+
+        while self.segments.len() > self.presentation.playlist_length {
+            let expired = self.segments.pop_front().expect("segments are listed");
+            if self
+                .segments
+                .front()
+                .is_some_and(|next| next.init != expired.init)
+            {
+                self.discontinuity_sequence += 1;
+            }
+            self.expired.push_back(expired.number);
+        }
+        while self.expired.len() > EXPIRED_SEGMENTS_KEPT {
+            let number = self.expired.pop_front().expect("segments expired");
+            self.presentation
+                .delete(&format!("{}/{}", self.name, segment_file(number)));
+        }
+
+        self.write_playlist()
+    }
+
+    fn write_playlist(&self) -> Result<(), String> {
+        let Some(first) = self.segments.front() else {
+            return Ok(());
+        };
+        let target_duration = self
+            .segments
+            .iter()
+            .map(|segment| segment.duration.as_secs_f64().ceil() as u64)
+            .max()
+            .unwrap_or_default()
+            .max(1);
+
+        let mut playlist = format!(
+            "#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-TARGETDURATION:{target_duration}\n#EXT-X-MEDIA-SEQUENCE:{}\n#EXT-X-DISCONTINUITY-SEQUENCE:{}\n",
+            first.number, self.discontinuity_sequence
+        );
+        let mut init = None;
+        for segment in &self.segments {
+            if init != Some(segment.init) {
+                if init.is_some() {
+                    playlist.push_str("#EXT-X-DISCONTINUITY\n");
+                }
+                playlist.push_str(&format!("#EXT-X-MAP:URI=\"init_{}.mp4\"\n", segment.init));
+                init = Some(segment.init);
+            }
+            playlist.push_str(&format!(
+                "#EXTINF:{:.3},\n{}\n",
+                segment.duration.as_secs_f64(),
+                segment_file(segment.number)
+            ));
+        }
+        if self.ended {
+            playlist.push_str("#EXT-X-ENDLIST\n");
+        }
+
+        self.presentation.put(
+            &format!("{}/playlist.m3u8", self.name),
+            playlist.into_bytes(),
+        )
+    }
+}
+
+fn segment_file(number: u64) -> String {
+    format!("segment_{number:05}.m4s")
+}
+
+/// Video with the audio as an alternative rendition when there are both, otherwise the one there is
+fn master_playlist(renditions: &[(String, u32)]) -> String {
+    let mut playlist = String::from("#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-INDEPENDENT-SEGMENTS\n");
+
+    let audio = renditions.iter().find(|(name, _)| name == "audio");
+    let video = renditions.iter().find(|(name, _)| name == "video");
+    match (audio, video) {
+        (Some((audio, audio_bandwidth)), Some((video, video_bandwidth))) => {
+            playlist.push_str(&format!(
+                "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"audio\",NAME=\"audio\",DEFAULT=YES,AUTOSELECT=YES,URI=\"{audio}/playlist.m3u8\"\n\
+                #EXT-X-STREAM-INF:BANDWIDTH={},AUDIO=\"audio\"\n{video}/playlist.m3u8\n",
+                audio_bandwidth + video_bandwidth
+            ));
+        }
+        _ => {
+            for (name, bandwidth) in renditions {
+                playlist.push_str(&format!(
+                    "#EXT-X-STREAM-INF:BANDWIDTH={bandwidth}\n{name}/playlist.m3u8\n"
+                ));
+            }
+        }
+    }
+
+    playlist
+}
+
+fn content_type(path: &str) -> &'static str {
+    if path.ends_with(".m3u8") {
+        "application/vnd.apple.mpegurl"
+    } else if path.ends_with(".m4s") {
+        "video/iso.segment"
+    } else {
+        "video/mp4"
+    }
+}
+
+/// PUTs whole files below `base` in the order they are sent, so a playlist never arrives before its init segment
+fn spawn_uploader(base: String) -> Sender<(String, Vec<u8>)> {
+    let (sender, receiver) = mpsc::channel::<(String, Vec<u8>)>();
+
+    std::thread::Builder::new()
+        .name("cmaf-upload".into())
+        .spawn(move || {
+            for (path, data) in receiver {
+                let url = format!("{base}/{path}");
+                if let Err(err) = ureq::put(&url)
+                    .timeout(UPLOAD_TIMEOUT)
+                    .set("Content-Type", content_type(&path))
+                    .send_bytes(&data)
+                {
+                    warn!("could not upload {url}: {err}");
+                }
+            }
+        })
+        .expect("could not spawn cmaf-upload thread");
+
+    sender
+}
+
+/// A file being written
+enum Upload {
+    File(File),
+    /// A chunked PUT on a thread of its own, its body is sent as the chunks come. Failures are logged there.
+    Http(Sender<Vec<u8>>),
+}
+
+impl Upload {
+    fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        match self {
+            Upload::File(file) => file.write_all(data).map_err(|err| err.to_string()),
+            Upload::Http(chunks) => {
+                let _ = chunks.send(data.to_vec());
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(self) -> Result<(), String> {
+        match self {
+            Upload::File(mut file) => file.flush().map_err(|err| err.to_string()),
+            // ends the body
+            Upload::Http(chunks) => {
+                drop(chunks);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Body of a chunked PUT, read from the chunks sent to it until the sender is dropped
+struct ChunkReader {
+    chunks: Receiver<Vec<u8>>,
+    current: Vec<u8>,
+    position: usize,
+}
+
+impl ChunkReader {
+    fn new(chunks: Receiver<Vec<u8>>) -> Self {
+        Self {
+            chunks,
+            current: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.current.len() {
+            match self.chunks.recv() {
+                Ok(chunk) => {
+                    self.current = chunk;
+                    self.position = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+
+        let count = buf.len().min(self.current.len() - self.position);
+        buf[..count].copy_from_slice(&self.current[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
@@ -0,0 +1,268 @@
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+/// How often the drift estimate is updated
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// Weight of a new measurement in the smoothed drift
+const SMOOTHING: f64 = 0.2;
+/// Offset accumulated before the estimate settled is worked off over this long, on top of the drift
+const CATCH_UP: Duration = Duration::from_secs(120);
+/// Anything faster than this is a gap or a jump in the timestamps, not a clock running off
+const MAX_DRIFT_PPM: f64 = 1000.0;
+/// Largest rate correction applied
+const MAX_CORRECTION_PPM: f64 = 500.0;
+
+/// Estimates how far the sender's sample clock runs from the local clock on one decoded audio track. The
+/// timestamps the jitterbuffer puts on the audio follow the local clock, the number of samples follows the
+/// sender's, so the two drift apart over long sessions.
+///
+/// The answer is the rate to claim for the incoming audio so a resampler to the nominal rate produces exactly
+/// as many samples as local time passes.
+pub struct DriftEstimator {
+    nominal_rate: u32,
+    rate: u32,
+    origin: Option<u64>,
+    /// Duration of the audio since `origin` at the rates it was claimed at, in ns
+    played: f64,
+    last_check: u64,
+    last_offset: f64,
+    /// How far the audio is behind local time since `origin`, in ns
+    offset: f64,
+    /// `offset` of the stretches before the last resync, in ns
+    skew: f64,
+    /// Duration the claimed rates added to the audio, in ns
+    corrected: f64,
+    drift_ppm: Option<f64>,
+}
+
+impl DriftEstimator {
+    pub fn new(nominal_rate: u32) -> Self {
+        Self {
+            nominal_rate,
+            rate: nominal_rate,
+            origin: None,
+            played: 0.0,
+            last_check: 0,
+            last_offset: 0.0,
+            offset: 0.0,
+            skew: 0.0,
+            corrected: 0.0,
+            drift_ppm: None,
+        }
+    }
+
+    /// The rate the audio is currently claimed at
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// Smoothed drift of the sender's clock in ppm, positive when it runs slow. None until the first estimate.
+    pub fn drift_ppm(&self) -> Option<f64> {
+        self.drift_ppm
+    }
+
+    /// The correction the claimed rate applies in ppm, positive when the audio is stretched
+    pub fn correction_ppm(&self) -> f64 {
+        (self.nominal_rate as f64 / self.rate as f64 - 1.0) * 1e6
+    }
+
+    /// How far the received audio fell behind local time in total at its nominal rate, in ms. Negative when it
+    /// got ahead. Gaps and jumps in the timestamps are left out.
+    pub fn skew_ms(&self) -> f64 {
+        self.residual_ms() + self.corrected_ms()
+    }
+
+    /// The part of the skew the correction hasn't worked off yet, in ms
+    pub fn residual_ms(&self) -> f64 {
+        (self.skew + self.offset) / 1e6
+    }
+
+    /// Audio the resampler added in total to follow local time, in ms. Negative when it took audio away.
+    pub fn corrected_ms(&self) -> f64 {
+        self.corrected / 1e6
+    }
+
+    /// Starts measuring again from the next buffer, after a discontinuity. The estimate and rate are kept.
+    pub fn resync(&mut self) {
+        self.origin = None;
+        self.played = 0.0;
+        self.skew += self.offset;
+        self.offset = 0.0;
+    }
+
+    /// Feeds one buffer of `samples` with timestamp `pts` in ns. Returns a new rate to claim whenever it changes.
+    pub fn update(&mut self, pts: u64, samples: u64) -> Option<u32> {
+        self.corrected +=
+            samples as f64 * 1e9 * (1.0 / self.rate as f64 - 1.0 / self.nominal_rate as f64);
+
+        let origin = match self.origin {
+            Some(origin) if pts >= origin => origin,
+            Some(_) => {
+                self.resync();
+                return None;
+            }
+            None => {
+                self.origin = Some(pts);
+                self.last_check = pts;
+                self.last_offset = 0.0;
+                self.played = samples as f64 * 1e9 / self.rate as f64;
+                return None;
+            }
+        };
+
+        // positive when the audio falls behind local time
+        let offset = (pts - origin) as f64 - self.played;
+        self.offset = offset;
+        self.played += samples as f64 * 1e9 / self.rate as f64;
+
+        let interval = pts - self.last_check;
+        if interval < CHECK_INTERVAL.as_nanos() as u64 {
+            return None;
+        }
+
+        // the slope left over with the current correction, plus that correction, is the sender's drift
+        let residual_ppm = (offset - self.last_offset) / interval as f64 * 1e6;
+        let correction_ppm = (self.rate as f64 / self.nominal_rate as f64 - 1.0) * 1e6;
+        let measured_ppm = residual_ppm - correction_ppm;
+
+        if residual_ppm.abs() > MAX_DRIFT_PPM {
+            // the jump is not part of the skew
+            self.offset = self.last_offset;
+            self.resync();
+            return None;
+        }
+
+        self.last_check = pts;
+        self.last_offset = offset;
+
+        let drift_ppm = match self.drift_ppm {
+            Some(drift_ppm) => drift_ppm + SMOOTHING * (measured_ppm - drift_ppm),
+            None => measured_ppm,
+        };
+        self.drift_ppm = Some(drift_ppm);
+
+        let catch_up_ppm = offset / CATCH_UP.as_nanos() as f64 * 1e6;
+        let correction_ppm =
+            (-drift_ppm - catch_up_ppm).clamp(-MAX_CORRECTION_PPM, MAX_CORRECTION_PPM);
+        let rate = (self.nominal_rate as f64 * (1.0 + correction_ppm / 1e6)).round() as u32;
+
+        if rate == self.rate {
+            return None;
+        }
+        self.rate = rate;
+        Some(rate)
+    }
+}
+
+/// The estimator of one track, None until its first buffer
+pub type TrackDrift = Mutex<Option<DriftEstimator>>;
+
+/// The drift estimators of a pipeline's decoded audio tracks, for reporting. A track is dropped with its leg.
+#[derive(Debug, Clone, Default)]
+pub struct DriftMonitor(Arc<Mutex<Vec<Weak<TrackDrift>>>>);
+
+impl DriftMonitor {
+    /// A new slot for the estimator of one track
+    pub fn track(&self) -> Arc<TrackDrift> {
+        let estimator = Arc::new(Mutex::new(None));
+        let mut tracks = self.0.lock().unwrap();
+        tracks.retain(|track| track.strong_count() > 0);
+        tracks.push(Arc::downgrade(&estimator));
+        estimator
+    }
+
+    pub fn to_json(&self) -> Value {
+        let tracks = self.0.lock().unwrap();
+        let tracks: Vec<Value> = tracks
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter_map(|estimator| {
+                let estimator = estimator.lock().unwrap();
+                let estimator = estimator.as_ref()?;
+                Some(json!({
+                    "drift_ppm": estimator.drift_ppm(),
+                    "correction_ppm": estimator.correction_ppm(),
+                    "skew_ms": estimator.skew_ms(),
+                    "corrected_ms": estimator.corrected_ms(),
+                    "residual_ms": estimator.residual_ms(),
+                }))
+            })
+            .collect();
+
+        Value::Array(tracks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48000;
+    /// 20ms buffers
+    const SAMPLES: u64 = 960;
+
+    /// Feeds `seconds` of buffers from a sender whose clock runs `ppm` fast, starting at `pts`. Returns the pts of
+    /// the next buffer.
+    fn feed(estimator: &mut DriftEstimator, mut pts: f64, seconds: u64, ppm: f64) -> f64 {
+        let duration = SAMPLES as f64 * 1e9 / RATE as f64 / (1.0 + ppm / 1e6);
+        for _ in 0..seconds * RATE as u64 / SAMPLES {
+            estimator.update(pts as u64, SAMPLES);
+            pts += duration;
+        }
+        pts
+    }
+
+    #[test]
+    fn follows_a_fast_sender() {
+        let mut estimator = DriftEstimator::new(RATE);
+        feed(&mut estimator, 0.0, 600, 50.0);
+
+        let drift_ppm = estimator.drift_ppm().unwrap();
+        assert!((drift_ppm + 50.0).abs() < 5.0);
+        // 48002.4 Hz, rounded either way
+        assert!((48002..=48003).contains(&estimator.rate()));
+        // the audio got 30ms ahead over the ten minutes, the correction took nearly all of it away
+        assert!((estimator.skew_ms() + 30.0).abs() < 1.0);
+        assert!(estimator.residual_ms().abs() < 5.0);
+    }
+
+    #[test]
+    fn leaves_a_steady_sender_alone() {
+        let mut estimator = DriftEstimator::new(RATE);
+        feed(&mut estimator, 0.0, 300, 0.0);
+
+        assert!(estimator.drift_ppm().unwrap().abs() < 1.0);
+        assert_eq!(estimator.rate(), RATE);
+        assert!(estimator.skew_ms().abs() < 1.0);
+    }
+
+    #[test]
+    fn limits_the_correction() {
+        let mut estimator = DriftEstimator::new(RATE);
+        feed(&mut estimator, 0.0, 600, 900.0);
+
+        assert_eq!(estimator.rate(), 48024);
+        assert!((estimator.correction_ppm() + MAX_CORRECTION_PPM).abs() < 1.0);
+    }
+
+    #[test]
+    fn resyncs_on_a_jump() {
+        let mut estimator = DriftEstimator::new(RATE);
+        let pts = feed(&mut estimator, 0.0, 300, 50.0);
+        let rate = estimator.rate();
+        let skew_ms = estimator.skew_ms();
+
+        // five seconds of audio missing
+        let pts = feed(&mut estimator, pts + 5e9, 30, 50.0);
+        assert!((estimator.skew_ms() - skew_ms + 1.5).abs() < 1.0);
+        assert!(estimator.rate().abs_diff(rate) <= 1);
+
+        // and back by as much
+        feed(&mut estimator, pts - 5e9, 30, 50.0);
+        assert!((estimator.skew_ms() - skew_ms + 3.0).abs() < 1.0);
+        assert!(estimator.rate().abs_diff(rate) <= 1);
+        assert!((estimator.drift_ppm().unwrap() + 50.0).abs() < 5.0);
+    }
+}
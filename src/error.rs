@@ -0,0 +1,121 @@
+use std::fmt;
+
+use serde_json::{Value, json};
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// Invalid command line or library configuration
+    Config(String),
+    /// The pipeline description references elements that are not installed
+    MissingElements(Vec<String>),
+    /// GStreamer failed to build or run the pipeline
+    Pipeline(String),
+    /// An element of the running pipeline posted an error
+    Element {
+        /// Path of the element that posted the error
+        element: Option<String>,
+        /// GStreamer error domain and code, e.g. `ResourceError::NotAuthorized`
+        code: String,
+        category: Category,
+        message: String,
+    },
+    /// The pipeline stopped making progress for --stall-timeout, running it again may help
+    Stalled(String),
+    /// The branch of a WHEP track could not be built while running, running the pipeline again may help
+    Branch(String),
+}
+
+/// Coarse classification of a fatal error, for deciding whether retrying or fixing the deployment helps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    /// Invalid arguments, templates or API requests
+    Config,
+    /// A GStreamer plugin or element is not installed
+    MissingPlugin,
+    /// The WHEP endpoint rejected the credentials
+    Auth,
+    /// The WHEP endpoint or the SRT peer could not be reached, or the connection was lost
+    Network,
+    /// The media could not be decoded, encoded or muxed
+    Media,
+    /// The pipeline stopped making progress
+    Stalled,
+    /// Anything else
+    Pipeline,
+}
+
+impl Category {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Category::Config => "config",
+            Category::MissingPlugin => "missing_plugin",
+            Category::Auth => "auth",
+            Category::Network => "network",
+            Category::Media => "media",
+            Category::Stalled => "stalled",
+            Category::Pipeline => "pipeline",
+        }
+    }
+}
+
+impl Error {
+    pub fn category(&self) -> Category {
+        match self {
+            Error::Config(_) => Category::Config,
+            Error::MissingElements(_) => Category::MissingPlugin,
+            Error::Pipeline(_) => Category::Pipeline,
+            Error::Element { category, .. } => *category,
+            Error::Stalled(_) => Category::Stalled,
+            Error::Branch(_) => Category::Pipeline,
+        }
+    }
+
+    /// Stable identifier of the error, finer grained than the category
+    pub fn code(&self) -> String {
+        match self {
+            Error::Config(_) => String::from("Config"),
+            Error::MissingElements(_) => String::from("MissingElements"),
+            Error::Pipeline(_) => String::from("Pipeline"),
+            Error::Element { code, .. } => code.clone(),
+            Error::Stalled(_) => String::from("Stalled"),
+            Error::Branch(_) => String::from("Branch"),
+        }
+    }
+
+    /// The error as a single JSON object, for orchestrators to classify failures by
+    pub fn to_json(&self) -> Value {
+        let (element, message) = match self {
+            Error::Element {
+                element, message, ..
+            } => (element.clone(), message.clone()),
+            Error::MissingElements(elements) => (elements.first().cloned(), self.to_string()),
+            _ => (None, self.to_string()),
+        };
+
+        json!({
+            "error": {
+                "code": self.code(),
+                "category": self.category().as_str(),
+                "message": message,
+                "element": element,
+            }
+        })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(msg) => write!(f, "Invalid configuration: {msg}"),
+            Error::MissingElements(elements) => write!(f, "Missing element(s): {elements:?}"),
+            Error::Pipeline(msg) => write!(f, "{msg}"),
+            Error::Element {
+                element, message, ..
+            } => write!(f, "Error from {element:?}: {message}"),
+            Error::Stalled(reason) => write!(f, "Pipeline stalled: {reason}"),
+            Error::Branch(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,195 @@
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{Value, json};
+use tracing::{debug, warn};
+
+use crate::alarms::AlarmChange;
+
+/// Gateway lifecycle events delivered to external systems
+#[derive(Debug, Clone)]
+pub enum Event {
+    Start,
+    WhepConnected,
+    WhepDisconnected {
+        state: String,
+    },
+    SrtCallerConnected {
+        address: String,
+    },
+    SrtCallerDisconnected {
+        address: String,
+    },
+    Error {
+        source: Option<String>,
+        message: String,
+    },
+    Alarm(AlarmChange),
+    Paused {
+        slate: bool,
+    },
+    Resumed,
+    /// The WHEP session was left for lack of SRT callers, see --on-idle suspend
+    InputSuspended,
+    InputResumed,
+    Shutdown,
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Start => "start",
+            Event::WhepConnected => "whep-connected",
+            Event::WhepDisconnected { .. } => "whep-disconnected",
+            Event::SrtCallerConnected { .. } => "srt-caller-connected",
+            Event::SrtCallerDisconnected { .. } => "srt-caller-disconnected",
+            Event::Error { .. } => "error",
+            Event::Alarm(change) if change.raised => "alarm-raised",
+            Event::Alarm(_) => "alarm-cleared",
+            Event::Paused { .. } => "paused",
+            Event::Resumed => "resumed",
+            Event::InputSuspended => "input-suspended",
+            Event::InputResumed => "input-resumed",
+            Event::Shutdown => "shutdown",
+        }
+    }
+
+    fn fields(&self) -> Value {
+        match self {
+            Event::WhepDisconnected { state } => json!({ "state": state }),
+            Event::SrtCallerConnected { address } | Event::SrtCallerDisconnected { address } => {
+                json!({ "address": address })
+            }
+            Event::Error { source, message } => json!({ "source": source, "message": message }),
+            Event::Alarm(change) => json!({
+                "alarm": change.kind.as_str(),
+                "value": change.value,
+                "threshold": change.threshold,
+            }),
+            Event::Paused { slate } => json!({ "slate": slate }),
+            Event::Start
+            | Event::WhepConnected
+            | Event::Resumed
+            | Event::InputSuspended
+            | Event::InputResumed
+            | Event::Shutdown => json!({}),
+        }
+    }
+}
+
+/// A destination for event payloads. Sinks run on the notifier's background thread, so they may block.
+pub trait EventSink: Send {
+    fn publish(&mut self, event: &Event, payload: &Value);
+}
+
+/// POSTs every payload as JSON to a url
+pub struct Webhook {
+    url: String,
+}
+
+impl Webhook {
+    pub fn new(url: String) -> Self {
+        Self { url }
+    }
+}
+
+impl EventSink for Webhook {
+    fn publish(&mut self, _event: &Event, payload: &Value) {
+        let result = ureq::post(&self.url)
+            .timeout(Duration::from_secs(5))
+            .set("Content-Type", "application/json")
+            .send_string(&payload.to_string());
+
+        match result {
+            Ok(_) => debug!("webhook delivered '{}'", payload["event"]),
+            Err(err) => warn!("webhook delivery of '{}' failed: {err}", payload["event"]),
+        }
+    }
+}
+
+struct Delivery {
+    event: Event,
+    timestamp: u64,
+    done: Option<mpsc::Sender<()>>,
+}
+
+/// Hands events to a background thread that publishes them as JSON to every sink.
+/// Cloning is cheap, and a notifier without sinks silently drops everything.
+#[derive(Clone, Default)]
+pub struct Notifier {
+    tx: Option<mpsc::Sender<Delivery>>,
+}
+
+impl Notifier {
+    /// `context` is merged into every payload, e.g. the input and output urls of this gateway
+    pub fn new(mut sinks: Vec<Box<dyn EventSink>>, context: Value) -> Self {
+        if sinks.is_empty() {
+            return Self::default();
+        }
+
+        let (tx, rx) = mpsc::channel::<Delivery>();
+
+        std::thread::Builder::new()
+            .name("events".into())
+            .spawn(move || {
+                for delivery in rx {
+                    let mut payload = json!({
+                        "event": delivery.event.name(),
+                        "timestamp": delivery.timestamp,
+                    });
+                    merge(&mut payload, &context);
+                    merge(&mut payload, &delivery.event.fields());
+
+                    for sink in sinks.iter_mut() {
+                        sink.publish(&delivery.event, &payload);
+                    }
+
+                    if let Some(done) = delivery.done {
+                        let _ = done.send(());
+                    }
+                }
+            })
+            .expect("could not spawn events thread");
+
+        Self { tx: Some(tx) }
+    }
+
+    pub fn notify(&self, event: Event) {
+        self.send(event, None);
+    }
+
+    /// Like `notify`, but blocks until the event is delivered or `timeout` passes. Used right before exiting.
+    pub fn notify_and_wait(&self, event: Event, timeout: Duration) {
+        let (done_tx, done_rx) = mpsc::channel();
+
+        if self.send(event, Some(done_tx)) {
+            let _ = done_rx.recv_timeout(timeout);
+        }
+    }
+
+    fn send(&self, event: Event, done: Option<mpsc::Sender<()>>) -> bool {
+        let Some(tx) = &self.tx else {
+            return false;
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        tx.send(Delivery {
+            event,
+            timestamp,
+            done,
+        })
+        .is_ok()
+    }
+}
+
+fn merge(target: &mut Value, source: &Value) {
+    if let (Value::Object(target), Value::Object(source)) = (target, source) {
+        for (key, value) in source {
+            target.insert(key.clone(), value.clone());
+        }
+    }
+}
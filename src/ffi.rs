@@ -0,0 +1,203 @@
+//! Minimal C ABI for embedding the gateway, see `include/whep_srt.h`.
+//! Event and stats payloads are handed over as NUL terminated JSON strings.
+
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
+use std::ptr::null_mut;
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+
+use clap::Parser;
+use serde_json::{Value, json};
+use tracing::error;
+use tracing_subscriber::EnvFilter;
+
+use crate::args::Args;
+use crate::error::Error;
+use crate::events::{Event, EventSink};
+use crate::gateway::Gateway;
+use crate::process::ProcessSampler;
+use crate::stats::StatsSampler;
+
+pub type WhepSrtEventCallback =
+    Option<unsafe extern "C" fn(payload: *const c_char, user_data: *mut c_void)>;
+
+struct CallbackSink {
+    callback: unsafe extern "C" fn(*const c_char, *mut c_void),
+    user_data: *mut c_void,
+}
+
+// the caller promises that user_data may be used from the events thread, see whep_srt_create
+unsafe impl Send for CallbackSink {}
+
+impl EventSink for CallbackSink {
+    fn publish(&mut self, _event: &Event, payload: &Value) {
+        if let Ok(payload) = CString::new(payload.to_string()) {
+            unsafe { (self.callback)(payload.as_ptr(), self.user_data) };
+        }
+    }
+}
+
+pub struct WhepSrtGateway {
+    gateway: Gateway,
+    runner: Option<JoinHandle<Result<(), Error>>>,
+    /// CPU usage in `whep_srt_stats` is since the previous call for this gateway
+    process: Mutex<ProcessSampler>,
+    /// Track bitrates in `whep_srt_stats` are since the previous call for this gateway
+    stats: Mutex<StatsSampler>,
+}
+
+/// Initializes tracing output to stderr (RUST_LOG, defaulting to info) for hosts that want the gateway's log output
+#[unsafe(no_mangle)]
+pub extern "C" fn whep_srt_init_logging() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .try_init();
+}
+
+/// Creates a gateway from command line style arguments, excluding the program name,
+/// e.g. `{"-i", "http://host/whep", "-o", "srt://0.0.0.0:1234?mode=listener"}`.
+/// Returns NULL if the arguments are invalid or the pipeline could not be built.
+///
+/// # Safety
+/// `argv` must point to `argc` valid NUL terminated strings. `callback` may be NULL, otherwise it is called
+/// from a background thread with `user_data`, which must stay valid until `whep_srt_destroy` returns.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn whep_srt_create(
+    argc: c_int,
+    argv: *const *const c_char,
+    callback: WhepSrtEventCallback,
+    user_data: *mut c_void,
+) -> *mut WhepSrtGateway {
+    let mut cli = vec![String::from("whep-srt")];
+    for i in 0..argc.max(0) as usize {
+        let arg = unsafe { CStr::from_ptr(*argv.add(i)) };
+        cli.push(arg.to_string_lossy().into_owned());
+    }
+
+    let args = match Args::try_parse_from(cli) {
+        Ok(args) => args,
+        Err(err) => {
+            error!("{err}");
+            return null_mut();
+        }
+    };
+
+    let mut sinks: Vec<Box<dyn EventSink>> = Vec::new();
+    if let Some(callback) = callback {
+        sinks.push(Box::new(CallbackSink {
+            callback,
+            user_data,
+        }));
+    }
+
+    match Gateway::with_sinks(args, sinks) {
+        Ok(gateway) => Box::into_raw(Box::new(WhepSrtGateway {
+            gateway,
+            runner: None,
+            process: Mutex::default(),
+            stats: Mutex::default(),
+        })),
+        Err(err) => {
+            error!("{err}");
+            null_mut()
+        }
+    }
+}
+
+/// Starts streaming on a background thread. Returns 0 on success, -1 if already started.
+///
+/// # Safety
+/// `gateway` must be a pointer returned by `whep_srt_create` that has not been destroyed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn whep_srt_start(gateway: *mut WhepSrtGateway) -> c_int {
+    let Some(gateway) = (unsafe { gateway.as_mut() }) else {
+        return -1;
+    };
+    if gateway.runner.is_some() {
+        return -1;
+    }
+
+    let runner = gateway.gateway.clone();
+    gateway.runner = Some(std::thread::spawn(move || runner.run()));
+
+    0
+}
+
+/// Stops streaming and waits for the pipeline to shut down.
+/// Returns 0 if the gateway ran without errors, -1 otherwise.
+///
+/// # Safety
+/// `gateway` must be a pointer returned by `whep_srt_create` that has not been destroyed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn whep_srt_stop(gateway: *mut WhepSrtGateway) -> c_int {
+    let Some(gateway) = (unsafe { gateway.as_mut() }) else {
+        return -1;
+    };
+    let Some(runner) = gateway.runner.take() else {
+        return -1;
+    };
+
+    gateway.gateway.stop();
+
+    match runner.join() {
+        Ok(Ok(())) => 0,
+        Ok(Err(err)) => {
+            error!("{err}");
+            -1
+        }
+        Err(_) => -1,
+    }
+}
+
+/// Returns the current status and statistics as JSON. Free the result with `whep_srt_free_string`.
+///
+/// # Safety
+/// `gateway` must be a pointer returned by `whep_srt_create` that has not been destroyed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn whep_srt_stats(gateway: *const WhepSrtGateway) -> *mut c_char {
+    let Some(gateway) = (unsafe { gateway.as_ref() }) else {
+        return null_mut();
+    };
+
+    let stats = json!({
+        "status": gateway.gateway.status().to_json(),
+        "health": gateway.gateway.health().to_json(),
+        "stats": gateway.stats.lock().unwrap().collect(gateway.gateway.pipeline()).to_json(),
+        "streaming_threads": gateway.gateway.streaming_threads(),
+        "queues": gateway.gateway.queues().to_json(),
+        "drift": gateway.gateway.drift().to_json(),
+        "startup": gateway.gateway.startup().to_json(),
+        "process": gateway.process.lock().unwrap().sample().map(|process| process.to_json()),
+    });
+
+    CString::new(stats.to_string())
+        .map(CString::into_raw)
+        .unwrap_or(null_mut())
+}
+
+/// # Safety
+/// `s` must be NULL or a string returned by this library that has not been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn whep_srt_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Stops the gateway if it is running and releases it.
+///
+/// # Safety
+/// `gateway` must be NULL or a pointer returned by `whep_srt_create` that has not been destroyed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn whep_srt_destroy(gateway: *mut WhepSrtGateway) {
+    if gateway.is_null() {
+        return;
+    }
+
+    unsafe {
+        whep_srt_stop(gateway);
+        drop(Box::from_raw(gateway));
+    }
+}
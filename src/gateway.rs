@@ -0,0 +1,4758 @@
+use std::env;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, mpsc};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use gio::prelude::*;
+use gst::prelude::*;
+use gstreamer::{
+    self as gst, DebugGraphDetails, ElementFactory, GhostPad, PadDirection, PadProbeData,
+    PadProbeReturn, PadProbeType,
+};
+use gstreamer_app as gst_app;
+use gstreamer_audio as gst_audio;
+use gstreamer_webrtc as gst_webrtc;
+use serde_json::{Value, json};
+use tracing::{error, info, warn};
+
+use crate::alarms;
+use crate::args::{
+    Args, AudioCodec, BedPosition, DtxFill, Media, OnIdle, OnInputEos, ProfileSettings,
+    RateControl, RecordFormat, TestSource, TrackGain, TrackPan, VideoCodec,
+};
+use crate::captions::{self, PendingPes, Transcriber};
+use crate::clock::{self, ReferenceClock};
+use crate::cmaf;
+use crate::drift::{DriftEstimator, DriftMonitor};
+use crate::error::{Category, Error};
+use crate::events::{Event, EventSink, Notifier, Webhook};
+use crate::health::{Check, Health, MediaClock, SrtState};
+use crate::nats::NatsPublisher;
+use crate::network;
+use crate::oauth::{self, ClientCredentials};
+use crate::output::{CmafDestination, Icecast, Output};
+use crate::queues::{self, QueueMonitor};
+use crate::startup::Startup;
+use crate::stats;
+use crate::threads::{self, ThreadOptions};
+use crate::ts::{DiscontinuityMarker, PmtRewriter, TsDescriptor};
+use crate::whep;
+
+/// Elements that are stood in for by others with the same properties when they are missing, in order of preference
+const SUBSTITUTES: &[(&str, &[&str])] = &[("avenc_aac", &["fdkaacenc", "voaacenc", "faac"])];
+
+/// Name of the application message `Gateway::stop` posts to end the bus loop
+const STOP_MESSAGE: &str = "whep-srt-stop";
+
+/// Name of the details of the error posted when the branch of a WHEP track could not be built
+const BRANCH_ERROR: &str = "whep-srt-branch-error";
+
+/// Name of the application message the --stall-timeout supervisor posts to end the bus loop
+const STALL_MESSAGE: &str = "whep-srt-stalled";
+
+/// How often the --stall-timeout supervisor looks at the pipeline
+const SUPERVISOR_INTERVAL: Duration = Duration::from_secs(1);
+
+/// x264enc bitrate in kbit/s of the --generate-video track, a test pattern needs very little
+const GENERATED_VIDEO_BITRATE: u32 = 500;
+
+/// Encoder bitrate in kbit/s when the source video is transcoded
+const SOURCE_VIDEO_BITRATE: u32 = 4000;
+
+/// How long the muxers of the --record-path and --dump-audio files get to finish them when the gateway stops
+const FILE_FINISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the local addresses are compared for --reconnect-on-network-change
+const NETWORK_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often --idle-timeout checks for the input to be left, a suspended input subscribes again as soon as a caller
+/// connects
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long webrtcbin gets to gather ICE candidates before a description is sent with those it has
+const ICE_GATHERING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the WebRTC connection gets to come back after an ICE restart, before --on-input-eos takes over
+const ICE_RESTART_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// STUN server of the --ice-restart webrtcbin, the one whepclientsrc uses by default
+const WHEP_STUN_SERVER: &str = "stun://stun.l.google.com:19302";
+
+/// What the --ice-restart webrtcbin offers to receive, in order of preference. H.264 first, the output can
+/// often take it as it is.
+const WHEP_AUDIO_CAPS: &str = "application/x-rtp, media=audio, encoding-name=OPUS, payload=111, clock-rate=48000, encoding-params=(string)2";
+const WHEP_VIDEO_CAPS: &str = "application/x-rtp, media=video, encoding-name=H264, payload=102, clock-rate=90000, packetization-mode=(string)1, profile-level-id=(string)42e01f; \
+    application/x-rtp, media=video, encoding-name=VP8, payload=96, clock-rate=90000; \
+    application/x-rtp, media=video, encoding-name=VP9, payload=98, clock-rate=90000";
+
+/// A WHEP track whose timestamps jump by more than this against the clock is resynchronized
+const RESYNC_THRESHOLD: gst::ClockTime = gst::ClockTime::from_seconds(1);
+
+/// Weight of each buffer in a track's usual delay, slow enough to ignore jitter
+const RESYNC_SMOOTHING: f64 = 0.01;
+
+/// Amplitude of --dtx-fill comfort-noise, about -66 dBFS
+const COMFORT_NOISE_LEVEL: f32 = 0.0005;
+
+/// RTP header extension carrying the transport-wide sequence numbers TWCC feedback is about
+const TWCC_EXTENSION: &str =
+    "http://www.ietf.org/id/draft-holmer-rmcat-transport-wide-cc-extensions-01";
+
+/// The generated pipeline, `--pipeline-template` replaces it. `{input}` is the WHEP source, `{mixer}` the mixer its audio
+/// pads are linked to, `{volume}` the master volume and `{output}` everything from the audio filter up to the sink.
+const DEFAULT_TEMPLATE: &str =
+    "{input} audiotestsrc wave=silence is-live=true ! {caps} ! {mixer} ! {volume} ! {output}";
+
+/// The pipeline with audio passthrough, the WHEP audio is linked to the muxer in `connect_input` and there is no mix
+const PASSTHROUGH_TEMPLATE: &str = "{input} {output}";
+
+/// The pipeline with --disable-audio, only the video reaches the muxer
+const VIDEO_ONLY_TEMPLATE: &str = "{input} {output}";
+
+/// Interval of the generated --teletext pages, one per 25fps frame
+const TELETEXT_FRAME: gst::ClockTime = gst::ClockTime::from_mseconds(40);
+
+/// Expansion ratio of the --gate-threshold expander, high enough to act as a noise gate
+const GATE_RATIO: f32 = 4.0;
+
+/// Coarse gateway state, derived from the lifecycle events
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Status {
+    pub running: bool,
+    pub whep_connected: bool,
+    pub srt_callers: usize,
+    pub last_error: Option<String>,
+    /// None while streaming, Some(slate) while paused
+    pub paused: Option<bool>,
+    /// Set while the WHEP session is left for lack of SRT callers
+    pub suspended: bool,
+    /// Unix times in seconds `running`, `whep_connected` and whether there are `srt_callers` last changed
+    pub running_since: Option<u64>,
+    pub whep_since: Option<u64>,
+    pub srt_callers_since: Option<u64>,
+}
+
+impl Status {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "running": self.running,
+            "whep_connected": self.whep_connected,
+            "srt_callers": self.srt_callers,
+            "last_error": self.last_error,
+            "paused": self.paused.is_some(),
+            "slate": self.paused == Some(true),
+            "suspended": self.suspended,
+        })
+    }
+}
+
+struct StatusTracker(Arc<Mutex<Status>>);
+
+impl EventSink for StatusTracker {
+    fn publish(&mut self, event: &Event, payload: &Value) {
+        let mut status = self.0.lock().unwrap();
+        let timestamp = payload["timestamp"].as_u64();
+
+        match event {
+            Event::Start => {
+                status.running = true;
+                status.running_since = timestamp;
+            }
+            Event::Shutdown => {
+                status.running = false;
+                status.running_since = timestamp;
+            }
+            Event::WhepConnected => {
+                status.whep_connected = true;
+                status.whep_since = timestamp;
+            }
+            Event::WhepDisconnected { .. } => {
+                status.whep_connected = false;
+                status.whep_since = timestamp;
+            }
+            Event::SrtCallerConnected { .. } => {
+                if status.srt_callers == 0 {
+                    status.srt_callers_since = timestamp;
+                }
+                status.srt_callers += 1;
+            }
+            Event::SrtCallerDisconnected { .. } => {
+                status.srt_callers = status.srt_callers.saturating_sub(1);
+                if status.srt_callers == 0 {
+                    status.srt_callers_since = timestamp;
+                }
+            }
+            Event::Error { message, .. } => status.last_error = Some(message.clone()),
+            Event::Paused { slate } => status.paused = Some(*slate),
+            Event::Resumed => status.paused = None,
+            Event::InputSuspended => status.suspended = true,
+            Event::InputResumed => status.suspended = false,
+            Event::Alarm(_) => (),
+        }
+    }
+}
+
+/// Takes the WHEP connected milestone from the lifecycle events
+struct StartupTracker(Startup);
+
+impl EventSink for StartupTracker {
+    fn publish(&mut self, event: &Event, _payload: &Value) {
+        if let Event::WhepConnected = event {
+            self.0.mark_whep_connected();
+        }
+    }
+}
+
+/// One WHEP to SRT pipeline. Cloning gives another handle to the same pipeline.
+#[derive(Clone)]
+pub struct Gateway {
+    args: Args,
+    pipeline: gst::Pipeline,
+    notifier: Notifier,
+    status: Arc<Mutex<Status>>,
+    streaming_threads: Arc<AtomicUsize>,
+    queues: QueueMonitor,
+    input: Input,
+    /// Set while paused without a slate, no transport stream reaches the SRT sink
+    output_paused: Arc<AtomicBool>,
+    /// SRT callers connected right now, counted on the sink's signals. `Status` only follows them once the events
+    /// got past every event sink, which can take seconds with a slow webhook.
+    srt_callers: Arc<SrtCallers>,
+    /// Highest sample of each channel of the mix since `audio_peaks` was last called
+    peaks: Arc<Mutex<Vec<f32>>>,
+    media: MediaClock,
+}
+
+impl Gateway {
+    pub fn new(args: Args) -> Result<Self, Error> {
+        Self::with_sinks(args, Vec::new())
+    }
+
+    /// Like `new`, with additional sinks receiving the lifecycle events next to the ones configured in `args`
+    pub fn with_sinks(args: Args, sinks: Vec<Box<dyn EventSink>>) -> Result<Self, Error> {
+        if let Some(test_source) = args.test_source {
+            let input = test_source_input(test_source, args.channels, &args.bed);
+            return Self::build(args, &input, sinks, false);
+        }
+
+        let whep_url = args
+            .input_url
+            .as_deref()
+            .ok_or_else(|| Error::Config(String::from("an input url is required")))?;
+
+        /*  NOTE:
+           whepsrc was the first WHIP implementation in gstreamer, based on webrtcbin directly. it's present in gstwebrtchttp plugin.
+           whepclientsrc has later been added and it is using the signaller interface on webrtcsrc and webrtcsink rust plugins. it's present in gstrswebrtc plugin.
+           whepclientsrc is reusing a lot of functionallity and is supposed to deprecate whepsrc in the future.
+
+           In this project we use the new whepclientsrc, --legacy-whepsrc selects the old whepsrc for servers it works better with.
+           Whichever is chosen, the other one is used when its plugin is not available.
+           --ice-restart uses neither, the gateway does the signalling itself on a webrtcbin, see WhepClient.
+        */
+
+        init()?;
+        let input = if args.ice_restart {
+            if ElementFactory::find("webrtcbin").is_none() {
+                return Err(Error::MissingElements(vec![String::from("webrtcbin")]));
+            }
+            info!("subscribing with webrtcbin and the gateway's own WHEP signalling");
+            // WhepClient adds the transceivers and subscribes
+            format!("webrtcbin name=input bundle-policy=max-bundle stun-server={WHEP_STUN_SERVER}")
+        } else if whep_source(args.legacy_whepsrc)? == "whepsrc" {
+            if args.no_nack {
+                warn!("whepsrc has no setting for retransmission, --no-nack is ignored");
+            }
+            // whepsrc only offers what it has caps for, so it receives audio only
+            if args.media == Media::Video {
+                return Err(Error::Config(String::from(
+                    "--legacy-whepsrc receives audio only, it can't be used with --media video",
+                )));
+            }
+            let audio_caps = &args.whepsrc_audio_caps;
+            format!(
+                "whepsrc name=input use-link-headers=false whep-endpoint=\"{whep_url}\" audio-caps=\"{audio_caps}\" video-caps=\"\""
+            )
+        } else {
+            // no codecs for a media kind means no transceiver for it, so it is never negotiated or received
+            let codecs = match args.media {
+                _ if args.disable_audio => " audio-codecs=\"<>\"",
+                Media::Both => "",
+                Media::Audio => " video-codecs=\"<>\"",
+                Media::Video => " audio-codecs=\"<>\"",
+            };
+            let retransmission = if args.no_nack {
+                " do-retransmission=false"
+            } else {
+                ""
+            };
+            format!(
+                "whepclientsrc name=input signaller::whep-endpoint=\"{whep_url}\"{codecs}{retransmission}"
+            )
+        };
+
+        let passthrough = audio_passthrough(&args);
+        Self::build(args, &input, sinks, passthrough)
+    }
+
+    /// The same encode/mux/SRT chain fed by a live test signal instead of WHEP, used by the bench mode and self-test.
+    /// The signal is `--test-source`, ticks by default.
+    pub fn test_source(args: Args) -> Result<Self, Error> {
+        if args.disable_audio {
+            return Err(Error::Config(String::from(
+                "--disable-audio needs a WHEP source, the test signal is audio",
+            )));
+        }
+        let input = test_source_input(
+            args.test_source.unwrap_or_default(),
+            args.channels,
+            &args.bed,
+        );
+
+        Self::build(args, &input, Vec::new(), false)
+    }
+
+    fn build(
+        mut args: Args,
+        input: &str,
+        mut sinks: Vec<Box<dyn EventSink>>,
+        passthrough: bool,
+    ) -> Result<Self, Error> {
+        args.output_url = args.srt_output_url()?;
+        let output_url = &args.output_url;
+
+        if let Some(webhook_url) = &args.webhook_url {
+            sinks.push(Box::new(Webhook::new(webhook_url.clone())));
+        }
+        if let Some(nats_url) = &args.nats_url {
+            let publisher =
+                NatsPublisher::new(nats_url, args.nats_subject.clone()).map_err(Error::Config)?;
+            sinks.push(Box::new(publisher));
+        }
+
+        let status = Arc::new(Mutex::new(Status::default()));
+        sinks.push(Box::new(StatusTracker(status.clone())));
+        let startup = Startup::new();
+        sinks.push(Box::new(StartupTracker(startup.clone())));
+
+        let notifier = Notifier::new(
+            sinks,
+            json!({ "input_url": args.input_url, "output_url": output_url }),
+        );
+
+        if args.dot_debug {
+            let current_dir = format!(
+                "{}",
+                env::current_dir()
+                    .expect("could not get current directory")
+                    .display()
+            );
+
+            tracing::info!("Debugging .dot files to '{current_dir}'");
+            unsafe {
+                env::set_var("GST_DEBUG_DUMP_DOT_DIR", current_dir);
+            }
+        }
+
+        let output = Output::parse(output_url)?;
+
+        init()?;
+
+        if !args.gst_debug.is_empty() {
+            // process wide, on top of GST_DEBUG
+            gst::log::set_active(true);
+            gst::log::set_threshold_from_string(&args.gst_debug.join(","), false);
+        }
+
+        if args.channels > 6 && matches!(args.audio_codec, AudioCodec::Ac3 | AudioCodec::Eac3) {
+            return Err(Error::Config(format!(
+                "AC-3 and E-AC-3 carry at most 6 channels, not --channels {}",
+                args.channels
+            )));
+        }
+        if args.disable_audio {
+            if output != Output::Srt {
+                return Err(Error::Config(String::from(
+                    "--disable-audio needs an SRT output",
+                )));
+            }
+            if args.media == Media::Audio {
+                return Err(Error::Config(String::from(
+                    "--disable-audio leaves nothing to send with --media audio",
+                )));
+            }
+        }
+        if args.dialnorm.is_some() && args.audio_codec == AudioCodec::Aac {
+            return Err(Error::Config(String::from(
+                "--dialnorm needs --audio-codec ac3 or eac3",
+            )));
+        }
+        if let Output::Cmaf(_) = &output {
+            if matches!(args.audio_codec, AudioCodec::Ac3 | AudioCodec::Eac3) {
+                return Err(Error::Config(String::from(
+                    "CMAF output supports --audio-codec aac or opus",
+                )));
+            }
+            if args.generate_video.is_some() {
+                return Err(Error::Config(String::from(
+                    "--generate-video is not supported with CMAF output",
+                )));
+            }
+        }
+        if args.record_path.is_some()
+            && args.record_format != RecordFormat::MkvSource
+            && output != Output::Srt
+        {
+            return Err(Error::Config(String::from(
+                "--record-path needs an SRT output, except with --record-format mkv-source",
+            )));
+        }
+        if let Some(rate_control) = args.audio_rate_control {
+            // lamemp3enc and vorbisenc have all three modes
+            let constant_bitrate_only = match &output {
+                Output::Sdi { .. } => {
+                    return Err(Error::Config(String::from(
+                        "--audio-rate-control needs an encoded output, SDI audio is uncompressed",
+                    )));
+                }
+                Output::Icecast(icecast) => icecast.mount.ends_with(".aac"),
+                Output::Srt | Output::Cmaf(_) => {
+                    if args.audio_codec == AudioCodec::Opus && rate_control == RateControl::Cq {
+                        return Err(Error::Config(String::from(
+                            "Opus has no constant quality mode, use --audio-rate-control cbr or vbr",
+                        )));
+                    }
+                    args.audio_codec != AudioCodec::Opus
+                }
+            };
+            if constant_bitrate_only && rate_control != RateControl::Cbr {
+                return Err(Error::Config(String::from(
+                    "AAC, AC-3 and E-AC-3 are only encoded at a constant bitrate, use --audio-rate-control cbr",
+                )));
+            }
+        }
+        if (args.idle_timeout.is_some() || args.lazy_connect)
+            && !(output == Output::Srt && args.output_url.contains("mode=listener"))
+        {
+            return Err(Error::Config(String::from(
+                "--idle-timeout and --lazy-connect need an SRT output in listener mode",
+            )));
+        }
+        if (args.output_thread_nice.is_some() || args.output_thread_rt_priority.is_some())
+            && output != Output::Srt
+        {
+            return Err(Error::Config(String::from(
+                "--output-thread-nice and --output-thread-rt-priority need an SRT output",
+            )));
+        }
+        if args.mosaic && output != Output::Srt {
+            return Err(Error::Config(String::from("--mosaic needs an SRT output")));
+        }
+        if args.captions.is_some() {
+            if output != Output::Srt {
+                return Err(Error::Config(String::from(
+                    "--captions needs an SRT output",
+                )));
+            }
+            if args
+                .teletext
+                .as_deref()
+                .is_some_and(|teletext| teletext != "generated")
+            {
+                return Err(Error::Config(String::from(
+                    "--captions generate the teletext PID, it can't be taken from --teletext URI",
+                )));
+            }
+        }
+        if args.interlaced && args.video_codec != VideoCodec::H264 {
+            return Err(Error::Config(String::from(
+                "interlaced output is only supported with --video-codec h264",
+            )));
+        }
+
+        if args.video_layer_rids.len() != 3 {
+            return Err(Error::Config(String::from(
+                "--video-layer-rids needs the rids of the high, medium and low layer",
+            )));
+        }
+
+        if !args.bed.is_empty() {
+            if args.bed.len() != args.channels as usize {
+                return Err(Error::Config(format!(
+                    "--bed has {} positions, it needs --channels {}",
+                    args.bed.len(),
+                    args.bed.len()
+                )));
+            }
+            let mut positions = args.bed.clone();
+            positions.sort();
+            positions.dedup();
+            if positions.len() != args.bed.len() {
+                return Err(Error::Config(String::from(
+                    "every position of --bed can only be taken once",
+                )));
+            }
+        }
+
+        if args.reconnect_on_network_change && args.on_input_eos != OnInputEos::Reconnect {
+            return Err(Error::Config(String::from(
+                "--reconnect-on-network-change needs --on-input-eos reconnect",
+            )));
+        }
+        if args.no_nack && (args.rtx_max_retries.is_some() || args.rtx_retry_period.is_some()) {
+            return Err(Error::Config(String::from(
+                "--rtx-max-retries and --rtx-retry-period have no effect with --no-nack",
+            )));
+        }
+
+        let mut optional_elements = Vec::new();
+        if args.agc {
+            optional_elements.push("webrtcdsp");
+        }
+        if args.gate_threshold.is_some() || args.limiter_ceiling.is_some() {
+            optional_elements.push("audiodynamic");
+        }
+        if !args.channel_map.is_empty() || !args.bed.is_empty() {
+            optional_elements.push("audiomixmatrix");
+        }
+        if !args.pan.is_empty() {
+            optional_elements.push("audiopanorama");
+        }
+        if args.sip_codecs {
+            optional_elements.extend(["mulawdec", "alawdec", "avdec_g722"]);
+        }
+        let missing: Vec<String> = optional_elements
+            .into_iter()
+            .filter(|name| ElementFactory::find(name).is_none())
+            .map(String::from)
+            .collect();
+        if !missing.is_empty() {
+            return Err(Error::MissingElements(missing));
+        }
+
+        match &output {
+            Output::Srt => info!("SRT output at {output_url}"),
+            Output::Sdi { device } => info!("SDI output on decklink device {device}"),
+            Output::Icecast(icecast) => info!(
+                "Icecast output at {}:{}{}",
+                icecast.host, icecast.port, icecast.mount
+            ),
+            Output::Cmaf(CmafDestination::Directory(directory)) => {
+                info!("CMAF output in {}", directory.display())
+            }
+            Output::Cmaf(CmafDestination::Http(url)) => info!("CMAF output to {url}"),
+        }
+        if passthrough {
+            info!("audio passthrough, the WHEP Opus goes to the muxer without re-encoding");
+        }
+        if args.disable_audio {
+            info!("audio disabled, the transport stream carries video only");
+        }
+        info!("---");
+
+        let credentials = args
+            .oauth_token_url
+            .as_ref()
+            .map(|token_url| ClientCredentials {
+                token_url: token_url.clone(),
+                client_id: args.oauth_client_id.clone().unwrap_or_default(),
+                client_secret: args.oauth_client_secret.clone().unwrap_or_default(),
+                scope: args.oauth_scope.clone(),
+            });
+        let oauth_token = match &credentials {
+            Some(credentials) => Some(credentials.fetch().map_err(|err| Error::Element {
+                element: None,
+                code: String::from("OAuth"),
+                category: Category::Auth,
+                message: format!("could not get an oauth token: {err}"),
+            })?),
+            None => None,
+        };
+
+        let mixer = "liveadder name=mixer"; //this could be audiomixer also, but liveadder will do fine here
+
+        let settings = args.profile.map(|p| p.settings()).unwrap_or_default();
+
+        // the source video goes into the transport stream unless a generated track takes its place
+        let mux_video = matches!(output, Output::Srt | Output::Cmaf(_))
+            && args.generate_video.is_none()
+            && args.media != Media::Audio;
+
+        let presentation = match &output {
+            Output::Cmaf(destination) => Some(cmaf::Presentation::new(
+                destination.clone(),
+                args.cmaf_playlist_length as usize,
+            )?),
+            _ => None,
+        };
+
+        let limiter = limiter(&args);
+        let mut output = match output {
+            Output::Srt => srt_output(&args, &settings, passthrough),
+            Output::Sdi { device } => sdi_output(&args, device),
+            Output::Icecast(icecast) => icecast_output(&args, &settings, &icecast),
+            Output::Cmaf(_) => cmaf_output(&args, &settings, mux_video),
+        };
+        if let Some(path) = &args.record_path
+            && args.record_format == RecordFormat::MkvSource
+        {
+            // the WHEP tracks are linked to the muxer as they come, in `SourceRecording`
+            output.push_str(&format!(
+                " matroskamux name=record_mux ! filesink name=record location=\"{path}\""
+            ));
+        }
+
+        let template = match &args.pipeline_template {
+            Some(path) => pipeline_template(path)?,
+            None if args.disable_audio => String::from(VIDEO_ONLY_TEMPLATE),
+            None if passthrough => String::from(PASSTHROUGH_TEMPLATE),
+            None => String::from(DEFAULT_TEMPLATE),
+        };
+
+        // the mix is processed ahead of the encoder, there is none without audio
+        let processing = if args.disable_audio {
+            String::new()
+        } else {
+            format!(
+                "{}{}{}{limiter}",
+                audio_dump(args.dump_audio.as_deref()),
+                captions_tap(args.captions.is_some()),
+                filter(args.audio_filter.as_deref(), "audioconvert")
+            )
+        };
+        let pipeline_str = template
+            .replace("{input}", input)
+            .replace("{caps}", &mix_caps(args.channels, &args.bed))
+            .replace(
+                "{mixer}",
+                &format!("{mixer} latency={}", settings.mixer_latency_ms),
+            )
+            .replace(
+                "{volume}",
+                &format!("volume name=master_volume volume={}", args.volume),
+            )
+            .replace("{output}", &format!("{processing}{output}"));
+
+        let mut context = gst::ParseContext::new();
+        let pipeline = match gst::parse::launch_full(
+            &pipeline_str,
+            Some(&mut context),
+            gst::ParseFlags::empty(),
+        ) {
+            Ok(pipeline) => pipeline,
+            Err(err) => {
+                if let Some(gst::ParseError::NoSuchElement) = err.kind::<gst::ParseError>() {
+                    return Err(Error::MissingElements(context.missing_elements()));
+                }
+
+                return Err(Error::Pipeline(format!("Failed to parse pipeline: {err}")));
+            }
+        };
+
+        let pipeline = pipeline
+            .dynamic_cast::<gst::Pipeline>()
+            .expect("could not cast pipeline");
+
+        let reference_clock = match (&args.ntp_server, args.ptp_domain) {
+            (Some(server), _) => Some(ReferenceClock::Ntp(server.clone())),
+            (None, Some(domain)) => Some(ReferenceClock::Ptp(domain)),
+            (None, None) => None,
+        };
+        if let Some(reference) = &reference_clock {
+            clock::slave(
+                &pipeline,
+                reference,
+                Duration::from_secs(args.clock_sync_timeout),
+            )?;
+        }
+
+        let output_paused = Arc::new(AtomicBool::new(false));
+        let srt_callers = Arc::new(SrtCallers::default());
+
+        // only there with srt output
+        if let Some(output_srt_sink) = pipeline.by_name("output") {
+            connect_srt_callers(&output_srt_sink, &notifier, &srt_callers, args.max_callers);
+            connect_output_pause(&output_srt_sink, &output_paused);
+        }
+        connect_webrtcbin(
+            &pipeline,
+            &notifier,
+            settings.jitter_buffer_ms,
+            Retransmission {
+                max_retries: args.rtx_max_retries,
+                retry_period_ms: args.rtx_retry_period,
+            },
+            Feedback {
+                twcc: args.twcc,
+                max_bitrate: args.max_receive_bitrate,
+                fec: args.fec,
+            },
+            MediaOffer {
+                // the tracks of a bed are mono, surround Opus is not asked for
+                channels: if args.bed.is_empty() {
+                    args.channels
+                } else {
+                    2
+                },
+                sip_codecs: args.sip_codecs,
+                video_rid: args.video_layer.map(|layer| args.video_layer_rid(layer)),
+            },
+            SdpDebug {
+                log: args.log_sdp,
+                save_dir: args.save_sdp.clone(),
+            },
+        );
+        if let Some(presentation) = &presentation {
+            connect_cmaf(
+                &pipeline,
+                presentation,
+                audio_bitrate(&args, &settings),
+                SOURCE_VIDEO_BITRATE * 1000,
+            )?;
+        }
+        connect_recording(&pipeline);
+        connect_opus_decoders(&pipeline, !args.no_opus_fec, !args.no_opus_plc);
+        connect_ts_descriptors(&pipeline, &args.ts_descriptors);
+        let captions = PendingPes::default();
+        connect_teletext(&pipeline, &captions);
+        if let Some(url) = &args.captions {
+            let transcriber = Transcriber::new(
+                url.clone(),
+                args.captions_model.clone(),
+                args.captions_token.clone(),
+            );
+            connect_captions(&pipeline, transcriber, captions);
+        }
+        let input = Input {
+            description: input.to_string(),
+            on_eos: args.on_input_eos,
+            resample_quality: args.resample_quality,
+            drift_correction: !args.no_drift_correction,
+            dtx_fill: args.dtx_fill,
+            crossfade: Duration::from_millis(args.crossfade),
+            agc: args.agc,
+            gate_threshold_db: args.gate_threshold,
+            channel_map: args.channel_map.clone(),
+            bed: args.bed.clone(),
+            track_gain: args.track_gain.clone(),
+            pan: args.pan.clone(),
+            passthrough,
+            language: args.audio_lang.clone(),
+            mux_video,
+            mosaic: args.mosaic.then(|| raw_video(&args).size),
+            video_passthrough: video_passthrough(&args),
+            video_transcode: format!(
+                "{video_filter}{encoder} ! {parser}",
+                video_filter = filter(args.video_filter.as_deref(), "videoconvert"),
+                encoder = video_encoder(&args, &settings, SOURCE_VIDEO_BITRATE),
+                parser = args.video_codec.parser(),
+            ),
+            startup,
+            auth_token: Arc::new(Mutex::new(
+                oauth_token
+                    .as_ref()
+                    .map(|token| token.access_token.clone())
+                    .or_else(|| args.auth_token.clone()),
+            )),
+            whep: args.ice_restart.then(|| WhepClient::new(&args)),
+            span: tracing::Span::current(),
+            recording: match args.record_format {
+                RecordFormat::MkvSource => pipeline.by_name("record_mux").map(SourceRecording::new),
+                _ => None,
+            },
+            ..Default::default()
+        };
+        if let Some(element) = pipeline.by_name("input") {
+            input.authenticate(&element);
+        }
+        if let (Some(credentials), Some(token)) = (credentials, oauth_token) {
+            let pipeline = pipeline.downgrade();
+            let auth_token = input.auth_token.clone();
+            oauth::spawn_refresher(credentials, token, move |token| {
+                let Some(pipeline) = pipeline.upgrade() else {
+                    return false;
+                };
+                if let Some(token) = token {
+                    *auth_token.lock().unwrap() = Some(token.to_string());
+                    // the running session ends with a DELETE that needs a valid token too
+                    if let Some(element) = pipeline.by_name("input") {
+                        set_auth_token(&element, token);
+                    }
+                }
+                true
+            });
+        }
+        connect_connection_lost(&pipeline, &input);
+        if input.whep.is_some() {
+            // the webrtcbin the pipeline was created with came before the handlers for it, a new one has them
+            input.remove(&pipeline);
+            input.subscribe(&pipeline)?;
+        } else {
+            connect_input(&pipeline, &input)?;
+        }
+        if let Some(output_srt_sink) = pipeline.by_name("output") {
+            // after the pause, so nothing is marked in what it drops
+            connect_discontinuities(&output_srt_sink, &input.discontinuity);
+            connect_first_ts(&output_srt_sink, &startup);
+        }
+
+        let streaming_threads = threads::watch(
+            &pipeline,
+            ThreadOptions {
+                nice: args.thread_nice,
+                output_nice: args.output_thread_nice,
+                output_rt_priority: args.output_thread_rt_priority,
+            },
+        );
+
+        let queues = queues::watch(&pipeline);
+
+        let peaks = Arc::new(Mutex::new(Vec::new()));
+        if let Some(pad) = pipeline
+            .by_name("master_volume")
+            .and_then(|volume| volume.static_pad("src"))
+        {
+            connect_peak_meter(&pad, &peaks);
+        }
+
+        let media = MediaClock::default();
+        connect_media_clock(&pipeline, &media);
+
+        Ok(Self {
+            args,
+            pipeline,
+            notifier,
+            status,
+            streaming_threads,
+            queues,
+            input,
+            output_paused,
+            srt_callers,
+            peaks,
+            media,
+        })
+    }
+
+    pub fn pipeline(&self) -> &gst::Pipeline {
+        &self.pipeline
+    }
+
+    pub fn status(&self) -> Status {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Liveness and readiness, each state with the time it last changed
+    pub fn health(&self) -> Health {
+        let status = self.status();
+        let media = self.media.check();
+
+        let srt = self.pipeline.by_name("output").map(|sink| {
+            let (_, current, _) = sink.state(gst::ClockTime::ZERO);
+            if current != gst::State::Playing {
+                (SrtState::Down, None)
+            } else if !self.output_url().contains("mode=listener") {
+                // srtsink fails when it loses its peer, so a caller sending data is connected
+                let state = if media.ok {
+                    SrtState::Connected
+                } else {
+                    SrtState::Down
+                };
+                (state, media.since)
+            } else if status.srt_callers > 0 {
+                (SrtState::Connected, status.srt_callers_since)
+            } else {
+                (
+                    SrtState::Listening,
+                    status.srt_callers_since.or(status.running_since),
+                )
+            }
+        });
+
+        Health {
+            process: Check {
+                ok: status.running,
+                since: status.running_since,
+            },
+            whep: Check {
+                ok: status.whep_connected,
+                since: status.whep_since,
+            },
+            media,
+            srt,
+            suspended: status.suspended,
+            paused: status.paused.is_some(),
+        }
+    }
+
+    /// The pipeline as it is now, with every element, pad and caps, as a Graphviz .dot document
+    pub fn graph(&self) -> String {
+        self.pipeline
+            .debug_to_dot_data(DebugGraphDetails::ALL)
+            .to_string()
+    }
+
+    /// Time to the first milestones of the stream, since the gateway was created
+    pub fn startup(&self) -> &Startup {
+        &self.input.startup
+    }
+
+    /// Number of streaming threads currently running in this gateway's pipeline
+    pub fn streaming_threads(&self) -> usize {
+        self.streaming_threads.load(Ordering::Relaxed)
+    }
+
+    /// Overrun/underrun counts of the pipeline's queues
+    pub fn queues(&self) -> &QueueMonitor {
+        &self.queues
+    }
+
+    /// Clock drift, skew and resampler correction of each decoded audio track
+    pub fn drift(&self) -> &DriftMonitor {
+        &self.input.drift
+    }
+
+    /// Current master volume, 1.0 is unity gain
+    pub fn volume(&self) -> f64 {
+        self.master_volume()
+            .map_or(1.0, |volume| volume.property::<f64>("volume"))
+    }
+
+    /// Changes the master volume of the mix while running, 0.0 to 10.0
+    pub fn set_volume(&self, volume: f64) -> Result<(), Error> {
+        if !(0.0..=10.0).contains(&volume) {
+            return Err(Error::Config(format!(
+                "volume {volume} is out of range 0.0-10.0"
+            )));
+        }
+
+        let master_volume = self.master_volume().ok_or_else(|| {
+            Error::Config(String::from(
+                "there is no master volume with --disable-audio or audio passthrough, see --force-transcode",
+            ))
+        })?;
+        master_volume.set_property("volume", volume);
+        info!("master volume set to {volume}");
+        Ok(())
+    }
+
+    /// Peak level of each channel of the mix in dBFS since the previous call, empty with audio passthrough or
+    /// before any audio
+    pub fn audio_peaks(&self) -> Vec<f64> {
+        let mut peaks = self.peaks.lock().unwrap();
+        let levels = peaks
+            .iter()
+            .map(|&peak| 20.0 * (peak.max(1e-6) as f64).log10())
+            .collect();
+        peaks.iter_mut().for_each(|peak| *peak = 0.0);
+        levels
+    }
+
+    /// None with audio passthrough, where there is no mix
+    fn master_volume(&self) -> Option<gst::Element> {
+        self.pipeline.by_name("master_volume")
+    }
+
+    /// Bitrate in bit/s of the audio encoder, None with audio passthrough or outputs without an `audio_encoder`
+    pub fn audio_bitrate(&self) -> Option<u32> {
+        encoder_bitrate(&self.pipeline.by_name("audio_encoder")?, 1)
+    }
+
+    /// Bitrate in bit/s of the video encoder, None when the video is passed through or there is none
+    pub fn video_bitrate(&self) -> Option<u32> {
+        encoder_bitrate(&self.pipeline.by_name("video_encoder")?, 1000)
+    }
+
+    /// Changes the bitrate of the audio encoder while running, where the encoder allows it, like opusenc
+    pub fn set_audio_bitrate(&self, bitrate: u32) -> Result<(), Error> {
+        let encoder = self.pipeline.by_name("audio_encoder").ok_or_else(|| {
+            Error::Config(String::from(
+                "there is no audio encoder with --disable-audio or audio passthrough, see --force-transcode",
+            ))
+        })?;
+        set_encoder_bitrate(&encoder, 1, bitrate)?;
+        info!("audio bitrate set to {bitrate} bit/s");
+        Ok(())
+    }
+
+    /// Changes the bitrate of the video encoder while running, where the encoder allows it, like x264enc
+    pub fn set_video_bitrate(&self, bitrate: u32) -> Result<(), Error> {
+        let encoder = self.pipeline.by_name("video_encoder").ok_or_else(|| {
+            Error::Config(String::from(
+                "there is no video encoder, with source video passthrough see --force-transcode",
+            ))
+        })?;
+        set_encoder_bitrate(&encoder, 1000, bitrate)?;
+        info!("video bitrate set to {bitrate} bit/s");
+        Ok(())
+    }
+
+    /// Where the output currently goes, after any `set_output_url`
+    pub fn output_url(&self) -> String {
+        self.pipeline
+            .by_name("output")
+            .and_then(|sink| sink.property::<Option<String>>("uri"))
+            .unwrap_or_else(|| self.args.output_url.clone())
+    }
+
+    /// Switches the SRT output to another destination while running, the WHEP session is not touched.
+    /// The data flow into the sink is blocked, a new sink for `output_url` replaces the old one and the flow resumes.
+    pub fn set_output_url(&self, output_url: &str) -> Result<(), Error> {
+        if Output::parse(output_url)? != Output::Srt {
+            return Err(Error::Config(String::from(
+                "the output can only be switched to another srt:// url",
+            )));
+        }
+        let old_sink = self
+            .pipeline
+            .by_name("output")
+            .ok_or_else(|| Error::Config(String::from("only an SRT output can be switched")))?;
+        let src_pad = old_sink
+            .static_pad("sink")
+            .and_then(|pad| pad.peer())
+            .ok_or_else(|| Error::Pipeline(String::from("the SRT output is not linked")))?;
+
+        // the new sink is configured like the old one, except for the destination
+        let new_sink = ElementFactory::make("srtsink")
+            .name("output")
+            .property("latency", old_sink.property::<i32>("latency"))
+            .property("sync", old_sink.property::<bool>("sync"))
+            .property(
+                "wait-for-connection",
+                old_sink.property::<bool>("wait-for-connection"),
+            )
+            .property("uri", output_url)
+            .build()
+            .map_err(|err| Error::Pipeline(format!("could not create srtsink: {err}")))?;
+        connect_srt_callers(
+            &new_sink,
+            &self.notifier,
+            &self.srt_callers,
+            self.args.max_callers,
+        );
+
+        let (done, switched) = std::sync::mpsc::channel();
+        let pipeline = self.pipeline.clone();
+        src_pad.add_probe(PadProbeType::IDLE, move |pad, _| {
+            // the old sink leaves first, the new one takes over its name
+            let _ = pad.unlink(&old_sink.static_pad("sink").unwrap());
+            let _ = old_sink.set_state(gst::State::Null);
+            let _ = pipeline.remove(&old_sink);
+
+            let result = pipeline
+                .add(&new_sink)
+                .map_err(|err| err.to_string())
+                .and_then(|_| {
+                    new_sink
+                        .sync_state_with_parent()
+                        .map_err(|err| err.to_string())
+                })
+                .and_then(|_| {
+                    pad.link(&new_sink.static_pad("sink").unwrap())
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                });
+            let _ = done.send(result);
+
+            PadProbeReturn::Remove
+        });
+
+        match switched.recv_timeout(Duration::from_secs(5)) {
+            Ok(Ok(())) => {
+                info!("output switched to {output_url}");
+                Ok(())
+            }
+            Ok(Err(err)) => Err(Error::Pipeline(format!("could not switch output: {err}"))),
+            Err(_) => Err(Error::Pipeline(String::from(
+                "timed out switching the output",
+            ))),
+        }
+    }
+
+    /// Pauses the output while the WHEP session and the SRT socket stay up. Without `slate` no transport stream
+    /// is sent at all, which needs an SRT output. With `slate` the input is dropped before the mix, so the output
+    /// carries silence, and any generated video, instead.
+    pub fn pause(&self, slate: bool) -> Result<(), Error> {
+        if !slate && self.pipeline.by_name("output").is_none() {
+            return Err(Error::Config(String::from(
+                "only an SRT output can be paused without a slate",
+            )));
+        }
+
+        self.output_paused.store(!slate, Ordering::Relaxed);
+        self.input.slate.store(slate, Ordering::Relaxed);
+
+        info!(
+            "output paused{}",
+            if slate { ", sending a slate" } else { "" }
+        );
+        self.notifier.notify(Event::Paused { slate });
+        Ok(())
+    }
+
+    /// Resumes a paused output. Returns false if it was not paused.
+    pub fn resume(&self) -> bool {
+        // the transport stream left out while paused is marked before the first packet gets through
+        if self.output_paused.load(Ordering::Relaxed) {
+            self.input.discontinuity.store(true, Ordering::Relaxed);
+        }
+        let output_paused = self.output_paused.swap(false, Ordering::Relaxed);
+        let slate = self.input.slate.swap(false, Ordering::Relaxed);
+        if !output_paused && !slate {
+            return false;
+        }
+
+        // decoders and receivers need a keyframe to pick the video up again
+        if let Some(sink) = self.pipeline.by_name("output") {
+            request_keyframe(&sink);
+        }
+
+        info!("output resumed");
+        self.notifier.notify(Event::Resumed);
+        true
+    }
+
+    /// Asks a running `run` loop to shut down. Safe to call from any thread, e.g. a ctrl-c handler.
+    pub fn stop(&self) {
+        post_stop(&self.pipeline);
+    }
+
+    /// Plays the pipeline and blocks until it ends, fails or `stop` is called
+    pub fn run(&self) -> Result<(), Error> {
+        let pipeline = &self.pipeline;
+        let notifier = &self.notifier;
+        let dot_debug = self.args.dot_debug;
+
+        let bus = pipeline.bus().unwrap();
+
+        if self.args.lazy_connect {
+            // subscribed when the first caller connects, and kept from reconnecting until then
+            self.input.reconnecting.store(true, Ordering::SeqCst);
+            self.input.remove(pipeline);
+        }
+
+        // Start pipeline - ICE role is configured via webrtcbin-ready signal
+        pipeline.set_state(gst::State::Playing).map_err(|err| {
+            Error::Pipeline(format!(
+                "Unable to set the pipeline to the `Playing` state: {err}"
+            ))
+        })?;
+
+        notifier.notify(Event::Start);
+        if self.args.lazy_connect {
+            info!("waiting for an srt caller to subscribe to the WHEP endpoint");
+            notifier.notify(Event::InputSuspended);
+        }
+
+        if let Some(seconds) = self.args.duration {
+            info!("stream will end after {seconds}s");
+            self.input
+                .end_stream(pipeline, Duration::from_secs(seconds));
+        }
+
+        let thresholds = alarms::Thresholds {
+            srt_retransmit_pct: self.args.alarm_srt_retransmit_pct,
+            webrtc_loss_pct: self.args.alarm_webrtc_loss_pct,
+            rtt_ms: self.args.alarm_rtt_ms,
+        };
+
+        if !thresholds.is_empty() {
+            let mut alarms = alarms::Alarms::new(thresholds);
+            let notifier = notifier.clone();
+            stats::spawn_poller(
+                pipeline,
+                Duration::from_secs(self.args.stats_interval),
+                move |snapshot| {
+                    for change in alarms.evaluate(snapshot) {
+                        notifier.notify(Event::Alarm(change));
+                    }
+                },
+            );
+        }
+
+        if self.args.reconnect_on_network_change {
+            let input = self.input.clone();
+            let session = pipeline.downgrade();
+            let pipeline = pipeline.downgrade();
+            network::spawn_watcher(
+                NETWORK_CHECK_INTERVAL,
+                move || stats::webrtc_local_address(&session.upgrade()?),
+                move |lost| {
+                    let Some(pipeline) = pipeline.upgrade() else {
+                        return false;
+                    };
+                    // the connection can take a while to fail on a path that is gone, so it is not waited for
+                    if lost {
+                        let _ = input.on_eos(&pipeline);
+                    }
+                    true
+                },
+            );
+        }
+
+        if let Some(seconds) = self.args.startup_timeout
+            && self.args.test_source.is_none()
+            && !self.args.lazy_connect
+        {
+            self.input
+                .watch_startup(pipeline, Duration::from_secs(seconds));
+        }
+
+        if self.args.idle_timeout.is_some() || self.args.lazy_connect {
+            // without a timeout, a lazy input is left as soon as the last caller has gone
+            let timeout = Duration::from_secs(self.args.idle_timeout.unwrap_or(0));
+            self.watch_idle(timeout, self.args.lazy_connect);
+        }
+
+        if let Some(seconds) = self.args.stall_timeout {
+            let output_paused = self.output_paused.clone();
+            let callers = self.srt_callers.clone();
+            let wait_for_connection = self.args.wait_for_connection;
+            spawn_supervisor(pipeline, Duration::from_secs(seconds), move || {
+                // no data is expected while paused, or while srtsink blocks until the first caller
+                output_paused.load(Ordering::Relaxed) || (wait_for_connection && callers.get() == 0)
+            });
+        }
+
+        let mut result = Ok(());
+        let mut ended = false;
+
+        for msg in bus.iter_timed(gst::ClockTime::NONE) {
+            use gst::MessageView;
+
+            match msg.view() {
+                MessageView::StateChanged(state) => {
+                    if !state
+                        .src()
+                        .unwrap()
+                        .type_()
+                        .to_string()
+                        .contains("GstPipeline")
+                    {
+                        continue;
+                    }
+
+                    tracing::debug!(
+                        "pipeline change: {:?} -> {:?}",
+                        state.old(),
+                        state.current()
+                    );
+
+                    if dot_debug {
+                        let pipe_bin = pipeline.dynamic_cast_ref::<gst::Bin>().unwrap();
+                        debug_pipeline(pipe_bin, &format!("{:?}", state.current()));
+                    }
+                }
+                MessageView::Eos(..) => {
+                    ended = true;
+                    break;
+                }
+                MessageView::Error(err) => {
+                    error!(
+                        "Error from {:?}: {} ({:?})",
+                        err.src().map(|s| s.path_string()),
+                        err.error(),
+                        err.debug()
+                    );
+
+                    notifier.notify(Event::Error {
+                        source: err.src().map(|s| s.path_string().to_string()),
+                        message: err.error().to_string(),
+                    });
+
+                    if dot_debug {
+                        let pipe_bin = pipeline.dynamic_cast_ref::<gst::Bin>().unwrap();
+                        debug_pipeline(pipe_bin, "error");
+                    }
+
+                    if err
+                        .details()
+                        .is_some_and(|details| details.has_name(BRANCH_ERROR))
+                    {
+                        // a new session builds every branch again
+                        if self.args.on_input_eos == OnInputEos::Reconnect {
+                            warn!("subscribing again to rebuild the track");
+                            let _ = self.input.on_eos(pipeline);
+                            continue;
+                        }
+                        result = Err(Error::Branch(err.error().to_string()));
+                    } else {
+                        result = Err(element_error(err));
+                    }
+                    break;
+                }
+                MessageView::Application(app)
+                    if app.structure().is_some_and(|s| s.has_name(STOP_MESSAGE)) =>
+                {
+                    info!("exit.. shutting down");
+                    break;
+                }
+                MessageView::Application(app)
+                    if app.structure().is_some_and(|s| s.has_name(STALL_MESSAGE)) =>
+                {
+                    let reason = app
+                        .structure()
+                        .and_then(|s| s.get::<String>("reason").ok())
+                        .unwrap_or_default();
+                    error!("pipeline stalled: {reason}");
+
+                    notifier.notify(Event::Error {
+                        source: None,
+                        message: format!("pipeline stalled: {reason}"),
+                    });
+
+                    result = Err(Error::Stalled(reason));
+                    break;
+                }
+                _ => (),
+            }
+        }
+
+        // after an EOS the files are already complete
+        if !ended {
+            finish_files(pipeline, FILE_FINISH_TIMEOUT);
+        }
+
+        notifier.notify_and_wait(Event::Shutdown, Duration::from_secs(2));
+        if let Some(leaving) = self.input.leave() {
+            let _ = leaving.join();
+        }
+
+        pipeline
+            .set_state(gst::State::Null)
+            .expect("Unable to set the pipeline to the `Null` state");
+
+        std::thread::sleep(std::time::Duration::from_secs(1));
+
+        result
+    }
+
+    /// Acts on --on-idle once no SRT caller has been connected for `timeout`, counted from when the last one left.
+    /// A suspended input subscribes again as soon as a caller connects. With --lazy-connect the input starts out
+    /// `suspended` and is always suspended again.
+    fn watch_idle(&self, timeout: Duration, mut suspended: bool) {
+        let pipeline = self.pipeline.downgrade();
+        let input = self.input.clone();
+        let callers = self.srt_callers.clone();
+        let notifier = self.notifier.clone();
+        let on_idle = if self.args.lazy_connect {
+            OnIdle::Suspend
+        } else {
+            self.args.on_idle
+        };
+
+        std::thread::spawn(move || {
+            let mut had_callers = false;
+            let mut idle_since = None;
+            let mut count = 0;
+            loop {
+                count = callers.wait_for_change(count, IDLE_CHECK_INTERVAL);
+                let Some(pipeline) = pipeline.upgrade() else {
+                    return;
+                };
+                if input.ending.load(Ordering::SeqCst)
+                    || pipeline.current_state() == gst::State::Null
+                {
+                    return;
+                }
+
+                if count > 0 {
+                    had_callers = true;
+                    idle_since = None;
+                    if suspended {
+                        info!("srt caller connected, subscribing again");
+                        if let Err(err) = input.subscribe(&pipeline) {
+                            error!("{err}");
+                            post_stop(&pipeline);
+                            return;
+                        }
+                        suspended = false;
+                        input.reconnecting.store(false, Ordering::SeqCst);
+                        notifier.notify(Event::InputResumed);
+                    }
+                    continue;
+                }
+                if !had_callers || suspended {
+                    continue;
+                }
+
+                let since = *idle_since.get_or_insert_with(Instant::now);
+                if since.elapsed() < timeout {
+                    continue;
+                }
+                match on_idle {
+                    OnIdle::Exit => {
+                        info!("no srt caller for {}s, stopping", timeout.as_secs());
+                        input.end_stream(&pipeline, Duration::ZERO);
+                        return;
+                    }
+                    // not in the middle of --on-input-eos reconnect, and kept from starting one while suspended
+                    OnIdle::Suspend if !input.reconnecting.swap(true, Ordering::SeqCst) => {
+                        info!(
+                            "no srt caller for {}s, leaving the WHEP session",
+                            timeout.as_secs()
+                        );
+                        input.remove(&pipeline);
+                        suspended = true;
+                        notifier.notify(Event::InputSuspended);
+                    }
+                    OnIdle::Suspend => (),
+                }
+            }
+        });
+    }
+}
+
+fn test_source_input(test_source: TestSource, channels: u32, bed: &[BedPosition]) -> String {
+    // named input like the whep source, it has static pads so the pad-added handling never runs
+    format!(
+        "audiotestsrc name=input wave={} is-live=true ! {} ! mixer.",
+        test_source.wave(),
+        mix_caps(channels, bed)
+    )
+}
+
+/// Raw audio format of the mix. More than two channels get the default positions, e.g. 5.1 for 6, unless a --bed
+/// sets them.
+fn mix_caps(channels: u32, bed: &[BedPosition]) -> String {
+    let mut caps = format!("audio/x-raw,format=F32LE,rate=48000,channels={channels}");
+    if !bed.is_empty() {
+        caps.push_str(&format!(",channel-mask=(bitmask)0x{:x}", bed_mask(bed)));
+    } else if channels > 2 {
+        let mask = gst_audio::AudioChannelPosition::fallback_mask(channels);
+        caps.push_str(&format!(",channel-mask=(bitmask)0x{mask:x}"));
+    }
+    caps
+}
+
+/// Channel mask of the positions of a --bed
+fn bed_mask(bed: &[BedPosition]) -> u64 {
+    use gst_audio::AudioChannelPosition as Position;
+
+    let positions: Vec<Position> = bed
+        .iter()
+        .map(|position| match position {
+            BedPosition::Fl => Position::FrontLeft,
+            BedPosition::Fr => Position::FrontRight,
+            BedPosition::Fc => Position::FrontCenter,
+            BedPosition::Lfe => Position::Lfe1,
+            BedPosition::Rl => Position::RearLeft,
+            BedPosition::Rr => Position::RearRight,
+            BedPosition::Rc => Position::RearCenter,
+            BedPosition::Sl => Position::SideLeft,
+            BedPosition::Sr => Position::SideRight,
+            BedPosition::Tfl => Position::TopFrontLeft,
+            BedPosition::Tfr => Position::TopFrontRight,
+        })
+        .collect();
+    Position::positions_to_mask(&positions, false).unwrap_or_default()
+}
+
+/// Depayloader and decoder of the audio encodings of SIP gateways. G.722 is decoded at 16 kHz whatever its RTP
+/// clock rate says, audio_leg resamples every track to the mix rate.
+fn sip_decoder(encoding: &str) -> Option<&'static str> {
+    match encoding.to_ascii_uppercase().as_str() {
+        "PCMU" => Some("rtppcmudepay ! mulawdec"),
+        "PCMA" => Some("rtppcmadepay ! alawdec"),
+        "G722" => Some("rtpg722depay ! avdec_g722"),
+        _ => None,
+    }
+}
+
+/// The media stream id of a WHEP track, from its pad or the webrtcbin pad behind the ghost pads
+fn track_msid(pad: &gst::Pad) -> Option<String> {
+    let mut pad = pad.clone();
+    loop {
+        if pad.find_property("msid").is_some()
+            && let Some(msid) = pad.property::<Option<String>>("msid")
+        {
+            return Some(msid);
+        }
+        pad = pad.downcast_ref::<GhostPad>()?.target()?;
+    }
+}
+
+/// Keeps the highest absolute sample of each channel of the F32LE mix passing `pad` in `peaks`
+fn connect_peak_meter(pad: &gst::Pad, peaks: &Arc<Mutex<Vec<f32>>>) {
+    let peaks = peaks.clone();
+    pad.add_probe(PadProbeType::BUFFER, move |pad, probe_info| {
+        let (Some(PadProbeData::Buffer(buffer)), Some(info)) = (
+            &probe_info.data,
+            pad.current_caps()
+                .and_then(|caps| gst_audio::AudioInfo::from_caps(&caps).ok()),
+        ) else {
+            return PadProbeReturn::Ok;
+        };
+        let Ok(map) = buffer.map_readable() else {
+            return PadProbeReturn::Ok;
+        };
+
+        let channels = info.channels() as usize;
+        let mut peaks = peaks.lock().unwrap();
+        peaks.resize(channels, 0.0);
+        for frame in map.chunks_exact(info.bpf() as usize) {
+            for (peak, sample) in peaks.iter_mut().zip(frame.chunks_exact(4)) {
+                let value = f32::from_le_bytes(sample.try_into().unwrap()).abs();
+                *peak = peak.max(value);
+            }
+        }
+
+        PadProbeReturn::Ok
+    });
+}
+
+/// Watches that the pipeline reaches PLAYING and that data keeps reaching its sinks, unless `idle` says none is
+/// expected. After `timeout` without either, the diagnostics are logged and the `run` loop is ended as stalled.
+fn spawn_supervisor<F>(pipeline: &gst::Pipeline, timeout: Duration, idle: F)
+where
+    F: Fn() -> bool + Send + 'static,
+{
+    // counted on the pads feeding the sinks, which stay when the SRT sink is switched
+    let progress = Arc::new(AtomicU64::new(0));
+    for sink in pipeline.iterate_sinks().into_iter().flatten() {
+        let Some(pad) = sink.static_pad("sink").and_then(|pad| pad.peer()) else {
+            continue;
+        };
+        let progress = progress.clone();
+        pad.add_probe(
+            PadProbeType::BUFFER | PadProbeType::BUFFER_LIST,
+            move |_, _| {
+                progress.fetch_add(1, Ordering::Relaxed);
+                PadProbeReturn::Ok
+            },
+        );
+    }
+
+    let pipeline = pipeline.downgrade();
+    std::thread::Builder::new()
+        .name("supervisor".into())
+        .spawn(move || {
+            let mut last_progress = 0;
+            let mut healthy_at = std::time::Instant::now();
+
+            loop {
+                std::thread::sleep(SUPERVISOR_INTERVAL);
+                let Some(pipeline) = pipeline.upgrade() else {
+                    return;
+                };
+
+                let (_, current, pending) = pipeline.state(gst::ClockTime::ZERO);
+                // `run` has ended
+                if current == gst::State::Null {
+                    return;
+                }
+                let playing = current == gst::State::Playing && pending == gst::State::VoidPending;
+                let progress = progress.load(Ordering::Relaxed);
+                let advancing = progress != last_progress || idle();
+                last_progress = progress;
+
+                if playing && advancing {
+                    healthy_at = std::time::Instant::now();
+                    continue;
+                }
+                if healthy_at.elapsed() < timeout {
+                    continue;
+                }
+
+                let reason = if playing {
+                    format!("no data reached the output for {}s", timeout.as_secs())
+                } else {
+                    format!(
+                        "stuck in {current:?} (pending {pending:?}) for {}s",
+                        timeout.as_secs()
+                    )
+                };
+                log_stall(&pipeline);
+
+                let msg = gst::message::Application::new(
+                    gst::Structure::builder(STALL_MESSAGE)
+                        .field("reason", reason)
+                        .build(),
+                );
+                let _ = pipeline.post_message(msg);
+                return;
+            }
+        })
+        .expect("could not spawn supervisor thread");
+}
+
+/// Logs what a stalled pipeline looks like: elements that are not PLAYING and how full the queues are.
+/// Also writes a .dot graph with --dot-debug.
+fn log_stall(pipeline: &gst::Pipeline) {
+    warn!("pipeline stalled, diagnostics:");
+
+    for elem in pipeline.iterate_recurse().into_iter().flatten() {
+        let (_, current, pending) = elem.state(gst::ClockTime::ZERO);
+        if current != gst::State::Playing || pending != gst::State::VoidPending {
+            warn!(
+                "  {} is {current:?} (pending {pending:?})",
+                elem.path_string()
+            );
+        }
+
+        if elem.type_().name() == "GstQueue" {
+            warn!(
+                "  {} holds {} buffers, {}ms",
+                elem.name(),
+                elem.property::<u32>("current-level-buffers"),
+                elem.property::<u64>("current-level-time") / 1_000_000
+            );
+        }
+    }
+
+    if env::var_os("GST_DEBUG_DUMP_DOT_DIR").is_some() {
+        debug_pipeline(pipeline.upcast_ref(), "stalled");
+    }
+}
+
+/// Ends the branches writing the --record-path and --dump-audio files on their own, so the muxers write their index
+/// and headers before the pipeline is torn down
+fn finish_files(pipeline: &gst::Pipeline, timeout: Duration) {
+    let mut entries = Vec::new();
+    let mut sinks = Vec::new();
+    if let Some(sink) = pipeline.by_name("record") {
+        match pipeline.by_name("record_queue") {
+            Some(queue) => entries.extend(queue.static_pad("sink")),
+            // the mkv-source legs are linked to the muxer directly
+            None => entries.extend(
+                pipeline
+                    .by_name("record_mux")
+                    .map(|mux| mux.sink_pads())
+                    .unwrap_or_default(),
+            ),
+        }
+        sinks.push(sink);
+    }
+    if let (Some(queue), Some(sink)) = (pipeline.by_name("dump_queue"), pipeline.by_name("dump")) {
+        entries.extend(queue.static_pad("sink"));
+        sinks.push(sink);
+    }
+    if sinks.is_empty() {
+        return;
+    }
+
+    let (done, finished) = std::sync::mpsc::channel();
+    for sink in &sinks {
+        let done = done.clone();
+        let name = sink.name();
+        sink.static_pad("sink")
+            .expect("could not get file sink pad")
+            .add_probe(PadProbeType::EVENT_DOWNSTREAM, move |_, info| {
+                if let Some(PadProbeData::Event(event)) = &info.data
+                    && event.type_() == gst::EventType::Eos
+                {
+                    let _ = done.send(name.clone());
+                }
+                PadProbeReturn::Ok
+            });
+    }
+
+    for pad in entries {
+        pad.send_event(gst::event::Eos::new());
+    }
+
+    let deadline = std::time::Instant::now() + timeout;
+    for _ in 0..sinks.len() {
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        match finished.recv_timeout(remaining) {
+            Ok(name) => info!("{name} file finished"),
+            Err(_) => {
+                warn!("timed out finishing the files, they may not be playable");
+                return;
+            }
+        }
+    }
+}
+
+/// Ends the `run` loop of the gateway owning the pipeline
+fn post_stop(pipeline: &gst::Pipeline) {
+    let msg = gst::message::Application::new(gst::Structure::new_empty(STOP_MESSAGE));
+    let _ = pipeline.post_message(msg);
+}
+
+/// Links the streams the --record-path parser finds to the MP4 or MKV muxer. One the container can't carry,
+/// like teletext, is dropped.
+fn connect_recording(pipeline: &gst::Pipeline) {
+    let (Some(parse), Some(mux)) = (
+        pipeline.by_name("record_parse"),
+        pipeline.by_name("record_mux"),
+    ) else {
+        return;
+    };
+
+    parse.connect_pad_added(move |_, pad| {
+        if let Some(mux_pad) = mux.compatible_pad(pad, None)
+            && pad.link(&mux_pad).is_ok()
+        {
+            return;
+        }
+
+        let media = pad
+            .current_caps()
+            .and_then(|caps| Some(caps.structure(0)?.name().to_string()))
+            .unwrap_or_default();
+        warn!("{media} can't be recorded, leaving it out");
+        pad.add_probe(PadProbeType::BUFFER, |_, _| PadProbeReturn::Drop);
+    });
+}
+
+/// `expect` for the code building the branch of a WHEP track while the pipeline runs, where a failure is an
+/// `Error::Branch` for `branch_failed` to report rather than a panic
+trait BranchContext<T> {
+    fn context(self, what: &str) -> Result<T, Error>;
+}
+
+impl<T, E: std::fmt::Display> BranchContext<T> for Result<T, E> {
+    fn context(self, what: &str) -> Result<T, Error> {
+        self.map_err(|err| Error::Branch(format!("{what}: {err}")))
+    }
+}
+
+impl<T> BranchContext<T> for Option<T> {
+    fn context(self, what: &str) -> Result<T, Error> {
+        self.ok_or_else(|| Error::Branch(what.to_string()))
+    }
+}
+
+/// Posts an error for a track whose branch could not be built, for `run` to subscribe again or give up, and
+/// drops what the track carries from then on instead of it failing on unlinked pads
+fn branch_failed(pipeline: &gst::Pipeline, pad: &gst::Pad, err: &Error) {
+    pad.add_probe(PadProbeType::BUFFER, |_, _| PadProbeReturn::Drop);
+
+    let message = gst::message::Error::builder(gst::CoreError::Pad, &err.to_string())
+        .src(pad)
+        .details(
+            gst::Structure::builder(BRANCH_ERROR)
+                .field("pad", pad.name().as_str())
+                .build(),
+        )
+        .build();
+    let _ = pipeline.post_message(message);
+}
+
+/// Classifies an error posted by an element by its GStreamer error domain and code. Elements report HTTP and
+/// connection failures as generic resource errors, so those are told apart by their message.
+fn element_error(err: &gst::message::Error) -> Error {
+    let error = err.error();
+    let text = format!("{error} {:?}", err.debug()).to_lowercase();
+
+    let (code, category) = if let Some(kind) = error.kind::<gst::CoreError>() {
+        let category = match kind {
+            gst::CoreError::MissingPlugin => Category::MissingPlugin,
+            _ => Category::Pipeline,
+        };
+        (format!("CoreError::{kind:?}"), category)
+    } else if let Some(kind) = error.kind::<gst::ResourceError>() {
+        let category = match kind {
+            gst::ResourceError::NotAuthorized => Category::Auth,
+            gst::ResourceError::NotFound
+            | gst::ResourceError::Busy
+            | gst::ResourceError::OpenRead
+            | gst::ResourceError::OpenWrite
+            | gst::ResourceError::OpenReadWrite
+            | gst::ResourceError::Close
+            | gst::ResourceError::Read
+            | gst::ResourceError::Write
+            | gst::ResourceError::Sync => Category::Network,
+            _ => category_from_message(&text),
+        };
+        (format!("ResourceError::{kind:?}"), category)
+    } else if let Some(kind) = error.kind::<gst::StreamError>() {
+        let category = match kind {
+            gst::StreamError::CodecNotFound => Category::MissingPlugin,
+            gst::StreamError::Failed => category_from_message(&text),
+            _ => Category::Media,
+        };
+        (format!("StreamError::{kind:?}"), category)
+    } else if let Some(kind) = error.kind::<gst::LibraryError>() {
+        (format!("LibraryError::{kind:?}"), Category::Pipeline)
+    } else {
+        (
+            error.domain().as_str().to_string(),
+            category_from_message(&text),
+        )
+    };
+
+    Error::Element {
+        element: err.src().map(|s| s.path_string().to_string()),
+        code,
+        category,
+        message: error.to_string(),
+    }
+}
+
+fn category_from_message(text: &str) -> Category {
+    const AUTH: &[&str] = &["401", "403", "unauthorized", "forbidden"];
+    const NETWORK: &[&str] = &[
+        "http",
+        "connection",
+        "refused",
+        "unreachable",
+        "resolve",
+        "timed out",
+        "timeout",
+    ];
+
+    if AUTH.iter().any(|word| text.contains(word)) {
+        Category::Auth
+    } else if NETWORK.iter().any(|word| text.contains(word)) {
+        Category::Network
+    } else {
+        Category::Pipeline
+    }
+}
+
+/// The WHEP source element to subscribe with: whepsrc with --legacy-whepsrc, otherwise whepclientsrc, or the other
+/// one when the preferred one is not available
+fn whep_source(legacy: bool) -> Result<&'static str, Error> {
+    let (preferred, fallback) = if legacy {
+        ("whepsrc", "whepclientsrc")
+    } else {
+        ("whepclientsrc", "whepsrc")
+    };
+
+    for (i, name) in [preferred, fallback].into_iter().enumerate() {
+        let Some(plugin) = ElementFactory::find(name).and_then(|factory| factory.plugin()) else {
+            continue;
+        };
+        if i == 0 {
+            info!(
+                "subscribing with {name} from the {} plugin",
+                plugin.plugin_name()
+            );
+        } else {
+            warn!(
+                "{preferred} is not available, falling back to {name} from the {} plugin",
+                plugin.plugin_name()
+            );
+        }
+        return Ok(name);
+    }
+
+    Err(Error::MissingElements(vec![
+        preferred.to_string(),
+        fallback.to_string(),
+    ]))
+}
+
+/// The elements standing in for `name` when it is missing, in order of preference
+pub(crate) fn substitutes(name: &str) -> &'static [&'static str] {
+    SUBSTITUTES
+        .iter()
+        .find(|(element, _)| *element == name)
+        .map_or(&[][..], |(_, substitutes)| substitutes)
+}
+
+/// The element to use for `name`: itself, or the first of its `SUBSTITUTES` that is available. `name` when none
+/// is, so the pipeline fails on it as before.
+fn substitute(name: &'static str) -> &'static str {
+    if ElementFactory::find(name).is_some() {
+        return name;
+    }
+    match substitutes(name)
+        .iter()
+        .find(|substitute| ElementFactory::find(substitute).is_some())
+    {
+        Some(substitute) => {
+            warn!("{name} is not available, using {substitute} instead");
+            substitute
+        }
+        None => name,
+    }
+}
+
+/// Initializes GStreamer and registers the statically linked plugins, once per process
+pub(crate) fn init() -> Result<(), Error> {
+    static INIT: OnceLock<Result<(), String>> = OnceLock::new();
+
+    INIT.get_or_init(|| {
+        gst::init().map_err(|err| format!("Could not initiate GStreamer: {err}"))?;
+        // either WHEP source will do, so an installed plugin may stand in for one that fails to register
+        if let Err(err) = gstrswebrtc::plugin_register_static() {
+            warn!("Could not register gstrswebrtc plugins: {err}");
+        }
+        if let Err(err) = gstwebrtchttp::plugin_register_static() {
+            warn!("Could not register gstwebrtchttp plugins: {err}");
+        }
+        Ok(())
+    })
+    .clone()
+    .map_err(Error::Pipeline)
+}
+
+/// Number of SRT callers connected right now, waking up whoever waits for it to change
+#[derive(Debug, Default)]
+struct SrtCallers {
+    count: Mutex<usize>,
+    changed: Condvar,
+}
+
+impl SrtCallers {
+    fn get(&self) -> usize {
+        *self.count.lock().unwrap()
+    }
+
+    fn add(&self) {
+        *self.count.lock().unwrap() += 1;
+        self.changed.notify_all();
+    }
+
+    fn remove(&self) {
+        let mut count = self.count.lock().unwrap();
+        *count = count.saturating_sub(1);
+        self.changed.notify_all();
+    }
+
+    /// Waits up to `timeout` for the count to be other than `seen`, returns the count either way
+    fn wait_for_change(&self, seen: usize, timeout: Duration) -> usize {
+        let count = self.count.lock().unwrap();
+        let (count, _) = self
+            .changed
+            .wait_timeout_while(count, timeout, |count| *count == seen)
+            .unwrap();
+        *count
+    }
+}
+
+/// Counts the callers of the SRT sink in `callers`, shared by every sink `set_output_url` switches to, and turns
+/// away the ones beyond --max-callers
+fn connect_srt_callers(
+    output_srt_sink: &gst::Element,
+    notifier: &Notifier,
+    callers: &Arc<SrtCallers>,
+    max_callers: Option<usize>,
+) {
+    if let Some(max_callers) = max_callers {
+        let callers = callers.clone();
+        output_srt_sink.connect("caller-connecting", false, move |values| {
+            let accept = callers.get() < max_callers;
+            if !accept {
+                warn!(
+                    "rejecting srt caller {}, already at --max-callers {max_callers}",
+                    caller_address(&values[1])
+                );
+            }
+            Some(accept.to_value())
+        });
+    }
+
+    let notifier_clone = notifier.clone();
+    let callers_clone = callers.clone();
+    output_srt_sink.connect("caller-added", false, move |values| {
+        let address = caller_address(&values[2]);
+        info!("srt caller connected: {address}");
+        callers_clone.add();
+        notifier_clone.notify(Event::SrtCallerConnected { address });
+
+        // a caller joining mid-stream can only start decoding video at a keyframe
+        let sink = values[0]
+            .get::<gst::Element>()
+            .expect("caller-added without sink");
+        request_keyframe(&sink);
+        None
+    });
+
+    let notifier_clone = notifier.clone();
+    let callers = callers.clone();
+    output_srt_sink.connect("caller-removed", false, move |values| {
+        let address = caller_address(&values[2]);
+        info!("srt caller disconnected: {address}");
+        callers.remove();
+        notifier_clone.notify(Event::SrtCallerDisconnected { address });
+        None
+    });
+}
+
+/// Drops everything flowing into the SRT sink while `paused` is set. The probe sits on the pad feeding the sink,
+/// so it stays in place when `set_output_url` swaps the sink.
+fn connect_output_pause(sink: &gst::Element, paused: &Arc<AtomicBool>) {
+    let Some(pad) = sink.static_pad("sink").and_then(|pad| pad.peer()) else {
+        return;
+    };
+
+    let paused = paused.clone();
+    pad.add_probe(
+        PadProbeType::BUFFER | PadProbeType::BUFFER_LIST,
+        move |_, _| {
+            if paused.load(Ordering::Relaxed) {
+                PadProbeReturn::Drop
+            } else {
+                PadProbeReturn::Ok
+            }
+        },
+    );
+}
+
+/// Marks a discontinuity in the transport stream going into the SRT sink whenever `pending` is set: after a
+/// timestamp resync, a re-subscribe or a pause without slate. Receivers then take the new timestamps and
+/// continuity counters as intended instead of reporting errors.
+fn connect_discontinuities(sink: &gst::Element, pending: &Arc<AtomicBool>) {
+    let Some(pad) = sink.static_pad("sink").and_then(|pad| pad.peer()) else {
+        return;
+    };
+
+    let pending = pending.clone();
+    let marker = Mutex::new(DiscontinuityMarker::new());
+    pad.add_probe(PadProbeType::BUFFER, move |_, probe_info| {
+        let mut marker = marker.lock().unwrap();
+        if pending.swap(false, Ordering::Relaxed) {
+            info!("marking a discontinuity in the transport stream");
+            marker.mark();
+        }
+
+        if !marker.is_idle()
+            && let Some(PadProbeData::Buffer(buffer)) = &mut probe_info.data
+            && let Ok(Some(data)) = buffer
+                .map_readable()
+                .map(|map| marker.process(map.as_slice()))
+        {
+            buffer
+                .make_mut()
+                .replace_all_memory(gst::Memory::from_mut_slice(data));
+        }
+
+        PadProbeReturn::Ok
+    });
+}
+
+/// Ticks the media clock with the data going into the sinks, on the pads feeding them, which stay when the SRT sink
+/// is switched
+fn connect_media_clock(pipeline: &gst::Pipeline, media: &MediaClock) {
+    for sink in pipeline.iterate_sinks().into_iter().flatten() {
+        let Some(pad) = sink.static_pad("sink").and_then(|pad| pad.peer()) else {
+            continue;
+        };
+        let media = media.clone();
+        pad.add_probe(
+            PadProbeType::BUFFER | PadProbeType::BUFFER_LIST,
+            move |_, _| {
+                media.tick();
+                PadProbeReturn::Ok
+            },
+        );
+    }
+}
+
+/// Takes the first TS milestone from the first data going into the SRT sink
+fn connect_first_ts(sink: &gst::Element, startup: &Startup) {
+    let Some(pad) = sink.static_pad("sink").and_then(|pad| pad.peer()) else {
+        return;
+    };
+
+    let startup = startup.clone();
+    pad.add_probe(
+        PadProbeType::BUFFER | PadProbeType::BUFFER_LIST,
+        move |_, _| {
+            startup.mark_first_ts();
+            PadProbeReturn::Remove
+        },
+    );
+}
+
+/// Sends a force-key-unit event upstream from `sink`. The local video encoder answers it with an IDR frame, and
+/// for passed through video the WebRTC session turns it into a PLI/FIR to the source.
+fn request_keyframe(sink: &gst::Element) {
+    let structure = gst::Structure::builder("GstForceKeyUnit")
+        .field("running-time", u64::MAX) // as soon as possible
+        .field("all-headers", true)
+        .field("count", 0u32)
+        .build();
+
+    if !sink.send_event(gst::event::CustomUpstream::new(structure)) {
+        info!("no video to request a keyframe from");
+    }
+}
+
+/// Hands what the CMAF muxers produce to the renditions of the presentation: init segments, and the chunks of
+/// the media segments as they come
+fn connect_cmaf(
+    pipeline: &gst::Pipeline,
+    presentation: &cmaf::Presentation,
+    audio_bandwidth: u32,
+    video_bandwidth: u32,
+) -> Result<(), Error> {
+    for (name, bandwidth) in [("audio", audio_bandwidth), ("video", video_bandwidth)] {
+        let Some(appsink) = pipeline
+            .by_name(&format!("cmaf_{name}"))
+            .and_then(|elem| elem.downcast::<gst_app::AppSink>().ok())
+        else {
+            continue;
+        };
+
+        let rendition = Arc::new(Mutex::new(presentation.rendition(name, bandwidth)?));
+        let rendition_clone = rendition.clone();
+        appsink.set_callbacks(
+            gst_app::AppSinkCallbacks::builder()
+                .new_sample(move |appsink| {
+                    let sample = appsink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                    let buffers: Vec<&gst::BufferRef> = match sample.buffer_list() {
+                        Some(list) => list.iter().collect(),
+                        None => sample.buffer().into_iter().collect(),
+                    };
+
+                    let result = cmaf_buffers(&mut rendition.lock().unwrap(), buffers);
+                    // the stream goes on, the next segment may well make it
+                    if let Err(err) = result {
+                        warn!("CMAF {name}: {err}");
+                    }
+                    Ok(gst::FlowSuccess::Ok)
+                })
+                .eos(move |_| {
+                    if let Err(err) = rendition_clone.lock().unwrap().finish() {
+                        warn!("CMAF {name}: {err}");
+                    }
+                })
+                .build(),
+        );
+    }
+
+    Ok(())
+}
+
+/// One output of a CMAF muxer: the init segment if it changed, followed by a chunk of a media segment
+fn cmaf_buffers(
+    rendition: &mut cmaf::Rendition,
+    mut buffers: Vec<&gst::BufferRef>,
+) -> Result<(), String> {
+    if buffers
+        .first()
+        .is_some_and(|first| first.flags().contains(gst::BufferFlags::HEADER))
+    {
+        let init = buffers.remove(0);
+        let map = init.map_readable().map_err(|err| err.to_string())?;
+        rendition.init(map.to_vec())?;
+    }
+    let Some(first) = buffers.first() else {
+        return Ok(());
+    };
+
+    // the first chunk of a fragment is the only one not flagged as a delta unit
+    let starts_segment = !first.flags().contains(gst::BufferFlags::DELTA_UNIT);
+    let pts = first.pts().map(|pts| Duration::from_nanos(pts.nseconds()));
+    let duration = first
+        .duration()
+        .map(|duration| Duration::from_nanos(duration.nseconds()));
+
+    let mut data = Vec::new();
+    for buffer in &buffers {
+        let map = buffer.map_readable().map_err(|err| err.to_string())?;
+        data.extend_from_slice(&map);
+    }
+    rendition.chunk(&data, starts_segment, pts, duration)
+}
+
+/// Feeds the generated --teletext PID with one PES of stuffing data units per video frame, or the --captions page
+/// when there is a new one
+fn connect_teletext(pipeline: &gst::Pipeline, captions: &PendingPes) {
+    let Some(appsrc) = pipeline
+        .by_name("teletext_source")
+        .and_then(|elem| elem.downcast::<gst_app::AppSrc>().ok())
+    else {
+        return;
+    };
+
+    // EN 300 472: data identifier 0x10, then 3 stuffing data units of 46 bytes, which together with the
+    // 45 byte PES header mpegtsmux writes for teletext fills exactly one TS packet
+    let mut payload = vec![0x10];
+    for _ in 0..3 {
+        payload.extend([0xff, 0x2c]);
+        payload.extend([0xff; 44]);
+    }
+
+    let frame = AtomicU64::new(0);
+    let captions = captions.clone();
+    appsrc.set_callbacks(
+        gst_app::AppSrcCallbacks::builder()
+            .need_data(move |appsrc, _| {
+                let n = frame.fetch_add(1, Ordering::Relaxed);
+
+                let page = captions.lock().unwrap().take();
+                let mut buffer = gst::Buffer::from_slice(page.unwrap_or_else(|| payload.clone()));
+                {
+                    let buffer = buffer.get_mut().expect("new buffer is writable");
+                    buffer.set_pts(TELETEXT_FRAME * n);
+                    buffer.set_duration(TELETEXT_FRAME);
+                }
+                let _ = appsrc.push_buffer(buffer);
+            })
+            .build(),
+    );
+}
+
+/// Collects the mono audio of the --captions tap into chunks and hands them to the transcription thread, which
+/// sets the subtitle page `connect_teletext` sends next
+fn connect_captions(pipeline: &gst::Pipeline, transcriber: Transcriber, pending: PendingPes) {
+    let Some(appsink) = pipeline
+        .by_name("captions_audio")
+        .and_then(|elem| elem.downcast::<gst_app::AppSink>().ok())
+    else {
+        return;
+    };
+
+    // one chunk waiting at most, the captions fall behind rather than the audio piling up
+    let (sender, receiver) = mpsc::sync_channel(1);
+    captions::spawn(transcriber, receiver, pending);
+
+    let chunk_len = (captions::SAMPLE_RATE as u64 * captions::CHUNK.as_secs()) as usize;
+    let chunk = Mutex::new(Vec::with_capacity(chunk_len));
+    appsink.set_callbacks(
+        gst_app::AppSinkCallbacks::builder()
+            .new_sample(move |appsink| {
+                let sample = appsink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                let Some(buffer) = sample.buffer() else {
+                    return Ok(gst::FlowSuccess::Ok);
+                };
+                let map = buffer.map_readable().map_err(|_| gst::FlowError::Error)?;
+
+                let mut chunk = chunk.lock().unwrap();
+                chunk.extend(
+                    map.chunks_exact(2)
+                        .map(|sample| i16::from_le_bytes([sample[0], sample[1]])),
+                );
+                if chunk.len() >= chunk_len {
+                    let full = std::mem::replace(&mut *chunk, Vec::with_capacity(chunk_len));
+                    if sender.try_send(full).is_err() {
+                        warn!("transcription is falling behind, audio left uncaptioned");
+                    }
+                }
+                Ok(gst::FlowSuccess::Ok)
+            })
+            .build(),
+    );
+}
+
+/// Adds the --ts-descriptor descriptors to the PMTs leaving the muxer
+fn connect_ts_descriptors(pipeline: &gst::Pipeline, descriptors: &[TsDescriptor]) {
+    if descriptors.is_empty() {
+        return;
+    }
+    // only there with srt output
+    let Some(mux_src) = pipeline
+        .by_name("mux")
+        .and_then(|mux| mux.static_pad("src"))
+    else {
+        return;
+    };
+
+    let rewriter = Mutex::new(PmtRewriter::new(descriptors.to_vec()));
+    let warned = AtomicBool::new(false);
+    mux_src.add_probe(PadProbeType::BUFFER, move |_, probe_info| {
+        if let Some(PadProbeData::Buffer(buffer)) = &mut probe_info.data
+            && let Ok(mut map) = buffer.make_mut().map_writable()
+        {
+            let skipped = rewriter.lock().unwrap().process(map.as_mut_slice());
+            if skipped > 0 && !warned.swap(true, Ordering::Relaxed) {
+                warn!("PMT does not fit its packet with the --ts-descriptor descriptors, left unchanged");
+            }
+        }
+
+        PadProbeReturn::Ok
+    });
+}
+
+/// Claims the rate the decoded audio really arrives at on the sink of a decode leg's audioresample, which then
+/// converts it to the mix rate. This keeps the sample count in step with the local clock over long sessions,
+/// instead of audiorate and the mixer cutting or padding audio once the sender's clock has drifted far enough.
+fn connect_drift_correction(audioresample: &gst::Element, monitor: &DriftMonitor) {
+    let pad = audioresample.static_pad("sink").unwrap();
+    let estimator = monitor.track();
+
+    pad.add_probe(PadProbeType::BUFFER, move |pad, probe_info| {
+        let Some(PadProbeData::Buffer(buffer)) = &probe_info.data else {
+            return PadProbeReturn::Ok;
+        };
+        let (Some(pts), Some(caps)) = (buffer.pts(), pad.current_caps()) else {
+            return PadProbeReturn::Ok;
+        };
+        let Ok(info) = gst_audio::AudioInfo::from_caps(&caps) else {
+            return PadProbeReturn::Ok;
+        };
+
+        let mut estimator = estimator.lock().unwrap();
+        // a rate other than the claimed one means upstream negotiated again, which starts over
+        if estimator.as_ref().is_none_or(|e| e.rate() != info.rate()) {
+            *estimator = Some(DriftEstimator::new(info.rate()));
+        }
+        let estimator = estimator.as_mut().unwrap();
+
+        if buffer.flags().contains(gst::BufferFlags::DISCONT) {
+            estimator.resync();
+        }
+        let samples = buffer.size() as u64 / info.bpf().max(1) as u64;
+        let Some(rate) = estimator.update(pts.nseconds(), samples) else {
+            return PadProbeReturn::Ok;
+        };
+
+        tracing::debug!(
+            "sender clock drift {:+.1} ppm, resampling from {rate} Hz, {:+.1}ms skew in total, {:+.1}ms corrected",
+            estimator.drift_ppm().unwrap_or_default(),
+            estimator.skew_ms(),
+            estimator.corrected_ms(),
+        );
+        let mut caps = caps.copy();
+        caps.make_mut().set("rate", rate as i32);
+        if !pad.send_event(gst::event::Caps::new(&caps)) {
+            warn!("could not apply drift correction of {}", pad.name());
+        }
+
+        PadProbeReturn::Ok
+    });
+}
+
+/// Configures the Opus decoders decodebin creates for the WHEP audio. Concealment needs the jitter buffers to
+/// report lost packets, which the depayloaders turn into gaps for the decoder to fill.
+fn connect_opus_decoders(pipeline: &gst::Pipeline, fec: bool, plc: bool) {
+    pipeline.connect_deep_element_added(move |_, _, elem| match elem.type_().name() {
+        "GstOpusDec" => {
+            elem.set_property("use-inband-fec", fec);
+            elem.set_property("plc", plc);
+        }
+        "GstRtpJitterBuffer" if plc => elem.set_property("do-lost", true),
+        _ => (),
+    });
+}
+
+/// Jitter buffer retransmission settings, unset ones keep the rtpjitterbuffer defaults
+#[derive(Debug, Clone, Copy)]
+struct Retransmission {
+    max_retries: Option<u32>,
+    retry_period_ms: Option<u32>,
+}
+
+/// What the offer tells the WHEP origin about congestion feedback, the bitrate it may send and the redundancy
+/// it may add
+#[derive(Debug, Clone, Copy)]
+struct Feedback {
+    twcc: bool,
+    max_bitrate: Option<u32>,
+    /// Offer RED and ULPFEC, webrtcbin then decodes them with rtpreddec and rtpulpfecdec
+    fec: bool,
+}
+
+impl Feedback {
+    /// Adds to the codec preferences of a new transceiver, webrtcbin writes them into the offer as
+    /// rtcp-fb, extmap and fmtp attributes
+    fn apply(&self, transceiver: &gst_webrtc::WebRTCRTPTransceiver) {
+        if self.fec {
+            transceiver.set_property("fec-type", gst_webrtc::WebRTCFECType::UlpRed);
+        }
+        if !self.twcc && self.max_bitrate.is_none() {
+            return;
+        }
+        let Some(mut caps) = transceiver.property::<Option<gst::Caps>>("codec-preferences") else {
+            return;
+        };
+
+        for s in caps.make_mut().iter_mut() {
+            if self.twcc {
+                s.set("rtcp-fb-transport-cc", true);
+
+                let used: Vec<u32> = s
+                    .fields()
+                    .filter_map(|field| field.strip_prefix("extmap-")?.parse().ok())
+                    .collect();
+                if let Some(id) = (1..=14).find(|id| !used.contains(id)) {
+                    s.set(format!("extmap-{id}").as_str(), TWCC_EXTENSION);
+                }
+            }
+
+            if let Some(max_bitrate) = self.max_bitrate {
+                let encoding = s.get::<String>("encoding-name").unwrap_or_default();
+                if encoding.to_lowercase().ends_with("opus") {
+                    // RFC 7587, the most Opus encoders take is 510 kbit/s
+                    s.set(
+                        "maxaveragebitrate",
+                        max_bitrate.clamp(6000, 510000).to_string(),
+                    );
+                } else if s.get::<String>("media").is_ok_and(|media| media == "video") {
+                    // honored by libwebrtc based senders, in kbit/s
+                    s.set("x-google-max-bitrate", (max_bitrate / 1000).to_string());
+                }
+            }
+        }
+
+        transceiver.set_property("codec-preferences", caps);
+    }
+}
+
+/// What to do with the session descriptions webrtcbin negotiates, for --log-sdp and --save-sdp
+#[derive(Debug, Clone)]
+struct SdpDebug {
+    log: bool,
+    save_dir: Option<String>,
+}
+
+impl SdpDebug {
+    fn describe(
+        &self,
+        property: &str,
+        session: &str,
+        description: &gst_webrtc::WebRTCSessionDescription,
+    ) {
+        let Ok(sdp) = description.sdp().as_text() else {
+            return;
+        };
+        let side = property.trim_end_matches("-description");
+        let sdp_type = format!("{:?}", description.type_()).to_lowercase();
+
+        if self.log {
+            log_description(side, &sdp_type, &sdp);
+        }
+
+        if let Some(save_dir) = &self.save_dir {
+            let dir = std::path::Path::new(save_dir).join(session);
+            let path = dir.join(format!("{sdp_type}.sdp"));
+            match std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(&path, &sdp)) {
+                Ok(()) => info!("saved {side} {sdp_type} to {}", path.display()),
+                Err(err) => warn!(
+                    "could not save {side} {sdp_type} to {}: {err}",
+                    path.display()
+                ),
+            }
+        }
+    }
+}
+
+/// Logs a negotiated session description and how many ICE candidates of each type it has
+fn log_description(side: &str, sdp_type: &str, sdp: &str) {
+    let mut candidates: Vec<(String, usize)> = Vec::new();
+    for line in sdp.lines().filter(|line| line.starts_with("a=candidate:")) {
+        // a=candidate:<foundation> <component> <transport> <priority> <address> <port> typ <type> ...
+        let kind = line
+            .split_whitespace()
+            .skip_while(|field| *field != "typ")
+            .nth(1)
+            .unwrap_or("unknown");
+        match candidates.iter_mut().find(|(k, _)| k == kind) {
+            Some((_, count)) => *count += 1,
+            None => candidates.push((kind.to_string(), 1)),
+        }
+    }
+    let summary: Vec<String> = candidates
+        .iter()
+        .map(|(kind, count)| format!("{count} {kind}"))
+        .collect();
+    let summary = if summary.is_empty() {
+        String::from("none in the sdp")
+    } else {
+        summary.join(", ")
+    };
+
+    info!("{side} {sdp_type}:\n{sdp}");
+    info!("{side} ice candidates: {summary}");
+}
+
+/// What the offer asks the WHEP origin for next to the codecs webrtcsrc offers: other audio codecs and a
+/// simulcast layer of the video
+#[derive(Debug, Clone)]
+struct MediaOffer {
+    channels: u32,
+    /// Also offer the codecs of SIP gateways, for --sip-codecs
+    sip_codecs: bool,
+    /// The rid of the simulcast layer to receive, for --video-layer
+    video_rid: Option<String>,
+}
+
+impl MediaOffer {
+    fn apply(&self, transceiver: &gst_webrtc::WebRTCRTPTransceiver) {
+        offer_multiopus(transceiver, self.channels);
+        if self.sip_codecs {
+            offer_sip_codecs(transceiver);
+        }
+        if let Some(rid) = &self.video_rid {
+            offer_video_layer(transceiver, rid);
+        }
+    }
+}
+
+/// Asks for a single simulcast layer of a video transceiver. A rid in the codec preferences makes webrtcbin
+/// offer `a=rid:<rid> recv` and `a=simulcast:recv <rid>`, so an origin with simulcast sends only that layer.
+fn offer_video_layer(transceiver: &gst_webrtc::WebRTCRTPTransceiver, rid: &str) {
+    let Some(mut caps) = transceiver.property::<Option<gst::Caps>>("codec-preferences") else {
+        return;
+    };
+    if !caps
+        .iter()
+        .any(|s| s.get::<String>("media").is_ok_and(|media| media == "video"))
+    {
+        return;
+    }
+
+    for s in caps.make_mut().iter_mut() {
+        s.set(format!("rid-{rid}").as_str(), "recv");
+    }
+    info!("asking for the video layer with rid {rid}");
+    transceiver.set_property("codec-preferences", caps);
+}
+
+/// Adds G.711 and G.722 behind the codec preferences of an audio transceiver, with their static payload types.
+/// G.722 samples at 16 kHz but is signaled with an 8 kHz RTP clock rate, as RFC 3551 has it.
+fn offer_sip_codecs(transceiver: &gst_webrtc::WebRTCRTPTransceiver) {
+    let Some(caps) = transceiver.property::<Option<gst::Caps>>("codec-preferences") else {
+        return;
+    };
+    if !caps
+        .iter()
+        .any(|s| s.get::<String>("media").is_ok_and(|media| media == "audio"))
+    {
+        return;
+    }
+
+    let mut preferences = caps.copy();
+    {
+        let preferences = preferences.make_mut();
+        for (encoding, payload) in [("PCMU", 0), ("PCMA", 8), ("G722", 9)] {
+            preferences.append_structure(
+                gst::Structure::builder("application/x-rtp")
+                    .field("media", "audio")
+                    .field("encoding-name", encoding)
+                    .field("payload", payload)
+                    .field("clock-rate", 8000)
+                    .build(),
+            );
+        }
+    }
+    transceiver.set_property("codec-preferences", preferences);
+}
+
+/// Adds surround Opus in front of the codec preferences of an audio transceiver, for 5.1 and 7.1 mixes.
+/// MULTIOPUS is the libwebrtc name for Opus with channel mapping family 1, its fmtp gives the stream layout.
+fn offer_multiopus(transceiver: &gst_webrtc::WebRTCRTPTransceiver, channels: u32) {
+    // Vorbis channel order, as libwebrtc offers it
+    let (channel_mapping, num_streams, coupled_streams) = match channels {
+        6 => ("0,4,1,2,3,5", 4, 2),
+        8 => ("0,6,1,2,3,4,5,7", 5, 3),
+        _ => return,
+    };
+    let Some(caps) = transceiver.property::<Option<gst::Caps>>("codec-preferences") else {
+        return;
+    };
+    let Some(opus) = caps.iter().find(|s| {
+        s.get::<String>("encoding-name")
+            .is_ok_and(|name| name.eq_ignore_ascii_case("opus"))
+    }) else {
+        return;
+    };
+
+    let used: Vec<i32> = caps
+        .iter()
+        .filter_map(|s| s.get::<i32>("payload").ok())
+        .collect();
+    let Some(payload) = (96..=127).find(|pt| !used.contains(pt)) else {
+        return;
+    };
+
+    let mut multiopus = opus.to_owned();
+    multiopus.set("encoding-name", "MULTIOPUS");
+    multiopus.set("encoding-params", channels.to_string());
+    multiopus.set("payload", payload);
+    multiopus.set("channel_mapping", channel_mapping);
+    multiopus.set("num_streams", num_streams.to_string());
+    multiopus.set("coupled_streams", coupled_streams.to_string());
+
+    let mut preferences = gst::Caps::new_empty();
+    {
+        let preferences = preferences.get_mut().unwrap();
+        preferences.append_structure(multiopus);
+        preferences.append(caps);
+    }
+    transceiver.set_property("codec-preferences", preferences);
+}
+
+fn connect_webrtcbin(
+    pipeline: &gst::Pipeline,
+    notifier: &Notifier,
+    jitter_buffer_ms: u32,
+    retransmission: Retransmission,
+    feedback: Feedback,
+    media_offer: MediaOffer,
+    sdp_debug: SdpDebug,
+) {
+    let notifier_clone = notifier.clone();
+    let span = tracing::Span::current();
+    let weak_pipeline = pipeline.downgrade();
+    pipeline.connect_deep_element_added(move |pipe, bin, elem| {
+        let elem_type = elem.type_().to_string();
+        let _ = pipe;
+        let _ = bin;
+
+        // webrtcbin's rtpbin creates a jitter buffer per stream, webrtcbin turns retransmission on or off in them
+        if elem_type == "GstRtpJitterBuffer" {
+            if let Some(max_retries) = retransmission.max_retries {
+                elem.set_property("rtx-max-retries", max_retries as i32);
+            }
+            if let Some(retry_period_ms) = retransmission.retry_period_ms {
+                elem.set_property("rtx-retry-period", retry_period_ms as i32);
+            }
+        }
+
+        if elem_type == "GstWebRTCBin" {
+            elem.set_property("latency", jitter_buffer_ms);
+
+            // everything this session's webrtcbin signals is logged within it
+            let session_span =
+                tracing::info_span!(parent: &span, "negotiation", webrtcbin = %elem.name());
+
+            if sdp_debug.log || sdp_debug.save_dir.is_some() {
+                // a new webrtcbin for every session, so each reconnect gets its own directory
+                let epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+                let session = format!("{}-{}", epoch.as_secs(), elem.name());
+
+                for property in ["local-description", "remote-description"] {
+                    let sdp_debug = sdp_debug.clone();
+                    let session = session.clone();
+                    let description_span = session_span.clone();
+                    elem.connect_notify(Some(property), move |elem, _| {
+                        let _entered = description_span.enter();
+                        if let Some(description) = elem
+                            .property::<Option<gst_webrtc::WebRTCSessionDescription>>(property)
+                        {
+                            sdp_debug.describe(property, &session, &description);
+                        }
+                    });
+                }
+            }
+
+            let transceiver_span = session_span.clone();
+            let media_offer = media_offer.clone();
+            elem.connect("on-new-transceiver", false, move |values| {
+                let _entered = transceiver_span.enter();
+                let transceiver = values[1]
+                    .get::<gst_webrtc::WebRTCRTPTransceiver>()
+                    .expect("on-new-transceiver without transceiver");
+                media_offer.apply(&transceiver);
+                feedback.apply(&transceiver);
+                None
+            });
+
+            let notifier_clone = notifier_clone.clone();
+            let state_span = session_span.clone();
+            elem.connect_notify(Some("connection-state"), move |elem, _| {
+                use gst_webrtc::WebRTCPeerConnectionState as State;
+                let _entered = state_span.enter();
+
+                let state = elem.property::<State>("connection-state");
+                info!("webrtc connection state: {state:?}");
+
+                match state {
+                    State::Connected => notifier_clone.notify(Event::WhepConnected),
+                    State::Disconnected | State::Failed | State::Closed => {
+                        notifier_clone.notify(Event::WhepDisconnected {
+                            state: format!("{state:?}").to_lowercase(),
+                        })
+                    }
+                    _ => (),
+                }
+            });
+
+            let pipeline = weak_pipeline.clone();
+            elem.connect_pad_added(move |elem, pad| {
+                let _entered = tracing::info_span!(
+                    parent: &session_span,
+                    "pad_setup",
+                    pad = %pad.name(),
+                )
+                .entered();
+                info!("webrtcbin pad added: '{}'", pad.name());
+
+                if let Err(err) = expose_track(elem, pad)
+                    && let Some(pipeline) = pipeline.upgrade()
+                {
+                    branch_failed(&pipeline, pad, &err);
+                }
+            });
+        }
+    });
+}
+
+/// Ghost pads a track out of the bins around webrtcbin. When receiving multiple audio tracks (ssrcs), the first
+/// track is automatically exposed out of the whepclientsrc bin, the others are not, so they are exposed here.
+fn expose_track(webrtcbin: &gst::Element, pad: &gst::Pad) -> Result<(), Error> {
+    let caps = pad
+        .current_caps()
+        .context(&format!("could not get current_caps on pad {}", pad.name()))?;
+    let media_type = caps
+        .structure(0)
+        .context("could not get structure 0 on caps")?
+        .get::<String>("media")
+        .context("could not get media from caps structure")?;
+
+    if pad.is_linked() {
+        return Ok(());
+    }
+    info!(
+        "pad '{}' is not automatically linked, handling ghostpads. media_type: {media_type}",
+        pad.name()
+    );
+
+    let parent = webrtcbin
+        .parent()
+        .and_then(|parent| parent.downcast::<gst::Bin>().ok())
+        .context("could not get webrtcbin parent bin")?;
+    if parent.is::<gst::Pipeline>() {
+        // the webrtcbin of --ice-restart is the input itself
+        return Ok(());
+    }
+
+    let new_pad_name = format!("{}_{}", media_type, pad.name());
+
+    let ghostpad = GhostPad::builder(PadDirection::Src)
+        .with_target(pad)
+        .context("could not create ghostpad")?
+        .name(&new_pad_name)
+        .build();
+    parent
+        .add_pad(&ghostpad)
+        .context("could not add ghostpad to parent")?;
+
+    let parent_parent = parent.parent().context("could not get parent parent")?;
+    if parent_parent.is::<gst::Pipeline>() {
+        // no more ghostpads needed
+        return Ok(());
+    }
+    let parent_parent = parent_parent
+        .downcast::<gst::Bin>()
+        .ok()
+        .context("could not cast webrtcbin parent parent")?;
+
+    let ghostpad2 = GhostPad::builder(PadDirection::Src)
+        .with_target(&ghostpad)
+        .context("could not create ghostpad2 with target ghostpad")?
+        .name(&new_pad_name)
+        .build();
+    parent_parent
+        .add_pad(&ghostpad2)
+        .context("could not add ghostpad2")?;
+
+    Ok(())
+}
+
+/// The replaceable front of the pipeline: the input element and the decode legs it feeds into the mixer
+#[derive(Clone, Default)]
+struct Input {
+    /// Launch description of the input element, to create it again on reconnect
+    description: String,
+    /// Span the gateway was built in, e.g. the control API's stream, parent of the input's spans
+    span: tracing::Span,
+    /// Re-subscribes so far
+    reconnects: Arc<AtomicUsize>,
+    on_eos: OnInputEos,
+    /// audioresample quality (0-10) of the decode legs
+    resample_quality: Option<u32>,
+    /// Resample the decode legs to follow the sender's clock drift
+    drift_correction: bool,
+    /// Drift estimators of the decode legs
+    drift: DriftMonitor,
+    dtx_fill: DtxFill,
+    /// Length of the fades when the input is replaced, zero for a hard cut
+    crossfade: Duration,
+    /// Set when the current input's legs should fade out, replaced by a fresh flag for each new input
+    fade_out: Arc<Mutex<Arc<AtomicBool>>>,
+    agc: bool,
+    gate_threshold_db: Option<f64>,
+    /// Input channel for each output channel, empty to pass channels through as they are
+    channel_map: Vec<usize>,
+    /// Position in the mix of each audio track in the order they arrive, empty to mix every track into all channels
+    bed: Vec<BedPosition>,
+    /// --track-gain of the known tracks
+    track_gain: Vec<TrackGain>,
+    /// --pan of the known tracks
+    pan: Vec<TrackPan>,
+    /// Audio tracks of the current input decoded so far, numbering them for the bed and the track gains
+    audio_tracks: Arc<AtomicUsize>,
+    /// Link the WHEP audio to the muxer as it is, instead of decoding it into the mixer
+    passthrough: bool,
+    /// ISO 639-2 language tagged on the passed through audio
+    language: Option<String>,
+    /// Set once an audio track is passed through, the muxer takes only one
+    passed_through: Arc<AtomicBool>,
+    startup: Startup,
+    /// Bearer token for the WHEP endpoint, --auth-token or the latest --oauth-token-url one
+    auth_token: Arc<Mutex<Option<String>>>,
+    /// The signalling of a webrtcbin input, with --ice-restart
+    whep: Option<WhepClient>,
+    /// Link the source video to the muxer instead of discarding it
+    mux_video: bool,
+    /// Width and height of the --mosaic frame the video tracks are tiled into
+    mosaic: Option<(i32, i32)>,
+    /// Whether H.264 the output can carry may skip transcoding
+    video_passthrough: bool,
+    /// Launch fragment encoding decoded source video for the muxer
+    video_transcode: String,
+    /// The --record-format mkv-source file every track is also written to
+    recording: Option<SourceRecording>,
+    legs: Arc<Mutex<Vec<gst::Element>>>,
+    mixer_pads: Arc<Mutex<Vec<gst::Pad>>>,
+    mux_pads: Arc<Mutex<Vec<gst::Pad>>>,
+    /// Compositor inputs of the video tracks, in the order of their tiles
+    mosaic_pads: Arc<Mutex<Vec<gst::Pad>>>,
+    reconnecting: Arc<AtomicBool>,
+    /// Set while paused with a slate, the WHEP tracks are dropped as they arrive
+    slate: Arc<AtomicBool>,
+    /// Set when the transport stream should carry a discontinuity, taken by the SRT output
+    discontinuity: Arc<AtomicBool>,
+    /// Set once the whole pipeline is being ended, the input's EOS is then part of that
+    ending: Arc<AtomicBool>,
+}
+
+impl Input {
+    /// Gives a new input element the current bearer token
+    fn authenticate(&self, input: &gst::Element) {
+        if let Some(token) = self.auth_token.lock().unwrap().as_deref() {
+            set_auth_token(input, token);
+        }
+    }
+
+    /// Sets up the signalling of a new webrtcbin input with --ice-restart, the WHEP elements do their own
+    fn signal(&self, input: &gst::Element) {
+        if let Some(whep) = &self.whep {
+            whep.connect(input, &self.auth_token);
+        }
+    }
+
+    /// Ends the WHEP session with --ice-restart, returning the thread doing it. The WHEP elements end theirs as
+    /// they stop.
+    fn leave(&self) -> Option<std::thread::JoinHandle<()>> {
+        self.whep
+            .as_ref()?
+            .leave(self.auth_token.lock().unwrap().clone())
+    }
+
+    fn track(&self, elem: &gst::Element) {
+        self.legs.lock().unwrap().push(elem.clone());
+    }
+
+    fn track_mixer_pad(&self, pad: &gst::Pad) {
+        self.mixer_pads.lock().unwrap().push(pad.clone());
+    }
+
+    fn track_mux_pad(&self, pad: &gst::Pad) {
+        self.mux_pads.lock().unwrap().push(pad.clone());
+    }
+
+    /// Builds the branch of a WHEP track as its first buffer arrives, and tees it off to a source recording
+    fn link_track(
+        &self,
+        pipeline: &gst::Pipeline,
+        mixer: Option<&gst::Element>,
+        pad: &gst::Pad,
+        media_type: &str,
+        caps: &gst::Caps,
+    ) -> Result<(), Error> {
+        match media_type {
+            "audio" if self.passthrough => self.pass_through(pipeline, pad)?,
+            "audio" => self.decode_audio(pipeline, mixer, pad)?,
+            "video" if self.mux_video && self.mosaic.is_some() => self.tile_video(pipeline, pad)?,
+            "video" if self.mux_video => self.link_video(pipeline, pad)?,
+            "video" => self.discard(pipeline, pad)?,
+            _ => {
+                error!("unhandled media type");
+            }
+        }
+
+        if let Some(recording) = &self.recording {
+            let encoding = caps
+                .structure(0)
+                .and_then(|s| s.get::<String>("encoding-name").ok())
+                .unwrap_or_default();
+            recording.record(pipeline, self, pad, &encoding)?;
+        }
+        Ok(())
+    }
+
+    /// Links an audio track to a decodebin, whose output `audio_leg` takes to the mixer
+    fn decode_audio(
+        &self,
+        pipeline: &gst::Pipeline,
+        mixer: Option<&gst::Element>,
+        pad: &gst::Pad,
+    ) -> Result<(), Error> {
+        let msid = track_msid(pad);
+        let encoding = pad
+            .current_caps()
+            .and_then(|caps| caps.structure(0)?.get::<String>("encoding-name").ok())
+            .unwrap_or_default();
+        if let Some(description) = sip_decoder(&encoding) {
+            return self.decode_sip_audio(pipeline, mixer, pad, description, msid.as_deref());
+        }
+
+        let decodebin = ElementFactory::make("decodebin")
+            .build()
+            .context("could not create decodebin")?;
+        pipeline
+            .add(&decodebin)
+            .context("could not add decodebin to pipeline")?;
+        decodebin
+            .sync_state_with_parent()
+            .context("could not sync_state on decode_bin")?;
+
+        self.track(&decodebin);
+
+        let pipeline_clone = pipeline.clone();
+        let mixer = mixer.cloned();
+        let input = self.clone();
+        // decodebin adds its pads later, on its own thread
+        let branch_span = tracing::Span::current();
+        decodebin.connect_pad_added(move |elem, pad| {
+            let _entered =
+                tracing::info_span!(parent: &branch_span, "audio_leg", pad = %pad.name()).entered();
+            info!("pad '{}' added on decodebin '{}'", pad.name(), elem.name());
+
+            if let Err(err) = input.audio_leg(&pipeline_clone, mixer.as_ref(), pad, msid.as_deref())
+            {
+                branch_failed(&pipeline_clone, pad, &err);
+            }
+        });
+
+        //link from webrtcbin to decodebin
+        let decodebin_pad = decodebin
+            .static_pad("sink")
+            .context("could not get decodebin sink pad")?;
+        pad.link(&decodebin_pad)
+            .context("could not link from webrtcbin audio pad to decodebin")?;
+        Ok(())
+    }
+
+    /// Decodes a G.711 or G.722 track with the depayloader and decoder for its encoding, decodebin's choice for
+    /// them depends on the installed plugins and their ranks
+    fn decode_sip_audio(
+        &self,
+        pipeline: &gst::Pipeline,
+        mixer: Option<&gst::Element>,
+        pad: &gst::Pad,
+        description: &str,
+        msid: Option<&str>,
+    ) -> Result<(), Error> {
+        info!("decoding audio with {description}");
+        let decoder = gst::parse::bin_from_description(description, true)
+            .context("could not create audio decoder")?
+            .upcast::<gst::Element>();
+        pipeline
+            .add(&decoder)
+            .context("could not add audio decoder to pipeline")?;
+        decoder
+            .sync_state_with_parent()
+            .context("could not sync state on audio decoder")?;
+        self.track(&decoder);
+
+        let decoder_pad = decoder
+            .static_pad("src")
+            .context("could not get audio decoder src pad")?;
+        self.audio_leg(pipeline, mixer, &decoder_pad, msid)?;
+
+        pad.link(&decoder.static_pad("sink").unwrap())
+            .context("could not link input audio to audio decoder")?;
+        Ok(())
+    }
+
+    /// Converts, resamples and processes the decoded audio of a track into the mixer
+    fn audio_leg(
+        &self,
+        pipeline: &gst::Pipeline,
+        mixer: Option<&gst::Element>,
+        pad: &gst::Pad,
+        msid: Option<&str>,
+    ) -> Result<(), Error> {
+        let track = self.audio_tracks.fetch_add(1, Ordering::Relaxed);
+        let channel_map = if self.bed.is_empty() {
+            self.channel_map()?
+        } else {
+            let Some(&position) = self.bed.get(track) else {
+                warn!(
+                    "all {} positions of the bed are taken, leaving the audio track out",
+                    self.bed.len()
+                );
+                return self.discard(pipeline, pad);
+            };
+            info!("audio track {} at {position:?} of the bed", track + 1);
+            self.bed_channel(position)?
+        };
+
+        let audioconvert = ElementFactory::make("audioconvert")
+            .build()
+            .context("could not create audioconvert")?;
+        let audioresample = ElementFactory::make("audioresample")
+            .build()
+            .context("could not create audioresample")?;
+        if let Some(quality) = self.resample_quality {
+            audioresample.set_property("quality", quality as i32);
+        }
+        if self.drift_correction {
+            connect_drift_correction(&audioresample, &self.drift);
+        }
+        let caps = ElementFactory::make("capsfilter")
+            .build()
+            .context("could not create capsfiler")?;
+        caps.set_property_from_str("caps", "audio/x-raw,format=F32LE,rate=48000");
+
+        let dynamics = self.dynamics()?;
+
+        let gain = self.track_gain(track + 1, msid)?;
+        let pan = self.pan(track + 1, msid)?;
+
+        let audiorate = self.dtx_fill()?;
+        self.fade(&audiorate.static_pad("src").unwrap());
+
+        let mut elements = vec![&audioconvert];
+        elements.extend(&channel_map);
+        elements.extend(&pan);
+        elements.push(&audioresample);
+        elements.extend(&dynamics);
+        elements.extend(&gain);
+        elements.push(&caps);
+        elements.push(&audiorate);
+
+        pipeline
+            .add_many(elements.iter().copied())
+            .context("could not add_many")?;
+        for elem in &elements {
+            elem.sync_state_with_parent()
+                .context("could not sync_state_with_parent")?;
+            self.track(elem);
+        }
+
+        gst::Element::link_many(elements.iter().copied())
+            .context("could not link many on elements")?;
+
+        //-- setup links from decodebin leg to audiomixer --
+        let leg_src_pad = audiorate.static_pad("src").unwrap();
+
+        let mixer_input_pad = mixer
+            .context("could not find mixer element")?
+            .request_pad_simple("sink_%u")
+            .context("could not get audio mixer input pad")?;
+        self.track_mixer_pad(&mixer_input_pad);
+
+        leg_src_pad
+            .link(&mixer_input_pad)
+            .context("could not link input audio to audiomixer")?;
+
+        //link decodebin pad to audioconvert
+        pad.link(&audioconvert.static_pad("sink").unwrap())
+            .context("could not link decodebin to audioconvert sink")?;
+        Ok(())
+    }
+
+    /// The muxer input for a video track: the only one of the video cmafmux of a CMAF output, or a new one of
+    /// the transport stream muxer
+    fn video_mux_pad(&self, pipeline: &gst::Pipeline) -> Result<gst::Pad, Error> {
+        if let Some(pad) = pipeline
+            .by_name("video_mux")
+            .and_then(|mux| mux.static_pad("sink"))
+        {
+            return Ok(pad);
+        }
+
+        let mux = pipeline
+            .by_name("mux")
+            .context("could not find mux element")?;
+        let mux_pad = mux
+            .request_pad_simple("sink_%d")
+            .context("could not get mux input pad")?;
+        self.track_mux_pad(&mux_pad);
+        Ok(mux_pad)
+    }
+
+    /// Links an Opus track to the muxer with depayloading and parsing only. The muxer takes one such track,
+    /// anything else is discarded.
+    fn pass_through(&self, pipeline: &gst::Pipeline, pad: &gst::Pad) -> Result<(), Error> {
+        let encoding = pad
+            .current_caps()
+            .and_then(|caps| caps.structure(0)?.get::<String>("encoding-name").ok())
+            .unwrap_or_default();
+        if !["opus", "multiopus"].contains(&encoding.to_lowercase().as_str()) {
+            error!("{encoding} audio can't be passed through to MPEG-TS, use --force-transcode");
+            return self.discard(pipeline, pad);
+        }
+        if self.passed_through.swap(true, Ordering::SeqCst) {
+            warn!("only one audio track is passed through, use --force-transcode to mix them all");
+            return self.discard(pipeline, pad);
+        }
+
+        let depay = ElementFactory::make("rtpopusdepay")
+            .build()
+            .context("could not create rtpopusdepay")?;
+        let parse = ElementFactory::make("opusparse")
+            .build()
+            .context("could not create opusparse")?;
+        let mut elements = vec![depay, parse];
+        if let Some(language) = &self.language {
+            let tags = ElementFactory::make("taginject")
+                .property("tags", format!("language-code={language}"))
+                .build()
+                .context("could not create taginject")?;
+            elements.push(tags);
+        }
+
+        pipeline
+            .add_many(&elements)
+            .context("could not add passthrough elements")?;
+        for elem in &elements {
+            elem.sync_state_with_parent()
+                .context("could not sync_state_with_parent")?;
+            self.track(elem);
+        }
+        gst::Element::link_many(&elements).context("could not link passthrough elements")?;
+
+        let mux = pipeline
+            .by_name("mux")
+            .context("could not find mux element")?;
+        let mux_pad = mux
+            .request_pad_simple("sink_%d")
+            .context("could not get mux input pad")?;
+        self.track_mux_pad(&mux_pad);
+        elements
+            .last()
+            .and_then(|elem| elem.static_pad("src"))
+            .context("could not get passthrough src pad")?
+            .link(&mux_pad)
+            .context("could not link passthrough audio to mux")?;
+
+        pad.link(&elements[0].static_pad("sink").unwrap())
+            .context("could not link input audio to rtpopusdepay")?;
+        Ok(())
+    }
+
+    /// Links a video track to the muxer. H.264 the output can carry is only depayloaded and parsed,
+    /// anything else is decoded and encoded with --video-codec.
+    fn link_video(&self, pipeline: &gst::Pipeline, pad: &gst::Pad) -> Result<(), Error> {
+        let caps = pad.current_caps();
+        let structure = caps.as_ref().and_then(|caps| caps.structure(0));
+        let encoding = structure
+            .and_then(|s| s.get::<String>("encoding-name").ok())
+            .unwrap_or_default();
+        let profile_level_id = structure.and_then(|s| s.get::<String>("profile-level-id").ok());
+
+        let description =
+            if self.video_passthrough && h264_compatible(&encoding, profile_level_id.as_deref()) {
+                info!("passing {encoding} video through");
+                // parameter sets in front of every keyframe, for receivers joining mid-stream
+                String::from("rtph264depay ! h264parse config-interval=-1")
+            } else {
+                info!("transcoding {encoding} video");
+                format!(
+                    "decodebin ! queue ! videoconvert ! {}",
+                    self.video_transcode
+                )
+            };
+
+        let leg = gst::parse::bin_from_description(&description, true)
+            .context("could not create video leg")?
+            .upcast::<gst::Element>();
+        pipeline
+            .add(&leg)
+            .context("could not add video leg to pipeline")?;
+        leg.sync_state_with_parent()
+            .context("could not sync state on video leg")?;
+        self.track(&leg);
+
+        let mux_pad = self.video_mux_pad(pipeline)?;
+        leg.static_pad("src")
+            .context("could not get video leg src pad")?
+            .link(&mux_pad)
+            .context("could not link video to mux")?;
+
+        pad.link(&leg.static_pad("sink").unwrap())
+            .context("could not link input video to video leg")?;
+        Ok(())
+    }
+
+    /// Decodes a video track into the next tile of the --mosaic, and lays out the grid again for it
+    fn tile_video(&self, pipeline: &gst::Pipeline, pad: &gst::Pad) -> Result<(), Error> {
+        let leg = gst::parse::bin_from_description("decodebin ! queue ! videoconvert", true)
+            .context("could not create mosaic leg")?
+            .upcast::<gst::Element>();
+        pipeline
+            .add(&leg)
+            .context("could not add mosaic leg to pipeline")?;
+        leg.sync_state_with_parent()
+            .context("could not sync state on mosaic leg")?;
+        self.track(&leg);
+
+        let mosaic = pipeline
+            .by_name("mosaic")
+            .context("could not find mosaic element")?;
+        let mosaic_pad = mosaic
+            .request_pad_simple("sink_%u")
+            .context("could not get mosaic input pad")?;
+        leg.static_pad("src")
+            .context("could not get mosaic leg src pad")?
+            .link(&mosaic_pad)
+            .context("could not link video to mosaic")?;
+
+        let mut pads = self.mosaic_pads.lock().unwrap();
+        pads.push(mosaic_pad);
+        let (width, height) = self.mosaic.unwrap_or_default();
+        for (pad, (xpos, ypos, width, height)) in pads.iter().zip(tiles(pads.len(), width, height))
+        {
+            pad.set_property("xpos", xpos);
+            pad.set_property("ypos", ypos);
+            pad.set_property("width", width);
+            pad.set_property("height", height);
+        }
+        info!("video track {} of the mosaic", pads.len());
+        drop(pads);
+
+        pad.link(&leg.static_pad("sink").unwrap())
+            .context("could not link input video to mosaic leg")?;
+        Ok(())
+    }
+
+    /// Sinks a track that isn't used
+    fn discard(&self, pipeline: &gst::Pipeline, pad: &gst::Pad) -> Result<(), Error> {
+        let fakesink = ElementFactory::make("fakesink")
+            .build()
+            .context("could not create fakesink")?;
+
+        pipeline
+            .add(&fakesink)
+            .context("could not add fakesink to pipeline")?;
+        fakesink
+            .sync_state_with_parent()
+            .context("could not sync state on fakesink")?;
+        self.track(&fakesink);
+        let fakesink_pad = fakesink
+            .static_pad("sink")
+            .context("could not get fakesink pad")?;
+        pad.link(&fakesink_pad)
+            .context("could not link to fakesink")?;
+        Ok(())
+    }
+
+    /// Channel routing for each audio leg, right after decoding
+    fn channel_map(&self) -> Result<Vec<gst::Element>, Error> {
+        let Some(in_channels) = self.channel_map.iter().max().map(|max| max + 1) else {
+            return Ok(Vec::new());
+        };
+        let out_channels = self.channel_map.len();
+
+        let rows: Vec<String> = self
+            .channel_map
+            .iter()
+            .map(|&from| {
+                let row: Vec<&str> = (0..in_channels)
+                    .map(|ch| {
+                        if ch == from {
+                            "(float)1.0"
+                        } else {
+                            "(float)0.0"
+                        }
+                    })
+                    .collect();
+                format!("<{}>", row.join(", "))
+            })
+            .collect();
+
+        let caps = ElementFactory::make("capsfilter")
+            .build()
+            .context("could not create capsfilter")?;
+        caps.set_property_from_str("caps", &format!("audio/x-raw,channels={in_channels}"));
+
+        let matrix = ElementFactory::make("audiomixmatrix")
+            .property_from_str("mode", "manual")
+            .property("in-channels", in_channels as u32)
+            .property("out-channels", out_channels as u32)
+            .build()
+            .context("could not create audiomixmatrix")?;
+        matrix.set_property_from_str("matrix", &format!("<{}>", rows.join(", ")));
+
+        Ok(vec![caps, matrix, audioconvert()?])
+    }
+
+    /// The --track-gain of the `number`-th audio track, the first one matching it
+    fn track_gain(&self, number: usize, msid: Option<&str>) -> Result<Option<gst::Element>, Error> {
+        let Some(gain) = self
+            .track_gain
+            .iter()
+            .find(|gain| gain.track.matches(number, msid))
+        else {
+            return Ok(None);
+        };
+        info!("audio track {number} at {:+}dB", gain.gain_db);
+
+        let volume = ElementFactory::make("volume")
+            .property("volume", gain.volume())
+            .build()
+            .context("could not create volume")?;
+        Ok(Some(volume))
+    }
+
+    /// The --pan of the `number`-th audio track, the first one matching it. audiopanorama takes mono or stereo,
+    /// the audioconvert behind it converts back to the channels of the mix.
+    fn pan(&self, number: usize, msid: Option<&str>) -> Result<Vec<gst::Element>, Error> {
+        let Some(pan) = self.pan.iter().find(|pan| pan.track.matches(number, msid)) else {
+            return Ok(Vec::new());
+        };
+        info!("audio track {number} panned to {:+.2}", pan.position);
+
+        let panorama = ElementFactory::make("audiopanorama")
+            .property("panorama", pan.position as f32)
+            .build()
+            .context("could not create audiopanorama")?;
+        Ok(vec![panorama, audioconvert()?])
+    }
+
+    /// Places the track, downmixed to mono, in its channel of the --bed, right after decoding
+    fn bed_channel(&self, position: BedPosition) -> Result<Vec<gst::Element>, Error> {
+        // the channels of the mix are in the order of the positions
+        let channel = self.bed.iter().filter(|&&other| other < position).count();
+        let rows: Vec<&str> = (0..self.bed.len())
+            .map(|ch| {
+                if ch == channel {
+                    "<(float)1.0>"
+                } else {
+                    "<(float)0.0>"
+                }
+            })
+            .collect();
+
+        let caps = ElementFactory::make("capsfilter")
+            .build()
+            .context("could not create capsfilter")?;
+        caps.set_property_from_str("caps", "audio/x-raw,channels=1");
+
+        let matrix = ElementFactory::make("audiomixmatrix")
+            .property_from_str("mode", "manual")
+            .property("in-channels", 1u32)
+            .property("out-channels", self.bed.len() as u32)
+            .property("channel-mask", bed_mask(&self.bed))
+            .build()
+            .context("could not create audiomixmatrix")?;
+        matrix.set_property_from_str("matrix", &format!("<{}>", rows.join(", ")));
+
+        Ok(vec![caps, matrix, audioconvert()?])
+    }
+
+    /// Fills the timestamp gaps of Opus DTX in each audio leg, before the mixer. audiorate inserts silence with
+    /// the right timestamps, which is replaced by low level noise for comfort-noise.
+    fn dtx_fill(&self) -> Result<gst::Element, Error> {
+        let audiorate = ElementFactory::make("audiorate")
+            .build()
+            .context("could not create audiorate")?;
+
+        if self.dtx_fill == DtxFill::ComfortNoise {
+            audiorate
+                .static_pad("src")
+                .context("could not get audiorate src pad")?
+                .add_probe(PadProbeType::BUFFER, |_, probe_info| {
+                    let Some(PadProbeData::Buffer(buffer)) = &mut probe_info.data else {
+                        return PadProbeReturn::Ok;
+                    };
+                    let buffer = buffer.make_mut();
+                    // the samples only have to differ between buffers, not between runs
+                    let mut seed = buffer.pts().map_or(1, |pts| pts.nseconds() as u32) | 1;
+                    let Ok(mut map) = buffer.map_writable() else {
+                        return PadProbeReturn::Ok;
+                    };
+
+                    // F32LE from the leg's capsfilter, all zero only where audiorate filled in
+                    if map.iter().all(|byte| *byte == 0) {
+                        for sample in map.chunks_exact_mut(4) {
+                            // xorshift, plenty random for noise
+                            seed ^= seed << 13;
+                            seed ^= seed >> 17;
+                            seed ^= seed << 5;
+                            let noise =
+                                (seed as f32 / u32::MAX as f32 * 2.0 - 1.0) * COMFORT_NOISE_LEVEL;
+                            sample.copy_from_slice(&noise.to_le_bytes());
+                        }
+                    }
+                    PadProbeReturn::Ok
+                });
+        }
+
+        Ok(audiorate)
+    }
+
+    /// Fades a leg's audio in as it starts and out once its input is being replaced, so a switch of inputs doesn't
+    /// click. `pad` carries the leg's F32LE audio to the mixer.
+    fn fade(&self, pad: &gst::Pad) {
+        if self.crossfade.is_zero() {
+            return;
+        }
+
+        let length = self.crossfade.as_nanos() as f64;
+        let fade_out = self.fade_out.lock().unwrap().clone();
+        // timestamps the fade in and the fade out started at
+        let starts: Mutex<(Option<u64>, Option<u64>)> = Mutex::new((None, None));
+
+        pad.add_probe(PadProbeType::BUFFER, move |pad, probe_info| {
+            let Some(PadProbeData::Buffer(buffer)) = &mut probe_info.data else {
+                return PadProbeReturn::Ok;
+            };
+            let (Some(pts), Some(info)) = (
+                buffer.pts(),
+                pad.current_caps()
+                    .and_then(|caps| gst_audio::AudioInfo::from_caps(&caps).ok()),
+            ) else {
+                return PadProbeReturn::Ok;
+            };
+
+            let pts = pts.nseconds();
+            let mut starts = starts.lock().unwrap();
+            let fade_in_start = *starts.0.get_or_insert(pts);
+            let fade_out_start = fade_out
+                .load(Ordering::Relaxed)
+                .then(|| *starts.1.get_or_insert(pts));
+
+            let gain = |time: u64| {
+                let fade_in = (time.saturating_sub(fade_in_start) as f64 / length).min(1.0);
+                let fade_out = fade_out_start.map_or(1.0, |start| {
+                    (1.0 - time.saturating_sub(start) as f64 / length).max(0.0)
+                });
+                (fade_in * fade_out) as f32
+            };
+
+            let end = pts + buffer.duration().map_or(0, |duration| duration.nseconds());
+            if gain(pts) == 1.0 && gain(end) == 1.0 {
+                return PadProbeReturn::Ok;
+            }
+
+            let Ok(mut map) = buffer.make_mut().map_writable() else {
+                return PadProbeReturn::Ok;
+            };
+            let frame_ns = 1e9 / info.rate() as f64;
+            for (index, frame) in map.chunks_exact_mut(info.bpf() as usize).enumerate() {
+                let gain = gain(pts + (index as f64 * frame_ns) as u64);
+                for sample in frame.chunks_exact_mut(4) {
+                    let value = f32::from_le_bytes(sample.try_into().unwrap()) * gain;
+                    sample.copy_from_slice(&value.to_le_bytes());
+                }
+            }
+
+            PadProbeReturn::Ok
+        });
+    }
+
+    /// Level processing for each audio leg, between resampling and the mixer
+    fn dynamics(&self) -> Result<Vec<gst::Element>, Error> {
+        let mut elements = Vec::new();
+
+        if let Some(threshold_db) = self.gate_threshold_db {
+            // an expander that pushes everything well below the threshold down to silence
+            let gate = ElementFactory::make("audiodynamic")
+                .property_from_str("mode", "expander")
+                .property_from_str("characteristics", "hard-knee")
+                .property("threshold", 10f32.powf(threshold_db.min(0.0) as f32 / 20.0))
+                .property("ratio", GATE_RATIO)
+                .build()
+                .context("could not create audiodynamic")?;
+            elements.push(gate);
+        }
+
+        if self.agc {
+            // webrtcdsp only takes S16LE interleaved or non-interleaved float, so convert around it
+            let agc = ElementFactory::make("webrtcdsp")
+                .property("echo-cancel", false)
+                .property("noise-suppression", false)
+                .property("high-pass-filter", false)
+                .property("gain-control", true)
+                .build()
+                .context("could not create webrtcdsp")?;
+
+            elements.extend([audioconvert()?, agc, audioconvert()?]);
+        }
+
+        Ok(elements)
+    }
+
+    /// Sends EOS through the pipeline after `delay`, so the muxer finishes the stream before `run` returns.
+    /// Stops the hard way if EOS has not come out at the sink after a few seconds.
+    fn end_stream(&self, pipeline: &gst::Pipeline, delay: Duration) {
+        let pipeline = pipeline.downgrade();
+        let ending = self.ending.clone();
+
+        std::thread::spawn(move || {
+            std::thread::sleep(delay);
+            let Some(pipeline) = pipeline.upgrade() else {
+                return;
+            };
+
+            if ending.swap(true, Ordering::SeqCst) {
+                return;
+            }
+
+            info!("ending stream");
+            pipeline.send_event(gst::event::Eos::new());
+
+            std::thread::sleep(Duration::from_secs(5));
+            post_stop(&pipeline);
+        });
+    }
+
+    /// Fails the pipeline when no RTP has arrived `timeout` after starting, or after subscribing again. With
+    /// --on-input-eos reconnect, the input subscribes again instead and is given another `timeout`.
+    fn watch_startup(&self, pipeline: &gst::Pipeline, timeout: Duration) {
+        let input = self.clone();
+        let pipeline = pipeline.downgrade();
+
+        std::thread::spawn(move || {
+            loop {
+                std::thread::sleep(timeout);
+                let Some(pipeline) = pipeline.upgrade() else {
+                    return;
+                };
+                if input.startup.first_rtp().is_some()
+                    || input.ending.load(Ordering::SeqCst)
+                    || pipeline.current_state() == gst::State::Null
+                {
+                    return;
+                }
+
+                if input.on_eos == OnInputEos::Reconnect {
+                    warn!(
+                        "no media received within {}s, re-subscribing",
+                        timeout.as_secs()
+                    );
+                    let _ = input.on_eos(&pipeline);
+                    continue;
+                }
+
+                let msg = gst::message::Error::builder(
+                    gst::ResourceError::Read,
+                    &format!(
+                        "no media received from the WHEP input within {}s",
+                        timeout.as_secs()
+                    ),
+                )
+                .src(&pipeline)
+                .build();
+                let _ = pipeline.post_message(msg);
+                return;
+            }
+        });
+    }
+
+    /// Called from the streaming thread when one of the input's pads ends
+    fn on_eos(&self, pipeline: &gst::Pipeline) -> PadProbeReturn {
+        if self.ending.load(Ordering::SeqCst) {
+            return PadProbeReturn::Ok;
+        }
+
+        match self.on_eos {
+            OnInputEos::Exit => {
+                info!("input ended, stopping");
+                self.end_stream(pipeline, Duration::ZERO);
+                PadProbeReturn::Ok
+            }
+            OnInputEos::Hold => {
+                info!("input ended, holding the output");
+                PadProbeReturn::Drop
+            }
+            OnInputEos::Reconnect => {
+                // every track of the input ends, the first one triggers the reconnect
+                if !self.reconnecting.swap(true, Ordering::SeqCst) {
+                    // an input that still plays, e.g. on a network change, fades out before it is torn down
+                    self.fade_out.lock().unwrap().store(true, Ordering::Relaxed);
+
+                    let input = self.clone();
+                    let pipeline = pipeline.clone();
+                    std::thread::spawn(move || {
+                        std::thread::sleep(Duration::from_secs(1));
+                        if let Err(err) = input.reconnect(&pipeline) {
+                            error!("{err}");
+                            post_stop(&pipeline);
+                        }
+                        input.reconnecting.store(false, Ordering::SeqCst);
+                    });
+                }
+                PadProbeReturn::Drop
+            }
+        }
+    }
+
+    /// Tears down the ended input and its decode legs and subscribes again with a new input element
+    fn reconnect(&self, pipeline: &gst::Pipeline) -> Result<(), Error> {
+        let attempt = self.reconnects.fetch_add(1, Ordering::Relaxed) + 1;
+        let _entered = tracing::info_span!(parent: &self.span, "reconnect", attempt).entered();
+        info!("input ended, re-subscribing");
+
+        self.remove(pipeline);
+        self.subscribe(pipeline)
+    }
+
+    /// Removes the input element and its decode legs, the output keeps running on the mix
+    fn remove(&self, pipeline: &gst::Pipeline) {
+        if let Some(old_input) = pipeline.by_name("input") {
+            let _ = old_input.set_state(gst::State::Null);
+            let _ = pipeline.remove(&old_input);
+        }
+        self.leave();
+
+        for elem in self.legs.lock().unwrap().drain(..) {
+            let _ = elem.set_state(gst::State::Null);
+            let _ = pipeline.remove(&elem);
+        }
+
+        *self.fade_out.lock().unwrap() = Arc::new(AtomicBool::new(false));
+        self.audio_tracks.store(0, Ordering::Relaxed);
+
+        // there is no mixer with --disable-audio
+        if let Some(mixer) = pipeline.by_name("mixer") {
+            for pad in self.mixer_pads.lock().unwrap().drain(..) {
+                mixer.release_request_pad(&pad);
+            }
+        }
+
+        if let Some(mux) = pipeline.by_name("mux") {
+            for pad in self.mux_pads.lock().unwrap().drain(..) {
+                mux.release_request_pad(&pad);
+            }
+        }
+
+        if let Some(mosaic) = pipeline.by_name("mosaic") {
+            for pad in self.mosaic_pads.lock().unwrap().drain(..) {
+                mosaic.release_request_pad(&pad);
+            }
+        }
+    }
+
+    /// Adds a new input element, which subscribes to the WHEP endpoint
+    fn subscribe(&self, pipeline: &gst::Pipeline) -> Result<(), Error> {
+        let new_input = gst::parse::launch(&self.description)
+            .map_err(|err| Error::Pipeline(format!("Failed to create input: {err}")))?;
+        pipeline
+            .add(&new_input)
+            .map_err(|err| Error::Pipeline(format!("Failed to add input: {err}")))?;
+        self.authenticate(&new_input);
+        self.signal(&new_input);
+
+        connect_input(pipeline, self)?;
+        self.discontinuity.store(true, Ordering::Relaxed);
+
+        new_input
+            .sync_state_with_parent()
+            .map_err(|err| Error::Pipeline(format!("Failed to start input: {err}")))?;
+
+        Ok(())
+    }
+}
+
+/// Sets the bearer token of whepsrc, or of whepclientsrc's signaller. Test source inputs have none.
+fn set_auth_token(input: &gst::Element, token: &str) {
+    let target = if input.find_property("auth-token").is_some() {
+        Some(input.clone().upcast::<gst::glib::Object>())
+    } else {
+        input
+            .dynamic_cast_ref::<gst::ChildProxy>()
+            .and_then(|proxy| proxy.child_by_name("signaller"))
+    };
+
+    if let Some(target) = target
+        && target.find_property("auth-token").is_some()
+    {
+        target.set_property("auth-token", token);
+    }
+}
+
+/// The gateway's own WHEP signalling on a webrtcbin input, for --ice-restart. whepclientsrc keeps the session
+/// resource to itself, this knows it, so a failed connection can restart ICE with a PATCH to it.
+#[derive(Clone)]
+struct WhepClient {
+    endpoint: String,
+    audio: bool,
+    video: bool,
+    nack: bool,
+    /// Session of the current input, None until it has subscribed and once it is left
+    session: Arc<Mutex<Option<whep::Session>>>,
+    /// Set while an ICE restart is under way, a connection failing again meanwhile is part of it
+    restarting: Arc<AtomicBool>,
+}
+
+impl WhepClient {
+    fn new(args: &Args) -> Self {
+        Self {
+            endpoint: args.input_url.clone().unwrap_or_default(),
+            audio: !args.disable_audio && args.media != Media::Video,
+            video: args.media != Media::Audio,
+            nack: !args.no_nack,
+            session: Arc::default(),
+            restarting: Arc::default(),
+        }
+    }
+
+    /// Adds the transceivers to receive with to a new webrtcbin input, and subscribes once it asks for negotiation
+    fn connect(&self, webrtcbin: &gst::Element, auth_token: &Arc<Mutex<Option<String>>>) {
+        for (receive, caps) in [(self.audio, WHEP_AUDIO_CAPS), (self.video, WHEP_VIDEO_CAPS)] {
+            if !receive {
+                continue;
+            }
+            let caps = caps
+                .parse::<gst::Caps>()
+                .expect("invalid whep receive caps");
+            let transceiver = webrtcbin.emit_by_name::<gst_webrtc::WebRTCRTPTransceiver>(
+                "add-transceiver",
+                &[&gst_webrtc::WebRTCRTPTransceiverDirection::Recvonly, &caps],
+            );
+            transceiver.set_property("do-nack", self.nack);
+        }
+
+        let client = self.clone();
+        let auth_token = auth_token.clone();
+        webrtcbin.connect("on-negotiation-needed", false, move |values| {
+            let webrtcbin = values[0]
+                .get::<gst::Element>()
+                .expect("on-negotiation-needed without webrtcbin");
+            let client = client.clone();
+            let token = auth_token.lock().unwrap().clone();
+
+            // webrtcbin creates the offer on the thread that emits this
+            std::thread::spawn(move || {
+                if let Err(err) = client.subscribe(&webrtcbin, token.as_deref()) {
+                    // classified by its message, e.g. as an auth error on a 401
+                    let message = gst::message::Error::builder(
+                        gst::ResourceError::Failed,
+                        &format!("could not subscribe to the WHEP endpoint: {err}"),
+                    )
+                    .src(&webrtcbin)
+                    .build();
+                    let _ = webrtcbin.post_message(message);
+                }
+            });
+            None
+        });
+    }
+
+    /// POSTs an offer with all ICE candidates to the endpoint and applies the answer
+    fn subscribe(&self, webrtcbin: &gst::Element, token: Option<&str>) -> Result<(), String> {
+        let offer = create_offer(webrtcbin)?;
+        call(webrtcbin, "set-local-description", &offer)?;
+        let offer = gathered_description(webrtcbin)?;
+
+        let (session, answer) = whep::subscribe(&self.endpoint, token, &offer)?;
+        info!("whep session at {}", session.url);
+        *self.session.lock().unwrap() = Some(session);
+
+        let answer = session_description(gst_webrtc::WebRTCSDPType::Answer, &answer)?;
+        call(webrtcbin, "set-remote-description", &answer)?;
+        Ok(())
+    }
+
+    /// Restarts ICE with new credentials, which the session resource gets in a PATCH and answers with its own.
+    /// webrtcbin doesn't restart ICE by itself, it keeps the credentials of its first offer, so they are replaced
+    /// in the offer and the answer is the previous one with the endpoint's new credentials and candidates. Fails
+    /// when the endpoint doesn't restart ICE, or the connection isn't back within `ICE_RESTART_TIMEOUT`.
+    fn restart_ice(&self, webrtcbin: &gst::Element, token: Option<&str>) -> Result<(), String> {
+        use gst_webrtc::WebRTCPeerConnectionState as State;
+
+        let session = self
+            .session
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| String::from("there is no whep session"))?;
+        let description = |property: &str| {
+            webrtcbin
+                .property::<Option<gst_webrtc::WebRTCSessionDescription>>(property)
+                .and_then(|description| description.sdp().as_text().ok())
+                .ok_or_else(|| format!("webrtcbin has no {property}"))
+        };
+        let previous_offer = description("local-description")?;
+        let previous_answer = description("remote-description")?;
+
+        let offer = create_offer(webrtcbin)?
+            .sdp()
+            .as_text()
+            .map_err(|err| err.to_string())?;
+        let (ufrag, pwd) = whep::ice_credentials();
+        let offer = whep::set_ice_credentials(&offer, &ufrag, &pwd);
+        call(
+            webrtcbin,
+            "set-local-description",
+            &session_description(gst_webrtc::WebRTCSDPType::Offer, &offer)?,
+        )?;
+        let offer = gathered_description(webrtcbin)?;
+
+        info!("restarting ice with the whep session at {}", session.url);
+        let sdpfrag = session.restart_ice(token, &whep::sdpfrag(&offer, &previous_offer))?;
+        let answer = whep::apply_sdpfrag(&previous_answer, &sdpfrag)
+            .ok_or_else(|| String::from("the whep session answered without ice credentials"))?;
+        call(
+            webrtcbin,
+            "set-remote-description",
+            &session_description(gst_webrtc::WebRTCSDPType::Answer, &answer)?,
+        )?;
+
+        let deadline = Instant::now() + ICE_RESTART_TIMEOUT;
+        while webrtcbin.property::<State>("connection-state") != State::Connected {
+            if Instant::now() >= deadline {
+                return Err(format!(
+                    "not connected again within {}s",
+                    ICE_RESTART_TIMEOUT.as_secs()
+                ));
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        Ok(())
+    }
+
+    /// Ends the current session with a DELETE on a thread of its own, returned to wait for it
+    fn leave(&self, token: Option<String>) -> Option<std::thread::JoinHandle<()>> {
+        let session = self.session.lock().unwrap().take()?;
+        Some(std::thread::spawn(move || session.leave(token.as_deref())))
+    }
+}
+
+fn create_offer(webrtcbin: &gst::Element) -> Result<gst_webrtc::WebRTCSessionDescription, String> {
+    call(webrtcbin, "create-offer", &None::<gst::Structure>)?
+        .and_then(|reply| {
+            reply
+                .get::<gst_webrtc::WebRTCSessionDescription>("offer")
+                .ok()
+        })
+        .ok_or_else(|| String::from("webrtcbin did not create an offer"))
+}
+
+fn session_description(
+    sdp_type: gst_webrtc::WebRTCSDPType,
+    sdp: &str,
+) -> Result<gst_webrtc::WebRTCSessionDescription, String> {
+    let sdp = gst_webrtc::gst_sdp::SDPMessage::parse_buffer(sdp.as_bytes())
+        .map_err(|_| format!("invalid sdp {sdp_type:?}").to_lowercase())?;
+    Ok(gst_webrtc::WebRTCSessionDescription::new(sdp_type, sdp))
+}
+
+/// Emits one of webrtcbin's promise based action signals and waits for the reply
+pub(crate) fn call(
+    webrtcbin: &gst::Element,
+    signal: &str,
+    arg: &dyn ToValue,
+) -> Result<Option<gst::Structure>, String> {
+    let promise = gst::Promise::new();
+    webrtcbin.emit_by_name::<()>(signal, &[arg, &promise]);
+
+    if promise.wait() != gst::PromiseResult::Replied {
+        return Err(format!("{signal} was not answered"));
+    }
+
+    let reply = promise.get_reply().map(|reply| reply.to_owned());
+    if let Some(err) = reply
+        .as_ref()
+        .and_then(|reply| reply.get::<gst::glib::Error>("error").ok())
+    {
+        return Err(format!("{signal} failed: {err}"));
+    }
+
+    Ok(reply)
+}
+
+/// The local description once ICE has gathered all candidates, or `ICE_GATHERING_TIMEOUT` has passed
+pub(crate) fn gathered_description(webrtcbin: &gst::Element) -> Result<String, String> {
+    let deadline = Instant::now() + ICE_GATHERING_TIMEOUT;
+    while webrtcbin.property::<gst_webrtc::WebRTCICEGatheringState>("ice-gathering-state")
+        != gst_webrtc::WebRTCICEGatheringState::Complete
+        && Instant::now() < deadline
+    {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    let local = webrtcbin
+        .property::<Option<gst_webrtc::WebRTCSessionDescription>>("local-description")
+        .ok_or_else(|| String::from("no local description"))?;
+
+    local.sdp().as_text().map_err(|err| err.to_string())
+}
+
+/// --record-format mkv-source: the WHEP tracks as they arrive, only depayloaded, in a Matroska file. The muxer
+/// takes no new inputs once it has started writing, so the legs into it outlive the input, and a track of the same
+/// codec after a reconnect continues in a leg that has lost its own.
+#[derive(Clone)]
+struct SourceRecording {
+    mux: gst::Element,
+    /// Encoding name and leg of every track recorded so far
+    legs: Arc<Mutex<Vec<(String, gst::Element)>>>,
+}
+
+impl SourceRecording {
+    fn new(mux: gst::Element) -> Self {
+        Self {
+            mux,
+            legs: Arc::default(),
+        }
+    }
+
+    /// Tees a WHEP track off on its way to the rest of the pipeline into the recording
+    fn record(
+        &self,
+        pipeline: &gst::Pipeline,
+        input: &Input,
+        pad: &gst::Pad,
+        encoding: &str,
+    ) -> Result<(), Error> {
+        let encoding = encoding.to_uppercase();
+        let depay = match encoding.as_str() {
+            "OPUS" | "MULTIOPUS" => "rtpopusdepay ! opusparse",
+            "H264" => "rtph264depay ! h264parse",
+            "VP8" => "rtpvp8depay",
+            "VP9" => "rtpvp9depay",
+            _ => {
+                warn!("{encoding} can't be recorded, leaving it out");
+                return Ok(());
+            }
+        };
+
+        let Some(leg) = self.leg(pipeline, &encoding, depay)? else {
+            return Ok(());
+        };
+        let peer = pad.peer().context("the track to record is not linked")?;
+
+        let tee = ElementFactory::make("tee")
+            .property("allow-not-linked", true)
+            .build()
+            .context("could not create tee")?;
+        pipeline
+            .add(&tee)
+            .context("could not add tee to pipeline")?;
+        tee.sync_state_with_parent()
+            .context("could not sync state on tee")?;
+        // removed with the input, which frees the recording leg for the next one
+        input.track(&tee);
+
+        let _ = pad.unlink(&peer);
+        pad.link(&tee.static_pad("sink").unwrap())
+            .context("could not link input to tee")?;
+        tee.request_pad_simple("src_%u")
+            .context("could not get tee src pad")?
+            .link(&peer)
+            .context("could not link tee to the track's branch")?;
+        tee.request_pad_simple("src_%u")
+            .context("could not get tee src pad")?
+            .link(&leg.static_pad("sink").unwrap())
+            .context("could not link tee to the recording")?;
+        Ok(())
+    }
+
+    /// A free leg for `encoding`, or a new one
+    fn leg(
+        &self,
+        pipeline: &gst::Pipeline,
+        encoding: &str,
+        depay: &str,
+    ) -> Result<Option<gst::Element>, Error> {
+        let mut legs = self.legs.lock().unwrap();
+        if let Some((_, leg)) = legs.iter().find(|(leg_encoding, leg)| {
+            leg_encoding == encoding && !leg.static_pad("sink").unwrap().is_linked()
+        }) {
+            return Ok(Some(leg.clone()));
+        }
+
+        // leaky, a recording that can't keep up must not hold up the track
+        let leg =
+            gst::parse::bin_from_description(&format!("queue leaky=downstream ! {depay}"), true)
+                .context("could not create recording leg")?
+                .upcast::<gst::Element>();
+        pipeline
+            .add(&leg)
+            .context("could not add recording leg to pipeline")?;
+        leg.sync_state_with_parent()
+            .context("could not sync state on recording leg")?;
+
+        if leg.link(&self.mux).is_err() {
+            warn!("a new {encoding} track can't be added to the recording once it has started");
+            let _ = leg.set_state(gst::State::Null);
+            let _ = pipeline.remove(&leg);
+            return Ok(None);
+        }
+
+        legs.push((encoding.to_string(), leg.clone()));
+        Ok(Some(leg))
+    }
+}
+
+/// Watches the running time of a WHEP track against the pipeline clock. A source restart or timestamp roll-over
+/// shows as a jump of more than `RESYNC_THRESHOLD` from the track's usual delay, which would otherwise reach the
+/// muxer as a huge PCR jump, or as buffers the mixer waits for or drops. The pad offset is moved by the jump so
+/// the running time carries on where it was, and the buffer is marked as a discontinuity.
+fn connect_resync(pipeline: &gst::Pipeline, pad: &gst::Pad, discontinuity: &Arc<AtomicBool>) {
+    let pipeline = pipeline.clone();
+    let discontinuity = discontinuity.clone();
+    // usual delay of the track behind the clock, in ns
+    let delay: Mutex<Option<f64>> = Mutex::new(None);
+
+    pad.add_probe(PadProbeType::BUFFER, move |pad, probe_info| {
+        let Some(PadProbeData::Buffer(buffer)) = &mut probe_info.data else {
+            return PadProbeReturn::Ok;
+        };
+        let running_time = buffer.pts().and_then(|pts| {
+            pad.sticky_event::<gst::event::Segment>(0)?
+                .segment()
+                .downcast_ref::<gst::ClockTime>()?
+                .to_running_time(pts)
+        });
+        let (Some(running_time), Some(now)) = (running_time, pipeline.current_running_time())
+        else {
+            return PadProbeReturn::Ok;
+        };
+
+        let current =
+            now.nseconds() as f64 - (running_time.nseconds() as i64 + pad.offset()) as f64;
+        let mut delay = delay.lock().unwrap();
+        let usual = *delay.get_or_insert(current);
+
+        let jump = current - usual;
+        if jump.abs() < RESYNC_THRESHOLD.nseconds() as f64 {
+            *delay = Some(usual + RESYNC_SMOOTHING * jump);
+            return PadProbeReturn::Ok;
+        }
+
+        warn!(
+            "timestamps of {} jumped by {:+.3}s, resynchronizing",
+            pad.name(),
+            -jump / 1e9
+        );
+        pad.set_offset(pad.offset() + jump as i64);
+        buffer.make_mut().set_flags(gst::BufferFlags::DISCONT);
+        discontinuity.store(true, Ordering::Relaxed);
+
+        PadProbeReturn::Ok
+    });
+}
+
+/// A failed WebRTC connection sends no EOS, the input just stops. With --ice-restart, ICE is restarted first. With
+/// --on-input-eos hold or reconnect it is handled like the input ending. Otherwise the gateway stops with a network
+/// error rather than the clean exit of an ended stream.
+fn connect_connection_lost(pipeline: &gst::Pipeline, input: &Input) {
+    let input = input.clone();
+    let weak_pipeline = pipeline.downgrade();
+    pipeline.connect_deep_element_added(move |_, _, elem| {
+        if elem.type_().name() != "GstWebRTCBin" {
+            return;
+        }
+
+        let pipeline = weak_pipeline.clone();
+        let input = input.clone();
+        elem.connect_notify(Some("connection-state"), move |elem, _| {
+            let state = elem.property::<gst_webrtc::WebRTCPeerConnectionState>("connection-state");
+            if state != gst_webrtc::WebRTCPeerConnectionState::Failed {
+                return;
+            }
+            warn!("webrtc connection failed");
+
+            let Some(whep) = &input.whep else {
+                if let Some(pipeline) = pipeline.upgrade() {
+                    connection_failed(&pipeline, elem, &input);
+                }
+                return;
+            };
+            if whep.restarting.swap(true, Ordering::SeqCst) {
+                return;
+            }
+
+            let (whep, input, pipeline, elem) =
+                (whep.clone(), input.clone(), pipeline.clone(), elem.clone());
+            // webrtcbin creates the restart's offer on the thread that notifies this
+            std::thread::spawn(move || {
+                let token = input.auth_token.lock().unwrap().clone();
+                let result = whep.restart_ice(&elem, token.as_deref());
+                whep.restarting.store(false, Ordering::SeqCst);
+
+                match result {
+                    Ok(()) => info!("webrtc connection restored by an ice restart"),
+                    Err(err) => {
+                        warn!("ice restart failed: {err}");
+                        if let Some(pipeline) = pipeline.upgrade() {
+                            connection_failed(&pipeline, &elem, &input);
+                        }
+                    }
+                }
+            });
+        });
+    });
+}
+
+fn connection_failed(pipeline: &gst::Pipeline, webrtcbin: &gst::Element, input: &Input) {
+    if input.on_eos != OnInputEos::Exit {
+        let _ = input.on_eos(pipeline);
+        return;
+    }
+
+    // classified as a network error by element_error
+    let message = gst::message::Error::builder(
+        gst::ResourceError::Read,
+        "WebRTC connection to the WHEP source failed",
+    )
+    .src(webrtcbin)
+    .build();
+    let _ = pipeline.post_message(message);
+}
+
+fn connect_input(pipeline: &gst::Pipeline, input: &Input) -> Result<(), Error> {
+    let pipeline_clone = pipeline.clone();
+    let input_clone = input.clone();
+
+    // there is no mixer with audio passthrough
+    let mixer_clone = pipeline.by_name("mixer");
+
+    let input_whep_bin = pipeline
+        .by_name("input")
+        .ok_or_else(|| Error::Pipeline(String::from("could not get whep input bin")))?;
+
+    input_whep_bin.connect_pad_added(move |elem, pad| {
+        let _entered =
+            tracing::info_span!(parent: &input_clone.span, "pad_setup", pad = %pad.name())
+                .entered();
+        info!(
+            "pad added on {} named '{}': '{}'",
+            elem.type_(),
+            elem.name(),
+            pad.name()
+        );
+
+        let pipeline_eos = pipeline_clone.clone();
+        let input_eos = input_clone.clone();
+        pad.add_probe(
+            PadProbeType::EVENT_DOWNSTREAM,
+            move |_, probe_info| match &probe_info.data {
+                Some(PadProbeData::Event(event)) if event.type_() == gst::EventType::Eos => {
+                    input_eos.on_eos(&pipeline_eos)
+                }
+                _ => PadProbeReturn::Ok,
+            },
+        );
+
+        connect_resync(&pipeline_clone, pad, &input_clone.discontinuity);
+
+        let slate = input_clone.slate.clone();
+        let pipeline_clone = pipeline_clone.clone();
+        let mixer_clone = mixer_clone.clone();
+        let input_clone = input_clone.clone();
+
+        pad.add_probe(PadProbeType::BUFFER, move |pad, _probe_info| {
+            // the caps event can still be on its way, the branch is then built for a later buffer
+            let Some((caps, media_type)) = pad.current_caps().and_then(|caps| {
+                let media_type = caps.structure(0)?.get::<String>("media").ok()?;
+                Some((caps, media_type))
+            }) else {
+                tracing::debug!("no caps on {} yet, dropping a buffer", pad.name());
+                return PadProbeReturn::Drop;
+            };
+            let ssrc = caps.structure(0).and_then(|s| s.get::<u32>("ssrc").ok());
+
+            let _entered = tracing::info_span!(
+                parent: &input_clone.span,
+                "branch",
+                pad = %pad.name(),
+                media = %media_type,
+                ssrc = ?ssrc,
+            )
+            .entered();
+            info!("getting {media_type} track");
+            input_clone.startup.mark_first_rtp();
+            if let Err(err) = input_clone.link_track(
+                &pipeline_clone,
+                mixer_clone.as_ref(),
+                pad,
+                &media_type,
+                &caps,
+            ) {
+                branch_failed(&pipeline_clone, pad, &err);
+            }
+
+            gstreamer::PadProbeReturn::Remove
+        });
+
+        // added after the linking probe, so the track is still linked while a slate is up
+        pad.add_probe(PadProbeType::BUFFER, move |_, _| {
+            if slate.load(Ordering::Relaxed) {
+                PadProbeReturn::Drop
+            } else {
+                PadProbeReturn::Ok
+            }
+        });
+    });
+
+    Ok(())
+}
+
+fn audioconvert() -> Result<gst::Element, Error> {
+    ElementFactory::make("audioconvert")
+        .build()
+        .context("could not create audioconvert")
+}
+
+/// Hard limiter on the mix, so summed inputs can't clip the encoder. Empty without --limiter-ceiling.
+fn limiter(args: &Args) -> String {
+    match args.limiter_ceiling {
+        Some(ceiling_db) => format!(
+            "audiodynamic name=limiter mode=compressor characteristics=hard-knee ratio=0 threshold={} ! ",
+            10f64.powf(ceiling_db.min(0.0) / 20.0)
+        ),
+        None => String::new(),
+    }
+}
+
+/// The encoder bitrate of the profile, capped by --max-bitrate
+fn audio_bitrate(args: &Args, settings: &ProfileSettings) -> u32 {
+    args.max_bitrate
+        .map_or(settings.aac_bitrate, |max| max.min(settings.aac_bitrate))
+}
+
+/// The "bitrate" property of an encoder in bit/s, whatever integer type it is, `unit` bit/s per step
+fn encoder_bitrate(encoder: &gst::Element, unit: u32) -> Option<u32> {
+    encoder.find_property("bitrate")?;
+    let value = encoder.property_value("bitrate").transform::<u32>().ok()?;
+    value
+        .get::<u32>()
+        .ok()
+        .map(|bitrate| bitrate.saturating_mul(unit))
+}
+
+/// Sets the "bitrate" property of a running encoder, if it is one that takes changes while playing
+fn set_encoder_bitrate(encoder: &gst::Element, unit: u32, bitrate: u32) -> Result<(), Error> {
+    let name = encoder
+        .factory()
+        .map_or(String::from("the encoder"), |factory| {
+            factory.name().to_string()
+        });
+    let pspec = encoder
+        .find_property("bitrate")
+        .ok_or_else(|| Error::Config(format!("{name} has no bitrate")))?;
+    if !pspec.flags().contains(gst::PARAM_FLAG_MUTABLE_PLAYING) {
+        return Err(Error::Config(format!(
+            "{name} can't change its bitrate while running"
+        )));
+    }
+
+    let value = (bitrate / unit)
+        .to_value()
+        .transform_with_type(pspec.value_type())
+        .ok()
+        .filter(|value| pspec.value_is_valid(value))
+        .ok_or_else(|| Error::Config(format!("bitrate {bitrate} is out of range for {name}")))?;
+    encoder.set_property_from_value("bitrate", &value);
+    Ok(())
+}
+
+/// AAC and optional video in MPEG-TS, sent over SRT. With passthrough the audio is linked to the muxer in `connect_input`,
+/// with --disable-audio there is none.
+fn srt_output(args: &Args, settings: &ProfileSettings, passthrough: bool) -> String {
+    let audio = if passthrough || args.disable_audio {
+        String::new()
+    } else {
+        format!(
+            "{audio_encoder} ! {language}mux. ",
+            audio_encoder = audio_encoder(args, settings),
+            // mpegtsmux turns the language tag into the ISO 639 descriptor of the audio stream
+            language = args.audio_lang.as_ref().map_or(String::new(), |lang| {
+                format!("taginject tags=\"language-code={lang}\" ! ")
+            }),
+        )
+    };
+
+    let (tee, recording) = match &args.record_path {
+        Some(path) if args.record_format != RecordFormat::MkvSource => (
+            "tee name=record_tee ! ",
+            format!(" record_tee. ! {}", recording(args.record_format, path)),
+        ),
+        _ => ("", String::new()),
+    };
+
+    format!(
+        "{audio}{video}{teletext}\
+        mpegtsmux name=mux alignment=7 pat-interval={table_interval} pmt-interval={table_interval} ! {tee}{queue} ! srtsink name=output latency={srt_latency} uri=\"{output_url}\" sync={sync} wait-for-connection={wait_for_connection}{recording}",
+        video = generated_video(args, settings),
+        teletext = teletext(args),
+        // 90kHz clock
+        table_interval = settings.ts_table_interval_ms * 90,
+        queue = queue(args),
+        // before the uri, so a latency parameter in the url wins
+        srt_latency = settings.srt_latency_ms,
+        output_url = args.output_url,
+        sync = args.sync,
+        wait_for_connection = args.wait_for_connection,
+    )
+}
+
+/// The --record-path branch off the transport stream. For MP4 and MKV it is parsed back into its elementary
+/// streams, linked to the muxer in `connect_recording`, so the file holds exactly what the SRT output sends.
+fn recording(format: RecordFormat, path: &str) -> String {
+    // leaky, a recording that can't keep up must not hold up the output
+    let queue = "queue name=record_queue leaky=downstream";
+    let mux = match format {
+        RecordFormat::Ts => {
+            return format!("{queue} ! filesink name=record location=\"{path}\"");
+        }
+        // faststart moves the index in front of the media when the file is finished
+        RecordFormat::Mp4 => "mp4mux name=record_mux faststart=true",
+        RecordFormat::Mkv | RecordFormat::MkvSource => "matroskamux name=record_mux",
+    };
+
+    format!("{queue} ! parsebin name=record_parse {mux} ! filesink name=record location=\"{path}\"")
+}
+
+/// Tees the mix off to the --dump-audio file, ahead of the rest of the output
+fn audio_dump(path: Option<&str>) -> String {
+    match path {
+        // leaky, so a slow disk doesn't glitch the output being debugged
+        Some(path) => format!(
+            "tee name=dump_tee ! queue name=dump_queue leaky=downstream ! wavenc ! filesink name=dump location=\"{path}\" dump_tee. ! "
+        ),
+        None => String::new(),
+    }
+}
+
+/// Tees the mix off to the --captions transcription, as 16 kHz mono, what speech models take
+fn captions_tap(captions: bool) -> String {
+    if !captions {
+        return String::new();
+    }
+
+    // leaky, a transcription that can't keep up must not hold up the output
+    format!(
+        "tee name=captions_tee ! queue name=captions_queue leaky=downstream ! audioconvert ! audioresample ! audio/x-raw,format=S16LE,rate={},channels=1 ! appsink name=captions_audio sync=false captions_tee. ! ",
+        captions::SAMPLE_RATE
+    )
+}
+
+/// Audio, and video if there is any, each in a CMAF track of its own, handed to the packager in `connect_cmaf`
+fn cmaf_output(args: &Args, settings: &ProfileSettings, mux_video: bool) -> String {
+    let ms = 1_000_000;
+    let mut fragmenting = format!("fragment-duration={}", args.cmaf_segment_duration * ms);
+    if args.cmaf_chunk_duration > 0 {
+        fragmenting.push_str(&format!(
+            " chunk-duration={}",
+            args.cmaf_chunk_duration * ms
+        ));
+    }
+    // async=false, a track that never comes must not hold up the pipeline
+    let sink = "sync=false async=false buffer-list=true";
+
+    let video = if mux_video {
+        format!(" cmafmux name=video_mux {fragmenting} ! appsink name=cmaf_video {sink}")
+    } else {
+        String::new()
+    };
+
+    format!(
+        "{audio_encoder} ! cmafmux name=audio_mux {fragmenting} ! appsink name=cmaf_audio {sink}{video}",
+        audio_encoder = audio_encoder(args, settings),
+    )
+}
+
+/// Audio encoder and parser for --audio-codec. Dialnorm is written into every AC-3/E-AC-3 frame.
+fn audio_encoder(args: &Args, settings: &ProfileSettings) -> String {
+    let bitrate = audio_bitrate(args, settings);
+    let dialnorm = args
+        .dialnorm
+        .map_or(String::new(), |dialnorm| format!(" dialnorm={dialnorm}"));
+
+    match args.audio_codec {
+        AudioCodec::Aac => aac_encoder(bitrate),
+        AudioCodec::Ac3 => format!(
+            "audioconvert ! avenc_ac3 name=audio_encoder bitrate={bitrate}{dialnorm} ! ac3parse"
+        ),
+        AudioCodec::Eac3 => format!(
+            "audioconvert ! avenc_eac3 name=audio_encoder bitrate={bitrate}{dialnorm} ! ac3parse"
+        ),
+        AudioCodec::Opus => {
+            let bitrate_type = match args.audio_rate_control {
+                Some(RateControl::Cbr) => " bitrate-type=cbr",
+                Some(RateControl::Vbr) => " bitrate-type=vbr",
+                // rejected in build
+                Some(RateControl::Cq) | None => "",
+            };
+            format!("opusenc name=audio_encoder bitrate={bitrate}{bitrate_type} ! opusparse")
+        }
+    }
+}
+
+/// AAC encoder and parser, avenc_aac or the first of its substitutes there is
+fn aac_encoder(bitrate: u32) -> String {
+    let encoder = substitute("avenc_aac");
+    // the substitutes take integer samples, not the float mix
+    let convert = if encoder == "avenc_aac" {
+        ""
+    } else {
+        "audioconvert ! "
+    };
+    format!("{convert}{encoder} name=audio_encoder bitrate={bitrate} ! aacparse")
+}
+
+/// Whether the WHEP audio can go to the muxer as it is: Opus into MPEG-TS over SRT, with nothing that needs the
+/// decoded audio. Holding or reconnecting the input needs the mix to keep the output going.
+fn audio_passthrough(args: &Args) -> bool {
+    args.audio_codec == AudioCodec::Opus
+        && !args.force_transcode
+        && args.output_url.starts_with("srt://")
+        && args.pipeline_template.is_none()
+        && args.on_input_eos == OnInputEos::Exit
+        && args.volume == 1.0
+        && args.audio_filter.is_none()
+        && args.limiter_ceiling.is_none()
+        && args.gate_threshold.is_none()
+        && !args.agc
+        && args.channel_map.is_empty()
+        && args.bed.is_empty()
+        && args.track_gain.is_empty()
+        && args.pan.is_empty()
+        && !args.sip_codecs
+        && args.max_bitrate.is_none()
+        && args.dump_audio.is_none()
+        && args.captions.is_none()
+        && args.audio_rate_control.is_none()
+        && !args.disable_audio
+        && !args.suspends_input()
+}
+
+/// Uncompressed playout over a Blackmagic SDI card. SDI always carries video, the --generate-video pattern or black.
+fn sdi_output(args: &Args, device: u32) -> String {
+    let video_filter = filter(args.video_filter.as_deref(), "videoconvert");
+    let pattern = args
+        .generate_video
+        .map_or("black", |pattern| pattern.pattern());
+
+    let (caps, interlace, mode) = if args.interlaced {
+        (
+            "video/x-raw,width=1920,height=1080,framerate=50/1",
+            "interlace field-pattern=1:1 top-field-first=true ! ",
+            "1080i50",
+        )
+    } else {
+        (
+            "video/x-raw,width=1920,height=1080,framerate=25/1",
+            "",
+            "1080p25",
+        )
+    };
+
+    format!(
+        "audioconvert ! audio/x-raw,format=S32LE,channels=2 ! decklinkaudiosink name=sdi_audio device-number={device} \
+        videotestsrc name=video_source is-live=true pattern={pattern} ! {caps} ! {video_filter}{interlace}videoconvert ! decklinkvideosink name=sdi_video device-number={device} mode={mode}"
+    )
+}
+
+/// Audio only stream to an Icecast/Shoutcast server, the encoding follows the mount's extension: .mp3, .aac, or Ogg Vorbis otherwise
+fn icecast_output(args: &Args, settings: &ProfileSettings, icecast: &Icecast) -> String {
+    let bitrate = audio_bitrate(args, settings);
+
+    let encoder = if icecast.mount.ends_with(".mp3") {
+        match args.audio_rate_control {
+            // lame's average bitrate mode
+            Some(RateControl::Vbr) => format!(
+                "audioconvert ! lamemp3enc target=bitrate cbr=false bitrate={}",
+                bitrate / 1000
+            ),
+            Some(RateControl::Cq) => String::from("audioconvert ! lamemp3enc target=quality"),
+            Some(RateControl::Cbr) | None => format!(
+                "audioconvert ! lamemp3enc target=bitrate cbr=true bitrate={}",
+                bitrate / 1000
+            ),
+        }
+    } else if icecast.mount.ends_with(".aac") {
+        format!(
+            "{} ! audio/mpeg,mpegversion=4,stream-format=adts",
+            aac_encoder(bitrate)
+        )
+    } else {
+        let rate_control = match args.audio_rate_control {
+            Some(RateControl::Cbr) => format!(
+                "managed=true bitrate={bitrate} min-bitrate={bitrate} max-bitrate={bitrate}"
+            ),
+            // vorbisenc's default quality
+            Some(RateControl::Cq) => String::new(),
+            Some(RateControl::Vbr) | None => format!("bitrate={bitrate}"),
+        };
+        format!("audioconvert ! vorbisenc {rate_control} ! oggmux")
+    };
+
+    format!(
+        "{encoder} ! shout2send name=icecast ip=\"{}\" port={} mount=\"{}\" username=\"{}\" password=\"{}\"",
+        icecast.host, icecast.port, icecast.mount, icecast.username, icecast.password
+    )
+}
+
+/// Teletext PID for --teletext, from the teletext stream of another transport stream or generated empty pages,
+/// which carry the --captions subtitles
+fn teletext(args: &Args) -> String {
+    match args.teletext.as_deref() {
+        None if args.captions.is_none() => String::new(),
+        None | Some("generated") => String::from(
+            "appsrc name=teletext_source is-live=true format=time caps=private/teletext ! queue ! mux. ",
+        ),
+        Some(uri) => {
+            format!("urisourcebin uri=\"{uri}\" ! tsdemux ! private/teletext ! queue ! mux. ")
+        }
+    }
+}
+
+/// Format of the video the gateway generates or composes itself
+struct RawVideo {
+    caps: &'static str,
+    size: (i32, i32),
+    /// Fragment weaving the frames into fields, with --interlaced
+    interlace: &'static str,
+}
+
+fn raw_video(args: &Args) -> RawVideo {
+    if args.interlaced {
+        // every two progressive frames are woven into one top field first frame, 1080i25
+        RawVideo {
+            caps: "video/x-raw,width=1920,height=1080,framerate=50/1",
+            size: (1920, 1080),
+            interlace: "interlace field-pattern=1:1 top-field-first=true ! ",
+        }
+    } else {
+        RawVideo {
+            caps: "video/x-raw,width=1280,height=720,framerate=25/1",
+            size: (1280, 720),
+            interlace: "",
+        }
+    }
+}
+
+/// Encoded test pattern for --generate-video, or the --mosaic of the source video, linked to the muxer next to
+/// the audio
+fn generated_video(args: &Args, settings: &ProfileSettings) -> String {
+    let source = match args.generate_video {
+        Some(pattern) => format!(
+            "videotestsrc name=video_source is-live=true pattern={}",
+            pattern.pattern()
+        ),
+        // a black background, which also keeps the compositor going before the first track and between them
+        None if args.mosaic => format!(
+            "videotestsrc is-live=true pattern=black ! {} ! compositor name=mosaic background=black",
+            raw_video(args).caps
+        ),
+        None => return String::new(),
+    };
+    let RawVideo {
+        caps, interlace, ..
+    } = raw_video(args);
+
+    format!(
+        "{source} ! {caps} ! {video_filter}{interlace}videoconvert ! {encoder} ! {parser} ! mux. ",
+        video_filter = filter(args.video_filter.as_deref(), "videoconvert"),
+        encoder = video_encoder(
+            args,
+            settings,
+            if args.mosaic {
+                SOURCE_VIDEO_BITRATE
+            } else {
+                GENERATED_VIDEO_BITRATE
+            },
+        ),
+        // the parser's caps make mpegtsmux pick the stream type, 0x1b for h264 and 0x24 for h265
+        parser = args.video_codec.parser(),
+    )
+}
+
+/// Position and size of each of `count` tiles of a `width`x`height` mosaic. The grid has as many rows as columns, so
+/// the tiles keep the frame's aspect ratio, and rows it doesn't need are left out of the middle.
+fn tiles(count: usize, width: i32, height: i32) -> Vec<(i32, i32, i32, i32)> {
+    let columns = (1..).find(|n| n * n >= count).unwrap_or(1);
+    let rows = count.div_ceil(columns);
+    let (tile_width, tile_height) = (width / columns as i32, height / columns as i32);
+    let top = (columns - rows) as i32 * tile_height / 2;
+
+    (0..count)
+        .map(|i| {
+            let (row, column) = ((i / columns) as i32, (i % columns) as i32);
+            (
+                column * tile_width,
+                top + row * tile_height,
+                tile_width,
+                tile_height,
+            )
+        })
+        .collect()
+}
+
+/// A user supplied --audio-filter/--video-filter fragment, with converters on both sides so it can take and produce any format
+fn filter(fragment: Option<&str>, convert: &str) -> String {
+    match fragment {
+        Some(fragment) => format!("{convert} ! {fragment} ! {convert} ! "),
+        None => String::new(),
+    }
+}
+
+/// Whether the source video may go to the muxer without transcoding when it is H.264, nothing asks for a
+/// different codec or for GOP and field settings only the local encoder can guarantee
+fn video_passthrough(args: &Args) -> bool {
+    !args.force_transcode
+        && args.video_codec == VideoCodec::H264
+        && args.video_filter.is_none()
+        && !args.interlaced
+        && !args.closed_gop
+        && args.bframes.is_none()
+        && args.video_rate_control.is_none()
+}
+
+/// H.264 in the baseline, main or high profile, what broadcast decoders take from a transport stream.
+/// Without a profile-level-id the RTP default is baseline.
+fn h264_compatible(encoding: &str, profile_level_id: Option<&str>) -> bool {
+    if !encoding.eq_ignore_ascii_case("h264") {
+        return false;
+    }
+
+    let profile_idc = profile_level_id
+        .and_then(|id| id.get(..2))
+        .map_or(Some(66), |idc| u8::from_str_radix(idc, 16).ok());
+    matches!(profile_idc, Some(66 | 77 | 100))
+}
+
+/// Video encoder for --video-codec with the GOP structure from the profile, --closed-gop, --bframes and
+/// --video-rate-control
+fn video_encoder(args: &Args, settings: &ProfileSettings, bitrate: u32) -> String {
+    let gop = settings.gop_frames;
+    let quality = args.video_quality;
+
+    match args.video_codec {
+        VideoCodec::H264 => {
+            let mut encoder = format!(
+                "x264enc name=video_encoder tune=zerolatency speed-preset=ultrafast bitrate={bitrate} key-int-max={gop}"
+            );
+            let mut options = Vec::new();
+
+            if args.interlaced {
+                // field coding, signaled in the SPS and the picture timing SEI
+                encoder.push_str(" interlaced=true");
+            }
+            if let Some(bframes) = args.bframes {
+                encoder.push_str(&format!(" bframes={bframes}"));
+            }
+            if args.closed_gop {
+                // every keyframe an IDR frame, and only at the fixed interval
+                options.push(String::from("open-gop=0:scenecut=0"));
+            }
+            // the default pass already caps the rate at the bitrate, CBR adds the HRD signaling and filler data
+            match args.video_rate_control {
+                Some(RateControl::Cbr) => options.push(String::from("nal-hrd=cbr")),
+                Some(RateControl::Vbr) => {
+                    options.push(format!("vbv-maxrate={0}:vbv-bufsize={0}", bitrate * 2))
+                }
+                // still capped at the bitrate
+                Some(RateControl::Cq) => {
+                    encoder.push_str(&format!(" pass=qual quantizer={quality}"))
+                }
+                None => (),
+            }
+            if !options.is_empty() {
+                encoder.push_str(&format!(" option-string=\"{}\"", options.join(":")));
+            }
+
+            encoder
+        }
+        // hardware encoding when an nvidia gpu is available, x265 is slow
+        VideoCodec::H265 if ElementFactory::find("nvh265enc").is_some() => {
+            let rate_control = match args.video_rate_control {
+                Some(RateControl::Cbr) => format!(" rc-mode=cbr bitrate={bitrate}"),
+                Some(RateControl::Vbr) => {
+                    format!(" rc-mode=vbr bitrate={bitrate} max-bitrate={}", bitrate * 2)
+                }
+                Some(RateControl::Cq) => {
+                    format!(" rc-mode=vbr bitrate=0 max-bitrate={bitrate} const-quality={quality}")
+                }
+                None => format!(" bitrate={bitrate}"),
+            };
+            let mut encoder = format!(
+                "nvh265enc name=video_encoder zerolatency=true{rate_control} gop-size={gop}"
+            );
+
+            if let Some(bframes) = args.bframes {
+                encoder.push_str(&format!(" bframes={bframes}"));
+            }
+            // nvenc GOPs are closed and without scene cut keyframes already
+
+            encoder
+        }
+        VideoCodec::H265 => {
+            let mut options = Vec::new();
+            if let Some(bframes) = args.bframes {
+                options.push(format!("bframes={bframes}"));
+            }
+            if args.closed_gop {
+                options.push(String::from("open-gop=0:scenecut=0"));
+            }
+            match args.video_rate_control {
+                Some(RateControl::Cbr) => options.push(format!(
+                    "vbv-maxrate={bitrate}:vbv-bufsize={bitrate}:strict-cbr=1"
+                )),
+                Some(RateControl::Vbr) => {
+                    options.push(format!("vbv-maxrate={0}:vbv-bufsize={0}", bitrate * 2))
+                }
+                Some(RateControl::Cq) => options.push(format!(
+                    "crf={quality}:vbv-maxrate={bitrate}:vbv-bufsize={bitrate}"
+                )),
+                None => (),
+            }
+
+            let mut encoder = format!(
+                "x265enc name=video_encoder tune=zerolatency speed-preset=ultrafast bitrate={bitrate} key-int-max={gop}"
+            );
+            if !options.is_empty() {
+                encoder.push_str(&format!(" option-string=\"{}\"", options.join(":")));
+            }
+
+            encoder
+        }
+    }
+}
+
+/// Reads a --pipeline-template file, which needs the placeholders the gateway looks up elements of
+fn pipeline_template(path: &str) -> Result<String, Error> {
+    let template = std::fs::read_to_string(path)
+        .map_err(|err| Error::Config(format!("could not read pipeline template {path}: {err}")))?;
+
+    let missing: Vec<&str> = ["{input}", "{mixer}", "{volume}"]
+        .into_iter()
+        .filter(|placeholder| !template.contains(placeholder))
+        .collect();
+    if !missing.is_empty() {
+        return Err(Error::Config(format!(
+            "pipeline template {path} is missing {}",
+            missing.join(", ")
+        )));
+    }
+
+    // line breaks are plain whitespace to gst-launch, so templates can be split over lines
+    Ok(template.trim().to_string())
+}
+
+/// Output queue with the size limits and leakiness given on the command line, GStreamer defaults otherwise
+fn queue(args: &Args) -> String {
+    let mut queue = String::from("queue name=output_queue");
+
+    if let Some(ms) = args.queue_max_time_ms {
+        queue.push_str(&format!(" max-size-time={}", ms.saturating_mul(1_000_000)));
+    }
+    if let Some(buffers) = args.queue_max_buffers {
+        queue.push_str(&format!(" max-size-buffers={buffers}"));
+    }
+    if let Some(bytes) = args.queue_max_bytes {
+        queue.push_str(&format!(" max-size-bytes={bytes}"));
+    }
+    if args.leaky_queue {
+        queue.push_str(" leaky=downstream");
+    }
+
+    queue
+}
+
+/// Formats the GSocketAddress argument of the srtsink caller-added/caller-removed signals
+fn caller_address(value: &gst::glib::Value) -> String {
+    value
+        .get::<gio::SocketAddress>()
+        .ok()
+        .and_then(|addr| addr.downcast::<gio::InetSocketAddress>().ok())
+        .map(|addr| format!("{}:{}", addr.address(), addr.port()))
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+pub fn debug_pipeline(pipe: &gst::Bin, str: &str) {
+    let epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+    let filename = format!("{:?}-{}", epoch.as_secs(), str);
+    pipe.debug_to_dot_file(DebugGraphDetails::ALL, &filename);
+
+    info!(
+        "debugging to file: '{filename}.dot'. Use xdot application to view or convert to svg with 'dot -Tsvg {filename}.dot -o {filename}.svg'"
+    );
+
+    /*
+    use std::{os::unix::process::CommandExt, process::Command};
+    let _ = std::process::Command::new("dot")
+        .arg("-Tsvg")
+        .arg(format!("{filename}.dot"))
+        .arg("-o")
+        .arg(format!("{filename}.svg"))
+        .spawn();
+    */
+}
@@ -0,0 +1,76 @@
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::args::Args;
+use crate::error::Error;
+use crate::events::{Event, EventSink};
+use crate::gateway::{Gateway, Status};
+use crate::health::Health;
+
+/// Forwards lifecycle events to the handle's broadcast channel
+struct Broadcast(broadcast::Sender<Event>);
+
+impl EventSink for Broadcast {
+    fn publish(&mut self, event: &Event, _payload: &Value) {
+        //no subscribers is not an error
+        let _ = self.0.send(event.clone());
+    }
+}
+
+/// A gateway running on tokio's blocking thread pool
+pub struct GatewayHandle {
+    gateway: Gateway,
+    events: broadcast::Sender<Event>,
+    task: JoinHandle<Result<(), Error>>,
+}
+
+impl Gateway {
+    /// Builds the pipeline and runs it in the background. Must be called from within a tokio runtime.
+    pub fn spawn(args: Args) -> Result<GatewayHandle, Error> {
+        let (events, _) = broadcast::channel(64);
+
+        let gateway = Gateway::with_sinks(args, vec![Box::new(Broadcast(events.clone()))])?;
+
+        let runner = gateway.clone();
+        let task = tokio::task::spawn_blocking(move || runner.run());
+
+        Ok(GatewayHandle {
+            gateway,
+            events,
+            task,
+        })
+    }
+}
+
+impl GatewayHandle {
+    pub fn status(&self) -> Status {
+        self.gateway.status()
+    }
+
+    pub fn health(&self) -> Health {
+        self.gateway.health()
+    }
+
+    /// Subscribes to lifecycle events. Events emitted before subscribing are not replayed.
+    pub fn events(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    pub fn gateway(&self) -> &Gateway {
+        &self.gateway
+    }
+
+    /// Stops the gateway and waits for the pipeline to shut down
+    pub async fn stop(self) -> Result<(), Error> {
+        self.gateway.stop();
+        self.wait().await
+    }
+
+    /// Waits for the gateway to end on its own, i.e. on EOS or error
+    pub async fn wait(self) -> Result<(), Error> {
+        self.task
+            .await
+            .map_err(|err| Error::Pipeline(format!("gateway task failed: {err}")))?
+    }
+}
@@ -0,0 +1,132 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{Value, json};
+
+/// Media is taken to flow while data reached the output within this long
+pub const MEDIA_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// When data last reached the output, and since when it has been flowing without a gap. Cloning gives another
+/// handle to the same times.
+#[derive(Debug, Clone, Default)]
+pub struct MediaClock {
+    /// Unix time in ms, 0 before the first data
+    last: Arc<AtomicU64>,
+    since: Arc<AtomicU64>,
+}
+
+impl MediaClock {
+    /// Called for every buffer going into the output
+    pub fn tick(&self) {
+        let now = now_ms();
+        let last = self.last.swap(now, Ordering::Relaxed);
+        if now.saturating_sub(last) > MEDIA_TIMEOUT.as_millis() as u64 {
+            self.since.store(now, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether media is flowing, since when it is, or since when it stopped. None before the first data.
+    pub fn check(&self) -> Check {
+        let last = self.last.load(Ordering::Relaxed);
+        if last == 0 {
+            return Check::default();
+        }
+        let timeout = MEDIA_TIMEOUT.as_millis() as u64;
+        if now_ms().saturating_sub(last) <= timeout {
+            Check {
+                ok: true,
+                since: Some(self.since.load(Ordering::Relaxed) / 1000),
+            }
+        } else {
+            Check {
+                ok: false,
+                since: Some((last + timeout) / 1000),
+            }
+        }
+    }
+}
+
+/// One state of the gateway, and the unix time in seconds it was last entered or left
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Check {
+    pub ok: bool,
+    pub since: Option<u64>,
+}
+
+impl Check {
+    pub fn to_json(&self) -> Value {
+        json!({ "ok": self.ok, "since": self.since })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SrtState {
+    /// The sink is not running
+    Down,
+    /// A listener waiting for callers
+    Listening,
+    /// A listener with callers, or a caller sending to its peer
+    Connected,
+}
+
+impl SrtState {
+    pub fn name(self) -> &'static str {
+        match self {
+            SrtState::Down => "down",
+            SrtState::Listening => "listening",
+            SrtState::Connected => "connected",
+        }
+    }
+}
+
+/// Liveness and readiness of a gateway. A gateway that is not live needs a restart, one that is live but not ready
+/// is waiting for its source or its SRT peer, which a restart doesn't help with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Health {
+    /// The pipeline is running
+    pub process: Check,
+    /// The WebRTC session with the WHEP source is established
+    pub whep: Check,
+    /// Data reaches the output
+    pub media: Check,
+    /// None for outputs other than SRT
+    pub srt: Option<(SrtState, Option<u64>)>,
+    /// Left without WHEP session for lack of SRT callers, on purpose
+    pub suspended: bool,
+    /// Paused on request, no media is expected
+    pub paused: bool,
+}
+
+impl Health {
+    pub fn live(&self) -> bool {
+        self.process.ok
+    }
+
+    /// Live, with the WHEP session established unless it is suspended, media flowing unless paused, and the SRT
+    /// output up
+    pub fn ready(&self) -> bool {
+        self.live()
+            && (self.whep.ok || self.suspended)
+            && (self.media.ok || self.paused)
+            && self.srt.is_none_or(|(state, _)| state != SrtState::Down)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "live": self.live(),
+            "ready": self.ready(),
+            "process": self.process.to_json(),
+            "whep": self.whep.to_json(),
+            "media": self.media.to_json(),
+            "srt": self.srt.map(|(state, since)| json!({ "state": state.name(), "since": since })),
+        })
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
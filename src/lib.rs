@@ -0,0 +1,55 @@
+//! WHEP (WebRTC) to SRT gateway.
+//!
+//! The `whep-srt` binary is a thin command line wrapper around [`Gateway`], which can also be embedded directly:
+//!
+//! ```no_run
+//! use clap::Parser;
+//! use whep_srt::{Args, Gateway};
+//!
+//! let args = Args::parse_from(["whep-srt", "-i", "http://localhost:8889/mystream/whep"]);
+//! let gateway = Gateway::new(args).expect("could not create gateway");
+//! gateway.run().expect("gateway failed");
+//! ```
+//!
+//! With the `tokio` feature, `Gateway::spawn` runs the gateway on tokio's blocking pool and returns a [`GatewayHandle`].
+//! The `tui` feature adds the `--tui` terminal dashboard.
+
+pub mod alarms;
+pub mod api;
+pub mod args;
+pub mod bench;
+pub mod captions;
+pub mod clock;
+pub mod cmaf;
+pub mod drift;
+pub mod error;
+pub mod events;
+pub mod ffi;
+pub mod gateway;
+#[cfg(feature = "tokio")]
+pub mod handle;
+pub mod health;
+pub mod mock_whep;
+pub mod nats;
+pub mod network;
+pub mod oauth;
+pub mod output;
+pub mod process;
+pub mod queues;
+pub mod schedule;
+pub mod self_test;
+pub mod startup;
+pub mod stats;
+pub mod threads;
+pub mod ts;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod version;
+pub mod whep;
+
+pub use args::Args;
+pub use error::Error;
+pub use events::Event;
+pub use gateway::{Gateway, Status};
+#[cfg(feature = "tokio")]
+pub use handle::GatewayHandle;
@@ -1,393 +1,225 @@
 use clap::Parser;
-use env_logger::Env;
-use log::{self, error, info};
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::{env, process::exit};
-
-use gst::prelude::*;
-use gstreamer::{
-    self as gst, DebugGraphDetails, ElementFactory, GhostPad, PadDirection, PadProbeType,
-};
-
-#[derive(Parser, Debug, Clone)]
-#[command(author, version, about, long_about = None)]
-pub struct Args {
-    /// WHEP source url
-    #[clap(short, long)]
-    pub input_url: String,
-
-    /// SRT output stream url
-    #[clap(short, long, default_value_t = String::from("srt://0.0.0.0:1234?mode=listener"))]
-    pub output_url: String,
-
-    /// Output debug .dot files
-    #[clap(long, default_value_t = false)]
-    pub dot_debug: bool,
-}
+use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
+
+use whep_srt::api::Api;
+use whep_srt::args::{Command, ServeTestSourceArgs};
+use whep_srt::mock_whep::MockWhepServer;
+use whep_srt::schedule::Scheduler;
+use whep_srt::version::VersionReport;
+use whep_srt::{Args, Error, Gateway, bench, self_test};
 
 fn main() {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-
     let args = Args::parse();
-    let whep_url = args.input_url;
-    let output_url = args.output_url;
-    let dot_debug = args.dot_debug;
-
-    if dot_debug {
-        let current_dir = format!(
-            "{}",
-            env::current_dir()
-                .expect("could not get current directory")
-                .display()
-        );
+    init_logging(&args);
+
+    match &args.command {
+        Some(Command::Bench(bench_args)) => {
+            match bench::run(&args, bench_args) {
+                Ok(report) => println!("{:#}", report.to_json()),
+                Err(err) => {
+                    fatal(&err);
+                }
+            }
+            return;
+        }
+        Some(Command::ServeTestSource(source_args)) => {
+            serve_test_source(source_args);
+            return;
+        }
+        Some(Command::Version(version_args)) => {
+            match VersionReport::collect(version_args.full) {
+                Ok(report) => println!("{report}"),
+                Err(err) => fatal(&err),
+            }
+            return;
+        }
+        None => (),
+    }
 
-        log::info!("Debugging .dot files to '{current_dir}'");
-        unsafe {
-            env::set_var("GST_DEBUG_DUMP_DOT_DIR", current_dir);
+    if let Some(seconds) = args.self_test {
+        match self_test::run(&args, Duration::from_secs(seconds)) {
+            Ok(report) if report.passed() => exit(0),
+            Ok(_) => exit(1),
+            Err(err) => {
+                error!("self-test failed");
+                report_fatal(&err);
+                exit(1);
+            }
         }
     }
 
-    gst::init().expect("Could not initiate GStreamer");
+    if let Some(api_listen) = args.api_listen.clone() {
+        serve_api(&api_listen, args);
+        return;
+    }
 
-    info!("SRT output at {output_url}");
-    info!("---");
+    if args.start_at.is_some() || args.stop_at.is_some() {
+        run_scheduled(args);
+        return;
+    }
 
-    /*  NOTE:
-       whepsrc was the first WHIP implementation in gstreamer, based on webrtcbin directly. it's present in gstwebrtchttp plugin.
-       whepclientsrc has later been added and it is using the signaller interface on webrtcsrc and webrtcsink rust plugins. it's present in gstrswebrtc plugin.
-       whepclientsrc is reusing a lot of functionallity and is supposed to deprecate whepsrc in the future.
+    run(args);
+}
 
-       In this project we use the new whepclientsrc. Below is some dev code if you want to try out the old whepsrc implementation for some reason.
-    */
+/// Logs to stderr, filtered by -v/-q or else RUST_LOG (default info). The --tui dashboard shows the log in a pane
+/// instead.
+fn init_logging(args: &Args) {
+    let filter = match args.log_filter() {
+        Some(filter) => EnvFilter::new(filter),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+
+    #[cfg(feature = "tui")]
+    if args.tui {
+        subscriber
+            .with_ansi(false)
+            .with_writer(whep_srt::tui::log_writer)
+            .init();
+        return;
+    }
 
-    let use_whepsrc = false;
-    let input = if use_whepsrc {
-        //gstwebrtchttp::plugin_register_static().expect("Could not register gstwebrtchttp plugins");
+    subscriber.with_writer(std::io::stderr).init();
+}
 
-        let audio_caps = "audio_caps=\"application/x-rtp, media=(string)audio, encoding-name=(string)opus, payload=(int)96, encoding-params=(string)2, clock-rate=(int)48000\"";
-        format!(
-            "whepsrc name=input use-link-headers=false whep-endpoint=\"{whep_url}\" {audio_caps} video-caps=\"\""
-        )
+/// Reports `err` and exits
+fn fatal(err: &Error) -> ! {
+    report_fatal(err);
+    exit(-1);
+}
+
+/// Logs `err` and writes it to stderr as a JSON object on a line of its own, for orchestrators to tell
+/// e.g. rejected credentials from an unreachable endpoint or a missing plugin
+fn report_fatal(err: &Error) {
+    error!("{err}");
+    eprintln!("{}", err.to_json());
+}
+
+/// Single stream mode. A pipeline that stalls (--stall-timeout) is built and started again.
+fn run(args: Args) {
+    let current: Arc<Mutex<Option<Gateway>>> = Arc::new(Mutex::new(None));
+    let stopped = Arc::new(AtomicBool::new(false));
+
+    let stop = {
+        let current = current.clone();
+        let stopped = stopped.clone();
+        move || {
+            info!("ctrl-c received");
+            stopped.store(true, Ordering::SeqCst);
+            if let Some(gateway) = current.lock().unwrap().as_ref() {
+                gateway.stop();
+            }
+        }
+    };
+    let _ = ctrlc::set_handler(stop.clone());
+
+    #[cfg(feature = "tui")]
+    let dashboard = if args.tui {
+        let current = current.clone();
+        match whep_srt::tui::Dashboard::start(move || current.lock().unwrap().clone(), stop) {
+            Ok(dashboard) => Some(dashboard),
+            Err(err) => fatal(&err),
+        }
     } else {
-        gstrswebrtc::plugin_register_static().expect("Could not register gstrswebrtc plugins");
+        None
+    };
 
-        format!("whepclientsrc name=input signaller::whep-endpoint=\"{whep_url}\"")
+    let result = loop {
+        let gateway = match Gateway::new(args.clone()) {
+            Ok(gateway) => gateway,
+            Err(err) => break Err(err),
+        };
+        *current.lock().unwrap() = Some(gateway.clone());
+
+        match gateway.run() {
+            Ok(()) => break Ok(()),
+            Err(Error::Stalled(_)) if !stopped.load(Ordering::SeqCst) => {
+                info!("restarting the stalled pipeline");
+            }
+            // with a supervisor, a track that could not be set up is retried with a new pipeline
+            Err(Error::Branch(_))
+                if args.stall_timeout.is_some() && !stopped.load(Ordering::SeqCst) =>
+            {
+                info!("restarting the pipeline to set up the failed track again");
+            }
+            Err(err) => break Err(err),
+        }
     };
 
-    let mixer = "liveadder name=mixer"; //this could be audiomixer also, but liveadder will do fine here
+    // the terminal is given back before the error is reported
+    #[cfg(feature = "tui")]
+    drop(dashboard);
 
-    let pipeline_str = format!(
-        "{input} audiotestsrc wave=silence is-live=true ! audio/x-raw,format=F32LE,rate=48000,channels=2 ! {mixer} ! avenc_aac ! aacparse ! mux. \
-        mpegtsmux name=mux alignment=7 ! queue ! srtsink uri=\"{output_url}\" sync=false wait-for-connection=false latency=100"
-    );
+    if let Err(err) = result {
+        fatal(&err);
+    }
+}
 
-    let mut context = gst::ParseContext::new();
-    let pipeline = match gst::parse::launch_full(
-        &pipeline_str,
-        Some(&mut context),
-        gst::ParseFlags::empty(),
-    ) {
-        Ok(pipeline) => pipeline,
+/// Multi-stream mode: streams are created and destroyed through the api. An input url given on the
+/// command line (or --test-source) becomes the stream 'default'.
+fn serve_api(api_listen: &str, args: Args) {
+    let api = match Api::bind(api_listen, args.api_token.clone()) {
+        Ok(api) => api,
         Err(err) => {
-            if let Some(gst::ParseError::NoSuchElement) = err.kind::<gst::ParseError>() {
-                error!("Missing element(s): {:?}", context.missing_elements());
-            } else {
-                error!("Failed to parse pipeline: {err}");
-            }
-
-            std::process::exit(-1)
+            fatal(&err);
         }
     };
 
-    let pipeline = pipeline
-        .dynamic_cast::<gst::Pipeline>()
-        .expect("could not cast pipeline");
-    let pipeline_clone = pipeline.clone();
-
-    let mixer = pipeline
-        .by_name("mixer")
-        .expect("could not find mixer element");
-    let mixer_clone = mixer.clone();
-
-    let input_whep_bin = pipeline
-        .by_name("input")
-        .expect("could not get whep input bin");
+    if args.input_url.is_some() || args.test_source.is_some() {
+        if let Err(err) = api.create(Some(String::from("default")), args) {
+            fatal(&err);
+        }
+    }
 
+    let api_clone = api.clone();
     let _ = ctrlc::set_handler(move || {
-        info!("exit.. shutting down");
-
-        pipeline_clone
-            .set_state(gst::State::Null)
-            .expect("Unable to set the pipeline to the `Null` state");
-
-        std::thread::sleep(std::time::Duration::from_secs(1));
-
+        info!("ctrl-c received, stopping all streams");
+        api_clone.shutdown();
         exit(0);
     });
 
-    let bus = pipeline.bus().unwrap();
-
-    let pipeline_clone = pipeline.clone();
-
-    pipeline.connect_deep_element_added(move |pipe, bin, elem| {
-        let elem_type = elem.type_().to_string();
-        let _ = pipe;
-        let _ = bin;
-
-        if elem_type == "GstWebRTCBin" {
-            
-            elem.connect_pad_added(move |elem, pad| {
-                info!("webrtcbin pad added: '{}'", pad.name());
-
-                /*
-                   Note: When receiving multiple audio tracks (ssrcs), the first track is automatically exposed 'out' of the whepclientsrc bin
-                   Other tracks are _not_ automatically exposed, so we have to handle that manually. That is why we listen for pad_added on webrtcbin and
-                   then ghostpad our way out of the bins.
-                */
-
-                let caps = pad
-                    .current_caps()
-                    .unwrap_or_else(|| panic!("could not get current_caps on pad {}", pad.name()));
-                let s = caps
-                    .structure(0)
-                    .expect("could not get structure 0 on caps");
-
-                //info!("full structure: {:#?}", s);
-
-                let media_type = s
-                    .get::<String>("media")
-                    .expect("could not get media from caps structure");
-
-                if !pad.is_linked() {
-                    //this is not automatically linked, we have to handle it. 
-                    info!("pad '{}' is not automatically linked, handling ghostpads. media_type: {media_type}", pad.name());
-
-                    let parent = elem.parent().expect("could not get webrtcbin parent");
-                    let parent = parent
-                        .dynamic_cast_ref::<gst::Bin>()
-                        .expect("could not cast webrtcbin parent");
-
-                    let new_pad_name = format!("{}_{}", media_type, pad.name());
-
-                    let ghostpad = GhostPad::builder(PadDirection::Src)
-                        .with_target(pad)
-                        .expect("could not create ghostpad")
-                        .name(&new_pad_name)
-                        .build();
-                    parent
-                        .add_pad(&ghostpad)
-                        .expect("could not add ghostpad to parent");
-
-                    let parent_parent = parent.parent().expect("could not get parent parent");
-                    if let Some(_pipe) = parent_parent.dynamic_cast_ref::<gst::Pipeline>() {
-                        //info!("found pipeline.. no more ghostpads needed");
-                    } else {
-                        let parent_parent = parent_parent
-                            .dynamic_cast_ref::<gst::Bin>()
-                            .expect("could cast webrtcbin parent parent");
-
-                        let ghostpad2 = GhostPad::builder(PadDirection::Src)
-                            .with_target(&ghostpad)
-                            .expect("could not create ghostpad2 with target ghostpad")
-                            .name(&new_pad_name)
-                            .build();
-                        parent_parent
-                            .add_pad(&ghostpad2)
-                            .expect("could not add ghostpad2");
-                    }
-                }
-            });
-        }
-    });
-
-    input_whep_bin.connect_pad_added(move |elem, pad| {
-        info!(
-            "pad added on {} named '{}': '{}'",
-            elem.type_(),
-            elem.name(),
-            pad.name()
-        );
-
-        let pipeline_clone = pipeline_clone.clone();
-        let mixer_clone = mixer_clone.clone();
-
-        pad.add_probe(PadProbeType::BUFFER, move |pad, _probe_info| {
-            let caps = pad.current_caps().unwrap();
-            let media_type = caps.structure(0).unwrap().get::<String>("media").unwrap();
-
-            info!("getting {media_type} track");
-            match media_type.as_str() {
-                "audio" => {
-                    let pipe_bin = pipeline_clone
-                        .dynamic_cast_ref::<gst::Bin>()
-                        .expect("could not cast pipeline to bin");
-
-                    let decodebin = ElementFactory::make("decodebin")
-                        .build()
-                        .expect("could not create decodebin");
-                    pipe_bin
-                        .add(&decodebin)
-                        .expect("could not add decodebin to pipe_bin");
-                    decodebin
-                        .sync_state_with_parent()
-                        .expect("could not sync_state on decode_bin");
-
-                    let pipe_bin_clone = pipe_bin.clone();
-
-                    let mixer_clone = mixer_clone.clone();
-                    decodebin.connect_pad_added(move |elem, pad| {
-                        info!("pad '{}' added on decodebin '{}'", pad.name(), elem.name());
-
-                        let audioconvert = ElementFactory::make("audioconvert")
-                            .build()
-                            .expect("could not create audioconvert");
-                        let audioresample = ElementFactory::make("audioresample")
-                            .build()
-                            .expect("could not create audioresample");
-                        let caps = ElementFactory::make("capsfilter")
-                            .build()
-                            .expect("could not create capsfiler");
-                        caps.set_property_from_str("caps", "audio/x-raw,format=F32LE,rate=48000");
-
-                        let elements = [&audioconvert, &audioresample, &caps];
-
-                        pipe_bin_clone
-                            .add_many(elements)
-                            .expect("could not add_many");
-                        for elem in elements {
-                            elem.sync_state_with_parent()
-                                .expect("could not sync_state_with_parent");
-                        }
-
-                        gst::Element::link_many(elements).expect("could not link many on elements");
-
-                        //-- setup links from decodebin leg to audiomixer --
-                        let caps_src_pad = caps.static_pad("src").unwrap();
-
-                        let mixer_input_pad = mixer_clone
-                            .request_pad_simple("sink_%u")
-                            .expect("could not get audio mixer input pad");
-
-                        caps_src_pad
-                            .link(&mixer_input_pad)
-                            .expect("could not link input audio to audiomixer");
-
-                        //link decodebin pad to audioconvert
-                        pad.link(&audioconvert.static_pad("sink").unwrap())
-                            .expect("could not link decodebin to audioconvert sink");
-                    });
-
-                    //link from webrtcbin to decodebin
-                    let decodebin_pad = decodebin.iterate_sink_pads().next().unwrap().unwrap();
-
-                    pad.link(&decodebin_pad)
-                        .expect("could not link from webrtcbin audio pad to decodebin");
-                }
-                "video" => {
-                    //TODO: this should be sent to muxer maybe?
-
-                    let fakesink = ElementFactory::make("fakesink")
-                        .build()
-                        .expect("could not create video fakesink");
+    api.serve();
+}
 
-                    pipeline_clone
-                        .add(&fakesink)
-                        .expect("could not add video fakesink to pipeline");
-                    fakesink
-                        .sync_state_with_parent()
-                        .expect("could not sync state on fakesink");
-                    let fakesink_pad = fakesink
-                        .static_pad("sink")
-                        .expect("could not get fakesink pad");
-                    pad.link(&fakesink_pad)
-                        .expect("could not link video sinkpad sink");
-                }
-                _ => {
-                    error!("unhandled media type");
-                }
-            }
+/// Single stream mode, limited to the --start-at/--stop-at window(s)
+fn run_scheduled(args: Args) {
+    let scheduler = Scheduler::new(args);
 
-            gstreamer::PadProbeReturn::Remove
-        });
+    let scheduler_clone = scheduler.clone();
+    let _ = ctrlc::set_handler(move || {
+        info!("ctrl-c received");
+        scheduler_clone.stop();
     });
 
-    // Start pipeline - ICE role is configured via webrtcbin-ready signal
-    pipeline
-        .set_state(gst::State::Playing)
-        .expect("Unable to set the pipeline to the `Playing` state");
-
-    let pipeline_clone = pipeline.clone();
-
-    for msg in bus.iter_timed(gst::ClockTime::NONE) {
-        use gst::MessageView;
-
-        match msg.view() {
-            MessageView::StateChanged(state) => {
-                if !state
-                    .src()
-                    .unwrap()
-                    .type_()
-                    .to_string()
-                    .contains("GstPipeline")
-                {
-                    continue;
-                }
-
-                log::debug!(
-                    "pipeline change: {:?} -> {:?}",
-                    state.old(),
-                    state.current()
-                );
-
-                if dot_debug {
-                    let pipe_bin = pipeline_clone.dynamic_cast_ref::<gst::Bin>().unwrap();
-                    debug_pipeline(pipe_bin, &format!("{:?}", state.current()));
-                }
-            }
-            MessageView::Eos(..) => break,
-            MessageView::Error(err) => {
-                error!(
-                    "Error from {:?}: {} ({:?})",
-                    err.src().map(|s| s.path_string()),
-                    err.error(),
-                    err.debug()
-                );
-
-                if dot_debug {
-                    let pipe_bin = pipeline_clone.dynamic_cast_ref::<gst::Bin>().unwrap();
-                    debug_pipeline(pipe_bin, "error");
-                }
-
-                break;
-            }
-            _ => (),
-        }
+    if let Err(err) = scheduler.run() {
+        fatal(&err);
     }
-
-    pipeline
-        .set_state(gst::State::Null)
-        .expect("Unable to set the pipeline to the `Null` state");
-
-    std::thread::sleep(std::time::Duration::from_secs(1));
 }
 
-fn debug_pipeline(pipe: &gst::Bin, str: &str) {
-    let epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+/// Local WHEP endpoint with test signals, for trying out the gateway without any infrastructure
+fn serve_test_source(source_args: &ServeTestSourceArgs) {
+    let mut server = match MockWhepServer::bind(&source_args.listen) {
+        Ok(server) => server,
+        Err(err) => {
+            fatal(&err);
+        }
+    };
+    if !source_args.audio_only {
+        server = server.with_video();
+    }
 
-    let filename = format!("{:?}-{}", epoch.as_secs(), str);
-    pipe.debug_to_dot_file(DebugGraphDetails::ALL, &filename);
+    info!("start a gateway with: whep-srt -i {}", server.url());
 
-    info!(
-        "debugging to file: '{filename}.dot'. Use xdot application to view or convert to svg with 'dot -Tsvg {filename}.dot -o {filename}.svg'"
-    );
+    let server_clone = server.clone();
+    let _ = ctrlc::set_handler(move || {
+        info!("ctrl-c received");
+        server_clone.shutdown();
+    });
 
-    /*
-    use std::{os::unix::process::CommandExt, process::Command};
-    let _ = std::process::Command::new("dot")
-        .arg("-Tsvg")
-        .arg(format!("{filename}.dot"))
-        .arg("-o")
-        .arg(format!("{filename}.svg"))
-        .spawn();
-    */
+    server.serve();
 }
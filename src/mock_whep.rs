@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use gst::prelude::*;
+use gstreamer as gst;
+use gstreamer_webrtc::{self as gst_webrtc, WebRTCSDPType, WebRTCSessionDescription};
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{info, warn};
+
+use crate::error::Error;
+use crate::gateway;
+
+/// Test signal sent to every viewer, linked into the session's webrtcbin
+const AUDIO_SOURCE: &str = "audiotestsrc is-live=true wave=sine ! audioconvert ! audioresample ! opusenc ! rtpopuspay pt=96 ! \
+    application/x-rtp,media=audio,encoding-name=OPUS,payload=96,clock-rate=48000 ! webrtcbin.";
+
+/// Added to the session with `with_video`
+const VIDEO_SOURCE: &str = "videotestsrc is-live=true pattern=smpte ! video/x-raw,width=640,height=360,framerate=30/1 ! videoconvert ! \
+    vp8enc deadline=1 ! rtpvp8pay pt=97 ! application/x-rtp,media=video,encoding-name=VP8,payload=97,clock-rate=90000 ! webrtcbin.";
+
+/// Minimal local WHEP endpoint at `/whep` streaming a test tone (and optionally test pattern), so the gateway can be exercised without an origin server.
+/// Every POSTed offer gets its own webrtcbin session, which is closed by DELETE on the returned Location.
+#[derive(Clone)]
+pub struct MockWhepServer {
+    server: Arc<Server>,
+    sessions: Arc<Mutex<HashMap<String, gst::Pipeline>>>,
+    next_id: Arc<AtomicU64>,
+    video: bool,
+}
+
+impl MockWhepServer {
+    /// Listens on `address`, use port 0 to pick a free port
+    pub fn bind(address: &str) -> Result<Self, Error> {
+        gateway::init()?;
+
+        let server = Server::http(address)
+            .map_err(|err| Error::Config(format!("could not listen on {address}: {err}")))?;
+
+        Ok(Self {
+            server: Arc::new(server),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(1)),
+            video: false,
+        })
+    }
+
+    /// Also send a VP8 test pattern next to the audio
+    pub fn with_video(mut self) -> Self {
+        self.video = true;
+        self
+    }
+
+    /// WHEP endpoint url, to be used as the gateway's input url
+    pub fn url(&self) -> String {
+        match self.server.server_addr().to_ip() {
+            Some(address) => format!("http://{address}/whep"),
+            None => String::from("http://localhost/whep"),
+        }
+    }
+
+    /// Serves requests on the calling thread until `shutdown` is called
+    pub fn serve(&self) {
+        info!("mock whep endpoint at {}", self.url());
+
+        for mut request in self.server.incoming_requests() {
+            let response = self.handle(&mut request);
+            if let Err(err) = request.respond(response) {
+                warn!("could not send whep response: {err}");
+            }
+        }
+    }
+
+    /// Ends `serve` and closes all sessions
+    pub fn shutdown(&self) {
+        self.server.unblock();
+
+        for (_, pipeline) in self.sessions.lock().unwrap().drain() {
+            let _ = pipeline.set_state(gst::State::Null);
+        }
+    }
+
+    fn handle(&self, request: &mut Request) -> Response<std::io::Cursor<Vec<u8>>> {
+        let path = request
+            .url()
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        match (request.method(), segments.as_slice()) {
+            (Method::Post, ["whep"]) => {
+                let mut offer = String::new();
+                if let Err(err) = request.as_reader().read_to_string(&mut offer) {
+                    return text(400, &err.to_string());
+                }
+
+                match answer(&offer, self.video) {
+                    Ok((pipeline, answer)) => {
+                        let id = self.next_id.fetch_add(1, Ordering::Relaxed).to_string();
+                        info!("whep session {id} started");
+                        self.sessions.lock().unwrap().insert(id.clone(), pipeline);
+
+                        text(201, &answer)
+                            .with_header(header("Content-Type", "application/sdp"))
+                            .with_header(header("Location", &format!("/whep/{id}")))
+                    }
+                    Err(err) => {
+                        warn!("could not answer whep offer: {err}");
+                        text(400, &err)
+                    }
+                }
+            }
+            // trickled candidates are not needed, the answer already has all of ours
+            (Method::Patch, ["whep", _]) => text(204, ""),
+            (Method::Delete, ["whep", id]) => match self.sessions.lock().unwrap().remove(*id) {
+                Some(pipeline) => {
+                    let _ = pipeline.set_state(gst::State::Null);
+                    info!("whep session {id} ended");
+                    text(200, "")
+                }
+                None => text(404, "not found"),
+            },
+            _ => text(404, "not found"),
+        }
+    }
+}
+
+/// Starts a test source session for the offer. Returns its pipeline and the SDP answer.
+fn answer(offer: &str, video: bool) -> Result<(gst::Pipeline, String), String> {
+    let sdp = gst_webrtc::gst_sdp::SDPMessage::parse_buffer(offer.as_bytes())
+        .map_err(|_| String::from("invalid sdp offer"))?;
+
+    let video_source = if video { VIDEO_SOURCE } else { "" };
+
+    let pipeline = gst::parse::launch(&format!(
+        "webrtcbin name=webrtcbin bundle-policy=max-bundle {AUDIO_SOURCE} {video_source}"
+    ))
+    .map_err(|err| format!("could not create session pipeline: {err}"))?
+    .dynamic_cast::<gst::Pipeline>()
+    .expect("could not cast pipeline");
+
+    let result = negotiate(&pipeline, sdp);
+    if result.is_err() {
+        let _ = pipeline.set_state(gst::State::Null);
+    }
+
+    result.map(|answer| (pipeline, answer))
+}
+
+fn negotiate(
+    pipeline: &gst::Pipeline,
+    offer: gst_webrtc::gst_sdp::SDPMessage,
+) -> Result<String, String> {
+    let webrtcbin = pipeline
+        .by_name("webrtcbin")
+        .expect("could not get webrtcbin");
+
+    pipeline
+        .set_state(gst::State::Playing)
+        .map_err(|err| format!("could not play session pipeline: {err}"))?;
+
+    let offer = WebRTCSessionDescription::new(WebRTCSDPType::Offer, offer);
+    gateway::call(&webrtcbin, "set-remote-description", &offer)?;
+
+    let answer = gateway::call(&webrtcbin, "create-answer", &None::<gst::Structure>)?
+        .and_then(|reply| reply.get::<WebRTCSessionDescription>("answer").ok())
+        .ok_or_else(|| String::from("webrtcbin did not create an answer"))?;
+    gateway::call(&webrtcbin, "set-local-description", &answer)?;
+
+    // the answer carries all candidates, trickle ICE is not supported
+    gateway::gathered_description(&webrtcbin)
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("could not create header")
+}
+
+fn text(status: u16, body: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(body).with_status_code(status)
+}
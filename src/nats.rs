@@ -0,0 +1,188 @@
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use serde_json::{Value, json};
+use tracing::{debug, info, warn};
+
+use crate::events::{Event, EventSink};
+
+/// Minimal NATS publisher speaking the plain text client protocol over TCP.
+/// Every event is published to `<subject>.<event name>`, so controllers can subscribe to `<subject>.>`.
+pub struct NatsPublisher {
+    address: String,
+    user: Option<String>,
+    pass: Option<String>,
+    token: Option<String>,
+    subject: String,
+    stream: Option<TcpStream>,
+}
+
+impl NatsPublisher {
+    /// Accepts `nats://[user:pass@|token@]host[:port]`
+    pub fn new(url: &str, subject: String) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("nats://")
+            .ok_or_else(|| format!("nats url '{url}' must start with nats://"))?;
+        let rest = rest.trim_end_matches('/');
+
+        let (credentials, host) = match rest.rsplit_once('@') {
+            Some((credentials, host)) => (Some(credentials), host),
+            None => (None, rest),
+        };
+
+        let (user, pass, token) = match credentials.map(|c| c.split_once(':')) {
+            Some(Some((user, pass))) => (Some(user.to_string()), Some(pass.to_string()), None),
+            Some(None) => (None, None, credentials.map(String::from)),
+            None => (None, None, None),
+        };
+
+        if host.is_empty() {
+            return Err(format!("nats url '{url}' has no host"));
+        }
+
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{host}:4222")
+        };
+
+        Ok(Self {
+            address,
+            user,
+            pass,
+            token,
+            subject,
+            stream: None,
+        })
+    }
+
+    fn connect(&self) -> std::io::Result<TcpStream> {
+        let stream = TcpStream::connect(&self.address)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+        //the server greets with a single INFO line before accepting CONNECT
+        let mut info = String::new();
+        BufReader::new(&stream).read_line(&mut info)?;
+        if !info.starts_with("INFO") {
+            return Err(std::io::Error::other(format!(
+                "unexpected nats greeting '{}'",
+                info.trim_end()
+            )));
+        }
+
+        let mut options = json!({ "verbose": false, "pedantic": false, "name": "whep-srt" });
+        if let Some(user) = &self.user {
+            options["user"] = Value::from(user.as_str());
+        }
+        if let Some(pass) = &self.pass {
+            options["pass"] = Value::from(pass.as_str());
+        }
+        if let Some(token) = &self.token {
+            options["auth_token"] = Value::from(token.as_str());
+        }
+
+        (&stream).write_all(format!("CONNECT {options}\r\n").as_bytes())?;
+        stream.set_nonblocking(true)?;
+
+        info!("connected to nats at {}", self.address);
+        Ok(stream)
+    }
+
+    fn write(&mut self, subject: &str, body: &str) -> std::io::Result<()> {
+        if self.stream.is_none() {
+            self.stream = Some(self.connect()?);
+        }
+        let stream = self.stream.as_mut().expect("nats stream is connected");
+
+        //answer any keep-alive PINGs that arrived since the last publish, otherwise the server drops us
+        let mut pending = [0u8; 4096];
+        loop {
+            match stream.read(&mut pending) {
+                Ok(0) => return Err(ErrorKind::ConnectionAborted.into()),
+                Ok(n) => {
+                    if String::from_utf8_lossy(&pending[..n]).contains("PING") {
+                        stream.set_nonblocking(false)?;
+                        stream.write_all(b"PONG\r\n")?;
+                        stream.set_nonblocking(true)?;
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        stream.set_nonblocking(false)?;
+        let result =
+            stream.write_all(format!("PUB {subject} {}\r\n{body}\r\n", body.len()).as_bytes());
+        stream.set_nonblocking(true)?;
+        result
+    }
+}
+
+impl EventSink for NatsPublisher {
+    fn publish(&mut self, _event: &Event, payload: &Value) {
+        let subject = format!(
+            "{}.{}",
+            self.subject,
+            payload["event"].as_str().unwrap_or("unknown")
+        );
+        let body = payload.to_string();
+
+        //one reconnect attempt covers connections the server closed while we were idle
+        for attempt in 0..2 {
+            match self.write(&subject, &body) {
+                Ok(()) => {
+                    debug!("nats published '{subject}'");
+                    return;
+                }
+                Err(err) => {
+                    self.stream = None;
+                    if attempt > 0 {
+                        warn!("nats publish of '{subject}' failed: {err}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn publisher(url: &str) -> NatsPublisher {
+        NatsPublisher::new(url, String::from("whep-srt")).unwrap()
+    }
+
+    #[test]
+    fn parses_urls() {
+        let nats = publisher("nats://localhost");
+        assert_eq!(nats.address, "localhost:4222");
+        assert_eq!((nats.user, nats.pass, nats.token), (None, None, None));
+
+        let nats = publisher("nats://nats.example.com:4333/");
+        assert_eq!(nats.address, "nats.example.com:4333");
+
+        let nats = publisher("nats://user:p@ss@10.0.0.1");
+        assert_eq!(nats.address, "10.0.0.1:4222");
+        assert_eq!(nats.user.as_deref(), Some("user"));
+        assert_eq!(nats.pass.as_deref(), Some("p@ss"));
+        assert_eq!(nats.token, None);
+
+        let nats = publisher("nats://s3cr3t@localhost:4222");
+        assert_eq!(nats.address, "localhost:4222");
+        assert_eq!(nats.token.as_deref(), Some("s3cr3t"));
+        assert_eq!((nats.user, nats.pass), (None, None));
+    }
+
+    #[test]
+    fn rejects_invalid_urls() {
+        let subject = || String::from("whep-srt");
+        assert!(NatsPublisher::new("tls://localhost", subject()).is_err());
+        assert!(NatsPublisher::new("localhost:4222", subject()).is_err());
+        assert!(NatsPublisher::new("nats://", subject()).is_err());
+        assert!(NatsPublisher::new("nats://token@", subject()).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeSet;
+use std::net::{IpAddr, UdpSocket};
+use std::time::Duration;
+
+/// Interfaces that are up and their addresses. Loopback interfaces and link-local addresses, which every interface
+/// has and no session to a WHEP origin uses, are left out.
+#[cfg(unix)]
+pub fn addresses() -> BTreeSet<(String, IpAddr)> {
+    use std::ffi::CStr;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    let mut addresses = BTreeSet::new();
+
+    let mut ifaddrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+        return addresses;
+    }
+
+    let mut current = ifaddrs;
+    while let Some(ifaddr) = unsafe { current.as_ref() } {
+        current = ifaddr.ifa_next;
+
+        let flags = ifaddr.ifa_flags as libc::c_int;
+        if flags & libc::IFF_UP == 0 || flags & libc::IFF_LOOPBACK != 0 || ifaddr.ifa_addr.is_null()
+        {
+            continue;
+        }
+
+        let address = unsafe {
+            match (*ifaddr.ifa_addr).sa_family as libc::c_int {
+                libc::AF_INET => {
+                    let sockaddr = &*(ifaddr.ifa_addr as *const libc::sockaddr_in);
+                    IpAddr::from(Ipv4Addr::from(u32::from_be(sockaddr.sin_addr.s_addr)))
+                }
+                libc::AF_INET6 => {
+                    let sockaddr = &*(ifaddr.ifa_addr as *const libc::sockaddr_in6);
+                    IpAddr::from(Ipv6Addr::from(sockaddr.sin6_addr.s6_addr))
+                }
+                _ => continue,
+            }
+        };
+        let link_local = match address {
+            IpAddr::V4(address) => address.is_link_local(),
+            IpAddr::V6(address) => address.is_unicast_link_local(),
+        };
+        if link_local {
+            continue;
+        }
+        let name = unsafe { CStr::from_ptr(ifaddr.ifa_name) }.to_string_lossy();
+
+        addresses.insert((name.into_owned(), address));
+    }
+
+    unsafe { libc::freeifaddrs(ifaddrs) };
+
+    addresses
+}
+
+#[cfg(not(unix))]
+pub fn addresses() -> BTreeSet<(String, IpAddr)> {
+    BTreeSet::new()
+}
+
+/// The interface of the default route: the one with the address a socket sending to the internet is bound to.
+/// Connecting a UDP socket only looks up the route, nothing is sent.
+fn route_interface(addresses: &BTreeSet<(String, IpAddr)>) -> Option<String> {
+    let route = [("0.0.0.0:0", "192.0.2.1:9"), ("[::]:0", "[2001:db8::1]:9")]
+        .into_iter()
+        .find_map(|(local, remote)| {
+            let socket = UdpSocket::bind(local).ok()?;
+            socket.connect(remote).ok()?;
+            socket.local_addr().ok()
+        })?;
+
+    addresses
+        .iter()
+        .find(|(_, address)| *address == route.ip())
+        .map(|(name, _)| name.clone())
+}
+
+/// Why the session's path is gone between two checks: the local address it used disappeared, or the default
+/// route moved to another interface. Addresses coming and going elsewhere leave it alone.
+fn path_lost(
+    previous: &BTreeSet<(String, IpAddr)>,
+    current: &BTreeSet<(String, IpAddr)>,
+    previous_route: Option<&str>,
+    route: Option<&str>,
+    used: Option<IpAddr>,
+) -> Option<String> {
+    let has = |addresses: &BTreeSet<(String, IpAddr)>, used: IpAddr| {
+        addresses.iter().any(|(_, address)| *address == used)
+    };
+    if let Some(used) = used
+        && has(previous, used)
+        && !has(current, used)
+    {
+        return Some(format!("the session's address {used} is gone"));
+    }
+
+    match (previous_route, route) {
+        (Some(previous), Some(route)) if previous != route => Some(format!(
+            "the default route moved from {previous} to {route}"
+        )),
+        _ => None,
+    }
+}
+
+/// Checks the local network every `interval` on a dedicated thread. `on_check` is told whether the WebRTC session
+/// lost its path since the previous check, see `path_lost`, and stops the thread by returning false.
+/// `session_address` is the local address the session uses, None while there is no session.
+pub fn spawn_watcher<S, F>(interval: Duration, mut session_address: S, mut on_check: F)
+where
+    S: FnMut() -> Option<IpAddr> + Send + 'static,
+    F: FnMut(bool) -> bool + Send + 'static,
+{
+    std::thread::Builder::new()
+        .name("network".into())
+        .spawn(move || {
+            let mut previous = addresses();
+            let mut previous_route = route_interface(&previous);
+            loop {
+                std::thread::sleep(interval);
+
+                let current = addresses();
+                let route = route_interface(&current);
+                if current != previous {
+                    let gone: Vec<_> = previous.difference(&current).collect();
+                    let new: Vec<_> = current.difference(&previous).collect();
+                    tracing::info!("local network changed, gone: {gone:?}, new: {new:?}");
+                }
+
+                let lost = path_lost(
+                    &previous,
+                    &current,
+                    previous_route.as_deref(),
+                    route.as_deref(),
+                    session_address(),
+                );
+                if let Some(reason) = &lost {
+                    tracing::info!("{reason}");
+                }
+                previous = current;
+                previous_route = route;
+
+                if !on_check(lost.is_some()) {
+                    break;
+                }
+            }
+        })
+        .expect("could not spawn network thread");
+}
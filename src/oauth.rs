@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use serde_json::Value;
+use tracing::{info, warn};
+
+/// A token is refreshed this long before it expires, at the latest
+const REFRESH_MARGIN: Duration = Duration::from_secs(30);
+
+/// Wait before trying again when the token endpoint fails
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// OAuth2 client credentials grant (RFC 6749 section 4.4) for the WHEP bearer token. The client authenticates
+/// with its id and secret in the request body.
+#[derive(Debug, Clone)]
+pub struct ClientCredentials {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub access_token: String,
+    /// None when the token endpoint doesn't say, the token is then taken not to expire
+    pub expires_in: Option<Duration>,
+}
+
+impl ClientCredentials {
+    /// Requests a new access token from the token endpoint
+    pub fn fetch(&self) -> Result<Token, String> {
+        let mut form = vec![
+            ("grant_type", "client_credentials"),
+            ("client_id", self.client_id.as_str()),
+            ("client_secret", self.client_secret.as_str()),
+        ];
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope));
+        }
+
+        let body = match ureq::post(&self.token_url)
+            .timeout(TIMEOUT)
+            .send_form(&form)
+        {
+            Ok(response) => response.into_string().map_err(|err| err.to_string())?,
+            // the error response says why, e.g. invalid_client
+            Err(ureq::Error::Status(status, response)) => {
+                let body = response.into_string().unwrap_or_default();
+                return Err(format!("token endpoint answered {status}: {body}"));
+            }
+            Err(err) => return Err(err.to_string()),
+        };
+        let response: Value =
+            serde_json::from_str(&body).map_err(|err| format!("invalid json: {err}"))?;
+
+        let access_token = response["access_token"]
+            .as_str()
+            .ok_or_else(|| String::from("no access_token in the response"))?
+            .to_string();
+        // some servers send it as a string
+        let expires_in = response["expires_in"]
+            .as_u64()
+            .or_else(|| response["expires_in"].as_str()?.parse().ok())
+            .map(Duration::from_secs);
+
+        Ok(Token {
+            access_token,
+            expires_in,
+        })
+    }
+}
+
+/// How long a token is used before a new one is fetched: 80% of its lifetime, and at least `REFRESH_MARGIN`
+/// before it expires, unless that is less than half of it. None for tokens that don't expire.
+pub fn refresh_after(expires_in: Option<Duration>) -> Option<Duration> {
+    let expires_in = expires_in?;
+    Some(
+        expires_in
+            .mul_f64(0.8)
+            .min(expires_in.saturating_sub(REFRESH_MARGIN))
+            .max(expires_in / 2)
+            .max(Duration::from_secs(1)),
+    )
+}
+
+/// Fetches a new token before `token` expires, and so on. `on_refresh` is called after every attempt, with the new
+/// token if there is one, and the thread stops when it returns false, or with a token that doesn't expire.
+pub fn spawn_refresher<F>(credentials: ClientCredentials, token: Token, mut on_refresh: F)
+where
+    F: FnMut(Option<&str>) -> bool + Send + 'static,
+{
+    std::thread::Builder::new()
+        .name("oauth".into())
+        .spawn(move || {
+            let mut refresh = refresh_after(token.expires_in);
+            while let Some(after) = refresh {
+                std::thread::sleep(after);
+
+                let token = match credentials.fetch() {
+                    Ok(token) => {
+                        info!("oauth token refreshed");
+                        refresh = refresh_after(token.expires_in);
+                        Some(token.access_token)
+                    }
+                    // the current token may still be good for a while
+                    Err(err) => {
+                        warn!("could not refresh the oauth token: {err}");
+                        refresh = Some(RETRY_INTERVAL);
+                        None
+                    }
+                };
+                if !on_refresh(token.as_deref()) {
+                    return;
+                }
+            }
+        })
+        .expect("could not spawn oauth thread");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refresh_after_secs(expires_in: f64) -> f64 {
+        refresh_after(Some(Duration::from_secs_f64(expires_in)))
+            .unwrap()
+            .as_secs_f64()
+    }
+
+    #[test]
+    fn refresh_times() {
+        assert_eq!(refresh_after(None), None);
+        // 80% of the lifetime
+        assert_eq!(refresh_after_secs(3600.0), 2880.0);
+        // the margin before it expires
+        assert_eq!(refresh_after_secs(100.0), 70.0);
+        // half of the lifetime, when the margin would leave less
+        assert_eq!(refresh_after_secs(40.0), 20.0);
+        // never hammering the token endpoint
+        assert_eq!(refresh_after_secs(1.0), 1.0);
+        assert_eq!(refresh_after_secs(0.0), 1.0);
+    }
+}
@@ -0,0 +1,364 @@
+use std::path::PathBuf;
+
+use crate::error::Error;
+
+/// Query parameters srtsink understands in its uri, the ones of the element itself followed by the SRT socket options
+const SRT_PARAMETERS: &[&str] = &[
+    "mode",
+    "localaddress",
+    "localport",
+    "passphrase",
+    "pbkeylen",
+    "poll-timeout",
+    "latency",
+    "streamid",
+    "authentication",
+    "auto-reconnect",
+    "keep-listening",
+    "transtype",
+    "maxbw",
+    "mss",
+    "fc",
+    "sndbuf",
+    "rcvbuf",
+    "ipttl",
+    "iptos",
+    "inputbw",
+    "oheadbw",
+    "tsbpdmode",
+    "tlpktdrop",
+    "snddropdelay",
+    "nakreport",
+    "conntimeo",
+    "lossmaxttl",
+    "rcvlatency",
+    "peerlatency",
+    "minversion",
+    "congestion",
+    "messageapi",
+    "payloadsize",
+    "kmrefreshrate",
+    "kmpreannounce",
+    "enforcedencryption",
+    "ipv6only",
+    "peeridletimeo",
+    "packetfilter",
+];
+
+/// SRT parameters that take a number
+const SRT_NUMERIC_PARAMETERS: &[&str] = &[
+    "localport",
+    "pbkeylen",
+    "poll-timeout",
+    "latency",
+    "maxbw",
+    "mss",
+    "fc",
+    "sndbuf",
+    "rcvbuf",
+    "ipttl",
+    "iptos",
+    "inputbw",
+    "oheadbw",
+    "snddropdelay",
+    "conntimeo",
+    "lossmaxttl",
+    "rcvlatency",
+    "peerlatency",
+    "payloadsize",
+    "kmrefreshrate",
+    "kmpreannounce",
+    "peeridletimeo",
+];
+
+/// Where the mix goes, from the scheme of the output url
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    /// `srt://[host]:port[?parameters]`
+    Srt,
+    /// `decklink://<device number>`
+    Sdi { device: u32 },
+    /// `icecast://[user:password@]host:port/mount`
+    Icecast(Icecast),
+    /// `cmaf+file:///directory` or `cmaf+http[s]://host/path`
+    Cmaf(CmafDestination),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Icecast {
+    pub username: String,
+    pub password: String,
+    pub host: String,
+    pub port: u16,
+    pub mount: String,
+}
+
+/// Where the files of a CMAF output go
+#[derive(Debug, Clone, PartialEq)]
+pub enum CmafDestination {
+    /// A local directory, created if needed
+    Directory(PathBuf),
+    /// An HTTP ingest, every file is PUT below this url
+    Http(String),
+}
+
+impl Output {
+    /// Checks the output url up front, so mistakes are reported by name instead of by a failing pipeline
+    pub fn parse(output_url: &str) -> Result<Self, Error> {
+        let Some((scheme, rest)) = output_url.split_once("://") else {
+            return Err(Error::Config(format!(
+                "output url '{output_url}' has no scheme, expected srt://, decklink://, icecast:// or cmaf+file:// / cmaf+http[s]://"
+            )));
+        };
+
+        match scheme {
+            "srt" => validate_srt(rest).map(|_| Output::Srt),
+            "decklink" => parse_decklink(rest),
+            "icecast" => parse_icecast(rest),
+            "cmaf+file" | "cmaf+http" | "cmaf+https" => parse_cmaf(scheme, rest),
+            _ => Err(Error::Config(format!(
+                "unsupported output url scheme '{scheme}', expected srt://, decklink://, icecast:// or cmaf+file:// / cmaf+http[s]://"
+            ))),
+        }
+    }
+}
+
+fn validate_srt(rest: &str) -> Result<(), Error> {
+    let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+    // the host may be left out in listener mode, the port may not
+    let port = address
+        .trim_end_matches('/')
+        .rsplit_once(':')
+        .map(|(_, port)| port)
+        .ok_or_else(|| {
+            Error::Config(format!(
+                "SRT output '{address}' has no port, expected srt://[host]:port"
+            ))
+        })?;
+    if port.parse::<u16>().is_err() {
+        return Err(Error::Config(format!("invalid SRT port '{port}'")));
+    }
+
+    let parameters: Vec<(&str, &str)> = query
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| p.split_once('=').unwrap_or((p, "")))
+        .collect();
+
+    for &(key, value) in &parameters {
+        if !SRT_PARAMETERS.contains(&key) {
+            let suggestion = SRT_PARAMETERS
+                .iter()
+                .filter(|known| edit_distance(key, known) <= 2)
+                .min_by_key(|known| edit_distance(key, known))
+                .map_or(String::new(), |known| format!(", did you mean '{known}'?"));
+            return Err(Error::Config(format!(
+                "unknown SRT parameter '{key}'{suggestion}"
+            )));
+        }
+
+        if SRT_NUMERIC_PARAMETERS.contains(&key) && value.parse::<i64>().is_err() {
+            return Err(Error::Config(format!(
+                "SRT parameter '{key}' needs a number, got '{value}'"
+            )));
+        }
+
+        match key {
+            "mode" if !["caller", "listener", "rendezvous"].contains(&value) => {
+                return Err(Error::Config(format!(
+                    "invalid SRT mode '{value}', expected caller, listener or rendezvous"
+                )));
+            }
+            "pbkeylen" if !["0", "16", "24", "32"].contains(&value) => {
+                return Err(Error::Config(format!(
+                    "invalid SRT pbkeylen '{value}', expected 0, 16, 24 or 32"
+                )));
+            }
+            // each %XX escape is one character of the passphrase
+            "passphrase"
+                if !(10..=79).contains(&(value.len() - 2 * value.matches('%').count())) =>
+            {
+                return Err(Error::Config(String::from(
+                    "SRT passphrase must be 10 to 79 characters long",
+                )));
+            }
+            _ => (),
+        }
+    }
+
+    validate_key_material(&parameters)
+}
+
+/// Key rotation settings only mean something for encrypted outputs, and need to fit each other
+fn validate_key_material(parameters: &[(&str, &str)]) -> Result<(), Error> {
+    let get = |key: &str| {
+        parameters
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| *value)
+    };
+
+    let refresh = get("kmrefreshrate").and_then(|value| value.parse::<u64>().ok());
+    let pre_announce = get("kmpreannounce").and_then(|value| value.parse::<u64>().ok());
+
+    if (refresh.is_some() || pre_announce.is_some()) && get("passphrase").is_none() {
+        return Err(Error::Config(String::from(
+            "SRT key material refresh (kmrefreshrate/kmpreannounce) needs a passphrase",
+        )));
+    }
+
+    if let (Some(refresh), Some(pre_announce)) = (refresh, pre_announce)
+        && pre_announce > refresh / 2
+    {
+        return Err(Error::Config(format!(
+            "SRT kmpreannounce {pre_announce} is more than half of kmrefreshrate {refresh}"
+        )));
+    }
+
+    Ok(())
+}
+
+fn parse_decklink(rest: &str) -> Result<Output, Error> {
+    let device = rest.trim_end_matches('/').parse().map_err(|_| {
+        Error::Config(format!(
+            "invalid decklink device '{rest}', expected decklink://<device number>"
+        ))
+    })?;
+
+    Ok(Output::Sdi { device })
+}
+
+fn parse_icecast(rest: &str) -> Result<Output, Error> {
+    let invalid = || {
+        Error::Config(format!(
+            "invalid icecast url 'icecast://{rest}', expected icecast://[user:password@]host:port/mount"
+        ))
+    };
+
+    let (credentials, address) = match rest.rsplit_once('@') {
+        Some((credentials, address)) => (Some(credentials), address),
+        None => (None, rest),
+    };
+    let (username, password) = match credentials {
+        Some(credentials) => match credentials.split_once(':') {
+            Some((username, password)) => (username, password),
+            None => ("source", credentials),
+        },
+        None => ("source", "hackme"),
+    };
+
+    let (host_port, mount) = address.split_once('/').ok_or_else(invalid)?;
+    let (host, port) = host_port.rsplit_once(':').ok_or_else(invalid)?;
+    if host.is_empty() || mount.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(Output::Icecast(Icecast {
+        username: username.to_string(),
+        password: password.to_string(),
+        host: host.to_string(),
+        port: port.parse().map_err(|_| invalid())?,
+        mount: format!("/{mount}"),
+    }))
+}
+
+fn parse_cmaf(scheme: &str, rest: &str) -> Result<Output, Error> {
+    let rest = rest.trim_end_matches('/');
+
+    let destination = match scheme {
+        // cmaf+file:///abs/path or cmaf+file://relative/path
+        "cmaf+file" if !rest.is_empty() => CmafDestination::Directory(PathBuf::from(rest)),
+        "cmaf+http" | "cmaf+https"
+            if rest.split('/').next().is_some_and(|host| !host.is_empty()) =>
+        {
+            CmafDestination::Http(format!("{}://{rest}", &scheme["cmaf+".len()..]))
+        }
+        _ => {
+            return Err(Error::Config(format!(
+                "invalid CMAF output '{scheme}://{rest}', expected cmaf+file:///directory or cmaf+http[s]://host/path"
+            )));
+        }
+    };
+
+    Ok(Output::Cmaf(destination))
+}
+
+/// Replaces the host, port and query parameters of an `srt://` url, for the --srt-* options
+pub fn compose_srt_url(
+    base: &str,
+    host: Option<&str>,
+    port: Option<u16>,
+    parameters: &[(&str, String)],
+) -> Result<String, Error> {
+    let rest = base.strip_prefix("srt://").ok_or_else(|| {
+        Error::Config(format!(
+            "the --srt-* options need an srt:// output url, not '{base}'"
+        ))
+    })?;
+
+    let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let address = address.trim_end_matches('/');
+    let (base_host, base_port) = address.rsplit_once(':').unwrap_or((address, ""));
+
+    let host = host.unwrap_or(base_host);
+    let port = port.map_or(base_port.to_string(), |port| port.to_string());
+
+    let mut query: Vec<(String, String)> = query
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (key, value) = p.split_once('=').unwrap_or((p, ""));
+            (key.to_string(), value.to_string())
+        })
+        .collect();
+    for (key, value) in parameters {
+        let value = percent_encode(value);
+        match query.iter_mut().find(|(k, _)| k == key) {
+            Some(existing) => existing.1 = value,
+            None => query.push((key.to_string(), value)),
+        }
+    }
+
+    let mut url = format!("srt://{host}:{port}");
+    if !query.is_empty() {
+        let query: Vec<String> = query
+            .into_iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        url.push('?');
+        url.push_str(&query.join("&"));
+    }
+
+    Ok(url)
+}
+
+/// Escapes what would end a query value, or the quoted uri in the pipeline description
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Levenshtein distance, for suggesting the parameter that was probably meant
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
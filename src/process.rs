@@ -0,0 +1,74 @@
+use std::time::Instant;
+
+use serde_json::{Value, json};
+
+/// Resource usage of the whole gateway process
+#[derive(Debug, Clone, Default)]
+pub struct ProcessStats {
+    /// CPU usage since the previous sample, 100% per fully used core. None on the first sample.
+    pub cpu_percent: Option<f64>,
+    pub rss_bytes: u64,
+    pub threads: usize,
+}
+
+impl ProcessStats {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "cpu_percent": self.cpu_percent,
+            "rss_bytes": self.rss_bytes,
+            "threads": self.threads,
+        })
+    }
+}
+
+/// Takes samples of the process, each one's CPU usage since the previous one of the same sampler. Every consumer
+/// polling at its own interval keeps its own, so they don't cut each other's intervals short.
+#[derive(Debug, Default)]
+pub struct ProcessSampler {
+    /// CPU seconds consumed at the time of the previous sample
+    previous: Option<(Instant, f64)>,
+}
+
+impl ProcessSampler {
+    /// Samples /proc/self. Returns None where procfs is not available.
+    #[cfg(target_os = "linux")]
+    pub fn sample(&mut self) -> Option<ProcessStats> {
+        let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+        let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+
+        // the command name may contain spaces, so the numeric fields are counted from the closing paren
+        let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+        let utime: f64 = fields.get(11)?.parse().ok()?;
+        let stime: f64 = fields.get(12)?.parse().ok()?;
+        let threads: usize = fields.get(17)?.parse().ok()?;
+
+        let resident_pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+
+        let (ticks_per_second, page_size) = unsafe {
+            (
+                libc::sysconf(libc::_SC_CLK_TCK),
+                libc::sysconf(libc::_SC_PAGESIZE),
+            )
+        };
+
+        let now = Instant::now();
+        let cpu_seconds = (utime + stime) / ticks_per_second.max(1) as f64;
+
+        let cpu_percent = self.previous.and_then(|(at, prev_cpu_seconds)| {
+            let elapsed = now.duration_since(at).as_secs_f64();
+            (elapsed > 0.0).then(|| (cpu_seconds - prev_cpu_seconds) * 100.0 / elapsed)
+        });
+        self.previous = Some((now, cpu_seconds));
+
+        Some(ProcessStats {
+            cpu_percent,
+            rss_bytes: resident_pages * page_size.max(0) as u64,
+            threads,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn sample(&mut self) -> Option<ProcessStats> {
+        None
+    }
+}
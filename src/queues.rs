@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use gst::prelude::*;
+use gstreamer as gst;
+use serde_json::{Value, json};
+use tracing::{debug, warn};
+
+/// How often a queue overrun is logged as a warning, every overrun is counted
+const OVERRUN_WARN_EVERY: u64 = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QueueCounters {
+    /// Times the queue was full, upstream was blocked or (leaky) data was dropped
+    pub overruns: u64,
+    /// Times the queue ran empty, downstream was starved
+    pub underruns: u64,
+}
+
+/// Overrun/underrun counts per queue element of a pipeline
+#[derive(Debug, Clone, Default)]
+pub struct QueueMonitor(Arc<Mutex<BTreeMap<String, QueueCounters>>>);
+
+impl QueueMonitor {
+    pub fn counters(&self) -> BTreeMap<String, QueueCounters> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let queues: serde_json::Map<String, Value> = self
+            .counters()
+            .into_iter()
+            .map(|(name, counters)| {
+                (
+                    name,
+                    json!({ "overruns": counters.overruns, "underruns": counters.underruns }),
+                )
+            })
+            .collect();
+
+        Value::Object(queues)
+    }
+
+    fn attach(&self, queue: &gst::Element) {
+        let name = queue.name().to_string();
+        self.0.lock().unwrap().entry(name.clone()).or_default();
+
+        let counters = self.0.clone();
+        let queue_name = name.clone();
+        queue.connect("overrun", false, move |_| {
+            let overruns = {
+                let mut counters = counters.lock().unwrap();
+                let entry = counters.entry(queue_name.clone()).or_default();
+                entry.overruns += 1;
+                entry.overruns
+            };
+
+            if overruns == 1 || overruns % OVERRUN_WARN_EVERY == 0 {
+                warn!("queue {queue_name} overrun ({overruns} so far)");
+            }
+            None
+        });
+
+        let counters = self.0.clone();
+        queue.connect("underrun", false, move |_| {
+            let underruns = {
+                let mut counters = counters.lock().unwrap();
+                let entry = counters.entry(name.clone()).or_default();
+                entry.underruns += 1;
+                entry.underruns
+            };
+
+            // live pipelines drain their queues all the time, so these are only interesting in aggregate
+            debug!("queue {name} underrun ({underruns} so far)");
+            None
+        });
+    }
+}
+
+/// Counts overruns and underruns of every queue in the pipeline, including the ones that are added later
+pub fn watch(pipeline: &gst::Pipeline) -> QueueMonitor {
+    let monitor = QueueMonitor::default();
+
+    for elem in pipeline.iterate_recurse().into_iter().flatten() {
+        if is_queue(&elem) {
+            monitor.attach(&elem);
+        }
+    }
+
+    let monitor_clone = monitor.clone();
+    pipeline.connect_deep_element_added(move |_, _, elem| {
+        if is_queue(elem) {
+            monitor_clone.attach(elem);
+        }
+    });
+
+    monitor
+}
+
+fn is_queue(elem: &gst::Element) -> bool {
+    elem.type_().name() == "GstQueue"
+}
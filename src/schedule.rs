@@ -0,0 +1,320 @@
+use std::str::FromStr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tracing::{error, info};
+
+use crate::args::Args;
+use crate::error::Error;
+use crate::gateway::Gateway;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Pause before restarting a recurring window whose gateway ended early, e.g. on an error
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// A `--start-at`/`--stop-at` value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScheduleTime {
+    /// Once, from an RFC3339 timestamp like `2026-05-01T18:00:00Z` or `2026-05-01T20:00:00+02:00`
+    At(SystemTime),
+    /// Every day at `HH:MM[:SS]` UTC, stored as seconds since midnight
+    Daily(u64),
+}
+
+impl ScheduleTime {
+    /// The first occurrence strictly after `time`
+    fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        match *self {
+            ScheduleTime::At(at) => (at > time).then_some(at),
+            ScheduleTime::Daily(seconds) => {
+                let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+                let midnight = since_epoch.as_secs() / DAY.as_secs() * DAY.as_secs();
+
+                let mut next = UNIX_EPOCH + Duration::from_secs(midnight + seconds);
+                if next <= time {
+                    next += DAY;
+                }
+                Some(next)
+            }
+        }
+    }
+}
+
+impl FromStr for ScheduleTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(seconds) = parse_time_of_day(s) {
+            return Ok(ScheduleTime::Daily(seconds));
+        }
+
+        parse_rfc3339(s).map(ScheduleTime::At).ok_or_else(|| {
+            format!("'{s}' is neither an RFC3339 timestamp nor a daily HH:MM[:SS] time")
+        })
+    }
+}
+
+fn parse_time_of_day(s: &str) -> Option<u64> {
+    let parts: Vec<&str> = s.split(':').collect();
+    if !(2..=3).contains(&parts.len()) || parts.iter().any(|p| p.len() != 2) {
+        return None;
+    }
+
+    let numbers: Vec<u64> = parts
+        .iter()
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    let (hours, minutes, seconds) = (numbers[0], numbers[1], numbers.get(2).copied().unwrap_or(0));
+    if hours > 23 || minutes > 59 || seconds > 59 {
+        return None;
+    }
+
+    Some(hours * 3600 + minutes * 60 + seconds)
+}
+
+/// humantime only reads UTC timestamps, so a `+HH:MM`/`-HH:MM` offset is applied here
+fn parse_rfc3339(s: &str) -> Option<SystemTime> {
+    let (local, offset) = match s.len().checked_sub(6).map(|i| s.split_at(i)) {
+        Some((local, offset))
+            if (offset.starts_with('+') || offset.starts_with('-')) && local.contains('T') =>
+        {
+            let seconds = parse_time_of_day(&offset[1..])?;
+            (
+                local,
+                if offset.starts_with('-') {
+                    -(seconds as i64)
+                } else {
+                    seconds as i64
+                },
+            )
+        }
+        _ => (s, 0),
+    };
+
+    let time = humantime::parse_rfc3339_weak(local).ok()?;
+
+    if offset >= 0 {
+        time.checked_sub(Duration::from_secs(offset as u64))
+    } else {
+        time.checked_add(Duration::from_secs(offset.unsigned_abs()))
+    }
+}
+
+/// Runs the gateway only within the `--start-at`/`--stop-at` window. With a daily start time the window
+/// repeats every day, a new gateway (and WHEP session) is created for every window.
+#[derive(Clone)]
+pub struct Scheduler {
+    args: Args,
+    stopped: Arc<(Mutex<bool>, Condvar)>,
+    current: Arc<Mutex<Option<Gateway>>>,
+}
+
+impl Scheduler {
+    pub fn new(args: Args) -> Self {
+        Self {
+            args,
+            stopped: Arc::new((Mutex::new(false), Condvar::new())),
+            current: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Ends the current window, if any, and makes `run` return. Safe to call from any thread.
+    pub fn stop(&self) {
+        *self.stopped.0.lock().unwrap() = true;
+        self.stopped.1.notify_all();
+
+        if let Some(gateway) = self.current.lock().unwrap().as_ref() {
+            gateway.stop();
+        }
+    }
+
+    /// Blocks until no window is left or `stop` is called
+    pub fn run(&self) -> Result<(), Error> {
+        let recurring = matches!(self.args.start_at, Some(ScheduleTime::Daily(_)));
+
+        while let Some((start, stop)) = self.next_window(SystemTime::now()) {
+            info!(
+                "next window starts {}{}",
+                humantime::format_rfc3339_seconds(start),
+                stop.map_or(String::new(), |stop| format!(
+                    ", ends {}",
+                    humantime::format_rfc3339_seconds(stop)
+                ))
+            );
+
+            if !self.sleep_until(start) {
+                break;
+            }
+
+            let gateway = Gateway::new(self.args.clone())?;
+            *self.current.lock().unwrap() = Some(gateway.clone());
+
+            if let Some(stop) = stop {
+                let scheduler = self.clone();
+                let gateway = gateway.clone();
+                std::thread::spawn(move || {
+                    if scheduler.sleep_until(stop) {
+                        info!("scheduled window ended");
+                        gateway.stop();
+                    }
+                });
+            }
+
+            let result = gateway.run();
+            *self.current.lock().unwrap() = None;
+
+            if !recurring {
+                return result;
+            }
+            if let Err(err) = result {
+                error!("{err}");
+                if !self.sleep_until(SystemTime::now() + RESTART_DELAY) {
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The next window at or after `now`. Starts right away when `now` is already inside a window.
+    fn next_window(&self, now: SystemTime) -> Option<(SystemTime, Option<SystemTime>)> {
+        let start = match self.args.start_at {
+            None => now,
+            Some(ScheduleTime::At(at)) => at.max(now),
+            Some(daily @ ScheduleTime::Daily(_)) => {
+                let previous = daily.next_after(now)? - DAY;
+                let previous_stop = self.args.stop_at.and_then(|stop| stop.next_after(previous));
+
+                match previous_stop {
+                    Some(stop) if stop > now => now,
+                    _ => daily.next_after(now)?,
+                }
+            }
+        };
+
+        let stop = match self.args.stop_at {
+            None => None,
+            Some(stop_at) => Some(stop_at.next_after(start)?),
+        };
+
+        Some((start, stop))
+    }
+
+    /// Sleeps until `time`. Returns false if `stop` was called in the meantime.
+    fn sleep_until(&self, time: SystemTime) -> bool {
+        let (stopped, condvar) = &*self.stopped;
+        let mut stopped = stopped.lock().unwrap();
+
+        while !*stopped {
+            let Ok(remaining) = time.duration_since(SystemTime::now()) else {
+                break;
+            };
+            stopped = condvar.wait_timeout(stopped, remaining).unwrap().0;
+        }
+
+        !*stopped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn utc(s: &str) -> SystemTime {
+        humantime::parse_rfc3339(s).unwrap()
+    }
+
+    fn scheduler(window: &[&str]) -> Scheduler {
+        let mut cli = vec!["whep-srt", "-i", "http://localhost/whep"];
+        cli.extend(window);
+        Scheduler::new(Args::parse_from(cli))
+    }
+
+    #[test]
+    fn rfc3339_offsets() {
+        assert_eq!(
+            parse_rfc3339("2026-05-01T20:00:00+02:00"),
+            Some(utc("2026-05-01T18:00:00Z"))
+        );
+        assert_eq!(
+            parse_rfc3339("2026-05-01T20:00:00-05:30"),
+            Some(utc("2026-05-02T01:30:00Z"))
+        );
+        assert_eq!(
+            parse_rfc3339("2026-05-01T18:00:00Z"),
+            Some(utc("2026-05-01T18:00:00Z"))
+        );
+        assert_eq!(parse_rfc3339("2026-05-01T20:00:00+2:00"), None);
+    }
+
+    #[test]
+    fn time_of_day() {
+        assert_eq!(parse_time_of_day("06:30"), Some(6 * 3600 + 30 * 60));
+        assert_eq!(parse_time_of_day("23:59:59"), Some(86399));
+        assert_eq!(parse_time_of_day("24:00"), None);
+        assert_eq!(parse_time_of_day("6:30"), None);
+        assert_eq!(
+            "07:00".parse::<ScheduleTime>(),
+            Ok(ScheduleTime::Daily(7 * 3600))
+        );
+    }
+
+    #[test]
+    fn daily_window_over_midnight() {
+        let scheduler = scheduler(&["--start-at", "22:00", "--stop-at", "02:00"]);
+
+        assert_eq!(
+            scheduler.next_window(utc("2026-05-01T12:00:00Z")),
+            Some((
+                utc("2026-05-01T22:00:00Z"),
+                Some(utc("2026-05-02T02:00:00Z"))
+            ))
+        );
+    }
+
+    #[test]
+    fn starts_inside_a_daily_window() {
+        let scheduler = scheduler(&["--start-at", "22:00", "--stop-at", "02:00"]);
+        let now = utc("2026-05-02T01:00:00Z");
+        assert_eq!(
+            scheduler.next_window(now),
+            Some((now, Some(utc("2026-05-02T02:00:00Z"))))
+        );
+
+        let scheduler = self::scheduler(&["--start-at", "06:00", "--stop-at", "09:00"]);
+        let now = utc("2026-05-01T07:30:00Z");
+        assert_eq!(
+            scheduler.next_window(now),
+            Some((now, Some(utc("2026-05-01T09:00:00Z"))))
+        );
+        // after the window, the next day's
+        assert_eq!(
+            scheduler.next_window(utc("2026-05-01T09:00:00Z")),
+            Some((
+                utc("2026-05-02T06:00:00Z"),
+                Some(utc("2026-05-02T09:00:00Z"))
+            ))
+        );
+    }
+
+    #[test]
+    fn starts_inside_a_one_off_window() {
+        let scheduler = scheduler(&[
+            "--start-at",
+            "2026-05-01T18:00:00+02:00",
+            "--stop-at",
+            "2026-05-01T20:00:00+02:00",
+        ]);
+        let now = utc("2026-05-01T17:00:00Z");
+        assert_eq!(
+            scheduler.next_window(now),
+            Some((now, Some(utc("2026-05-01T18:00:00Z"))))
+        );
+        // over, there is no next one
+        assert_eq!(scheduler.next_window(utc("2026-05-01T18:00:00Z")), None);
+    }
+}
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+use gstreamer as gst;
+use tracing::{error, info};
+
+use crate::args::Args;
+use crate::bench::count_bytes;
+use crate::error::Error;
+use crate::gateway::Gateway;
+use crate::stats;
+
+/// Outcome of `--self-test`
+#[derive(Debug, Clone, Default)]
+pub struct SelfTestReport {
+    pub packets_sent: u64,
+    /// Bytes read back by the loopback caller, None when the output is in caller mode
+    pub bytes_received: Option<u64>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.packets_sent > 0 && self.bytes_received.is_none_or(|bytes| bytes > 0)
+    }
+}
+
+/// Streams internal test sources to the configured SRT output for `duration`.
+/// A listener output is read back by a local caller, a caller output needs its remote listener to be up.
+pub fn run(args: &Args, duration: Duration) -> Result<SelfTestReport, Error> {
+    let mut test_args = args.clone();
+    test_args.input_url = None;
+    test_args.command = None;
+
+    let gateway = Gateway::test_source(test_args)?;
+    let runner = gateway.clone();
+    let runner = std::thread::spawn(move || runner.run());
+
+    let started = Instant::now();
+    while !gateway.status().running && !runner.is_finished() {
+        if started.elapsed() > Duration::from_secs(5) {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    let output_url = args.srt_output_url()?;
+    let loopback = loopback_uri(&output_url)
+        .map(|uri| {
+            info!("self-test: reading back from {uri}");
+            LoopbackReceiver::start(&uri)
+        })
+        .transpose()?;
+
+    info!(
+        "self-test: streaming test sources to {} for {}s",
+        output_url,
+        duration.as_secs()
+    );
+
+    let started = Instant::now();
+    while started.elapsed() < duration && !runner.is_finished() {
+        std::thread::sleep(Duration::from_millis(200));
+    }
+
+    // read before stopping, listener stats disappear with the caller
+    let packets_sent = stats::collect(gateway.pipeline())
+        .srt
+        .map(|srt| srt.packets_sent())
+        .unwrap_or_default();
+    let bytes_received = loopback.map(|loopback| loopback.stop());
+
+    gateway.stop();
+    runner
+        .join()
+        .unwrap_or_else(|_| Err(Error::Pipeline(String::from("self-test pipeline panicked"))))?;
+
+    let report = SelfTestReport {
+        packets_sent,
+        bytes_received,
+    };
+
+    if report.passed() {
+        info!("self-test passed: {report:?}");
+    } else {
+        error!("self-test failed: {report:?}");
+    }
+
+    Ok(report)
+}
+
+/// For a listener output, the uri a local caller uses to connect to it
+fn loopback_uri(output_url: &str) -> Option<String> {
+    let rest = output_url.strip_prefix("srt://")?;
+    let (host_port, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+    let params: Vec<&str> = query.split('&').filter(|p| !p.is_empty()).collect();
+    if !params.contains(&"mode=listener") {
+        return None;
+    }
+
+    let port = host_port.rsplit_once(':')?.1;
+    let params: Vec<&str> = params
+        .into_iter()
+        .map(|p| {
+            if p == "mode=listener" {
+                "mode=caller"
+            } else {
+                p
+            }
+        })
+        .collect();
+
+    Some(format!("srt://127.0.0.1:{port}?{}", params.join("&")))
+}
+
+struct LoopbackReceiver {
+    pipeline: gst::Pipeline,
+    bytes: Arc<AtomicU64>,
+}
+
+impl LoopbackReceiver {
+    fn start(uri: &str) -> Result<Self, Error> {
+        let pipeline = gst::parse::launch(&format!("srtsrc uri=\"{uri}\" ! fakesink name=sink"))
+            .map_err(|err| Error::Pipeline(format!("could not create loopback receiver: {err}")))?
+            .dynamic_cast::<gst::Pipeline>()
+            .expect("could not cast pipeline");
+
+        let sink = pipeline
+            .by_name("sink")
+            .expect("could not get loopback sink");
+        let bytes = count_bytes(&sink);
+
+        pipeline
+            .set_state(gst::State::Playing)
+            .map_err(|err| Error::Pipeline(format!("could not start loopback receiver: {err}")))?;
+
+        Ok(Self { pipeline, bytes })
+    }
+
+    /// Returns the number of bytes received
+    fn stop(self) -> u64 {
+        let _ = self.pipeline.set_state(gst::State::Null);
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,76 @@
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+use tracing::info;
+
+/// Time from creating a gateway to the first milestones of its stream. Each is taken once, so re-subscribing
+/// doesn't change them. Cloning gives another handle to the same times.
+#[derive(Debug, Clone)]
+pub struct Startup {
+    created: Instant,
+    whep_connected: Arc<OnceLock<Duration>>,
+    first_rtp: Arc<OnceLock<Duration>>,
+    first_ts: Arc<OnceLock<Duration>>,
+}
+
+impl Default for Startup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Startup {
+    pub fn new() -> Self {
+        Self {
+            created: Instant::now(),
+            whep_connected: Arc::default(),
+            first_rtp: Arc::default(),
+            first_ts: Arc::default(),
+        }
+    }
+
+    /// Until the WebRTC connection to the WHEP source was established
+    pub fn whep_connected(&self) -> Option<Duration> {
+        self.whep_connected.get().copied()
+    }
+
+    /// Until the first RTP buffer of any track arrived from the WHEP input
+    pub fn first_rtp(&self) -> Option<Duration> {
+        self.first_rtp.get().copied()
+    }
+
+    /// Until the first transport stream packets were handed to the SRT sink
+    pub fn first_ts(&self) -> Option<Duration> {
+        self.first_ts.get().copied()
+    }
+
+    pub(crate) fn mark_whep_connected(&self) {
+        self.mark(&self.whep_connected, "WHEP connected");
+    }
+
+    pub(crate) fn mark_first_rtp(&self) {
+        self.mark(&self.first_rtp, "first RTP received");
+    }
+
+    pub(crate) fn mark_first_ts(&self) {
+        self.mark(&self.first_ts, "first TS sent to SRT");
+    }
+
+    fn mark(&self, milestone: &OnceLock<Duration>, name: &str) {
+        let elapsed = self.created.elapsed();
+        if milestone.set(elapsed).is_ok() {
+            info!("startup: {name} after {}ms", elapsed.as_millis());
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let ms = |milestone: Option<Duration>| milestone.map(|elapsed| elapsed.as_millis() as u64);
+
+        json!({
+            "whep_connected_ms": ms(self.whep_connected()),
+            "first_rtp_ms": ms(self.first_rtp()),
+            "first_ts_ms": ms(self.first_ts()),
+        })
+    }
+}
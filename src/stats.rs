@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+use gstreamer as gst;
+use serde_json::{Value, json};
+
+/// Counters for a single SRT socket (the caller-mode connection, or one listener caller)
+#[derive(Debug, Clone, Default)]
+pub struct SrtSocketStats {
+    pub packets_sent: u64,
+    pub packets_sent_lost: u64,
+    pub packets_retransmitted: u64,
+    pub bytes_sent: u64,
+    pub rtt_ms: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SrtStats {
+    pub sockets: Vec<SrtSocketStats>,
+}
+
+impl SrtStats {
+    pub fn packets_sent(&self) -> u64 {
+        self.sockets.iter().map(|s| s.packets_sent).sum()
+    }
+
+    pub fn packets_retransmitted(&self) -> u64 {
+        self.sockets.iter().map(|s| s.packets_retransmitted).sum()
+    }
+
+    /// Worst round-trip time among the connected sockets
+    pub fn max_rtt_ms(&self) -> Option<f64> {
+        self.sockets.iter().map(|s| s.rtt_ms).reduce(f64::max)
+    }
+}
+
+/// Counters for one inbound RTP stream on the WebRTC leg
+#[derive(Debug, Clone, Default)]
+pub struct InboundRtpStats {
+    pub ssrc: u32,
+    /// `audio` or `video`, when webrtcbin tells
+    pub kind: Option<String>,
+    pub packets_received: u64,
+    pub packets_lost: u64,
+    pub bytes_received: u64,
+    /// Interarrival jitter in seconds
+    pub jitter: f64,
+    /// Received bits per second since the previous collection, None on the first one
+    pub bitrate_bps: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WebRtcStats {
+    pub inbound: Vec<InboundRtpStats>,
+    /// Lost packets ULPFEC recovered, with --fec
+    pub fec_recovered: u64,
+    /// Lost packets ULPFEC had too little redundancy for
+    pub fec_unrecovered: u64,
+}
+
+impl WebRtcStats {
+    pub fn packets_received(&self) -> u64 {
+        self.inbound.iter().map(|s| s.packets_received).sum()
+    }
+
+    pub fn packets_lost(&self) -> u64 {
+        self.inbound.iter().map(|s| s.packets_lost).sum()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub srt: Option<SrtStats>,
+    pub webrtc: Option<WebRtcStats>,
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "srt": self.srt.as_ref().map(|srt| json!({
+                "callers": srt.sockets.len(),
+                "packets_sent": srt.packets_sent(),
+                "packets_retransmitted": srt.packets_retransmitted(),
+                "rtt_ms": srt.max_rtt_ms(),
+            })),
+            "webrtc": self.webrtc.as_ref().map(|webrtc| json!({
+                "packets_received": webrtc.packets_received(),
+                "packets_lost": webrtc.packets_lost(),
+                "fec_recovered": webrtc.fec_recovered,
+                "fec_unrecovered": webrtc.fec_unrecovered,
+                "tracks": webrtc.inbound.iter().map(|track| json!({
+                    "ssrc": track.ssrc,
+                    "kind": track.kind,
+                    "packets_received": track.packets_received,
+                    "packets_lost": track.packets_lost,
+                    "jitter_ms": track.jitter * 1000.0,
+                    "bitrate_bps": track.bitrate_bps.map(f64::round),
+                })).collect::<Vec<_>>(),
+            })),
+        })
+    }
+}
+
+/// Polls the pipeline for statistics every `interval` on a dedicated thread, until the pipeline is gone or shut
+/// down
+pub fn spawn_poller<F>(pipeline: &gst::Pipeline, interval: Duration, mut on_snapshot: F)
+where
+    F: FnMut(&Snapshot) + Send + 'static,
+{
+    let pipeline = pipeline.downgrade();
+    std::thread::Builder::new()
+        .name("stats".into())
+        .spawn(move || {
+            let mut sampler = StatsSampler::default();
+            loop {
+                std::thread::sleep(interval);
+                let Some(pipeline) = pipeline.upgrade() else {
+                    break;
+                };
+                if pipeline.current_state() == gst::State::Null {
+                    break;
+                }
+                on_snapshot(&sampler.collect(&pipeline));
+            }
+        })
+        .expect("could not spawn stats thread");
+}
+
+/// Collects snapshots with the bitrate of each WebRTC stream since the previous snapshot of the same sampler. Like
+/// `ProcessSampler`, every consumer keeps its own, and the byte counts go away with it.
+#[derive(Debug, Default)]
+pub struct StatsSampler {
+    /// Bytes received per stream at the previous collection, keyed by webrtcbin name and SSRC
+    previous: HashMap<(String, u32), (Instant, u64)>,
+}
+
+impl StatsSampler {
+    pub fn collect(&mut self, pipeline: &gst::Pipeline) -> Snapshot {
+        let srt = pipeline
+            .by_name("output")
+            .map(|sink| srt_stats(&sink.property::<gst::Structure>("stats")));
+
+        let webrtc = webrtcbin(pipeline).and_then(|webrtcbin| {
+            let mut stats = webrtc_stats(&webrtcbin)?;
+            self.update_bitrates(&webrtcbin.name(), &mut stats.inbound);
+            Some(stats)
+        });
+
+        Snapshot { srt, webrtc }
+    }
+
+    /// Fills in the bitrate of each stream from the bytes received since the previous collection
+    fn update_bitrates(&mut self, webrtcbin: &str, inbound: &mut [InboundRtpStats]) {
+        let now = Instant::now();
+
+        // streams that are gone are forgotten, as are all of a webrtcbin replaced on reconnect
+        self.previous.retain(|(name, ssrc), _| {
+            name == webrtcbin && inbound.iter().any(|track| track.ssrc == *ssrc)
+        });
+
+        for track in inbound {
+            let key = (webrtcbin.to_string(), track.ssrc);
+            if let Some((at, bytes)) = self.previous.get(&key) {
+                let elapsed = now.duration_since(*at).as_secs_f64();
+                if elapsed > 0.0 {
+                    track.bitrate_bps =
+                        Some(track.bytes_received.saturating_sub(*bytes) as f64 * 8.0 / elapsed);
+                }
+            }
+            self.previous.insert(key, (now, track.bytes_received));
+        }
+    }
+}
+
+/// A single snapshot, without bitrates
+pub fn collect(pipeline: &gst::Pipeline) -> Snapshot {
+    StatsSampler::default().collect(pipeline)
+}
+
+/// Local address of the candidate pair ICE selected for the WebRTC session, None before there is one
+pub fn webrtc_local_address(pipeline: &gst::Pipeline) -> Option<IpAddr> {
+    let stats = webrtcbin_stats(&webrtcbin(pipeline)?)?;
+
+    // webrtcbin only reports the selected pair
+    let pair = stats
+        .values()
+        .find(|stat| stat.name() == "candidate-pair")?;
+    let local = stats.get(&pair.get::<String>("local-candidate-id").ok()?)?;
+    local.get::<String>("address").ok()?.parse().ok()
+}
+
+fn webrtcbin(pipeline: &gst::Pipeline) -> Option<gst::Element> {
+    pipeline
+        .iterate_recurse()
+        .into_iter()
+        .flatten()
+        .find(|elem| elem.type_().name() == "GstWebRTCBin")
+}
+
+/// The stats webrtcbin reports, keyed by their id
+fn webrtcbin_stats(webrtcbin: &gst::Element) -> Option<HashMap<String, gst::Structure>> {
+    let promise = gst::Promise::new();
+    webrtcbin.emit_by_name::<()>("get-stats", &[&None::<gst::Pad>, &promise]);
+
+    if promise.wait() != gst::PromiseResult::Replied {
+        return None;
+    }
+    let reply = promise.get_reply()?;
+
+    Some(
+        reply
+            .iter()
+            .filter_map(|(id, value)| Some((id.to_string(), value.get::<gst::Structure>().ok()?)))
+            .collect(),
+    )
+}
+
+fn srt_stats(stats: &gst::StructureRef) -> SrtStats {
+    // listener mode reports one structure per connected caller, caller mode reports a single socket inline
+    let sockets = match stats.get::<gst::glib::ValueArray>("callers") {
+        Ok(callers) => callers
+            .iter()
+            .filter_map(|caller| caller.get::<gst::Structure>().ok())
+            .map(|caller| srt_socket_stats(&caller))
+            .collect(),
+        Err(_) if stats.has_field("packets-sent") => vec![srt_socket_stats(stats)],
+        Err(_) => Vec::new(),
+    };
+
+    SrtStats { sockets }
+}
+
+fn srt_socket_stats(s: &gst::StructureRef) -> SrtSocketStats {
+    SrtSocketStats {
+        packets_sent: number(s, "packets-sent").unwrap_or_default() as u64,
+        packets_sent_lost: number(s, "packets-sent-lost").unwrap_or_default() as u64,
+        packets_retransmitted: number(s, "packets-retransmitted").unwrap_or_default() as u64,
+        bytes_sent: number(s, "bytes-sent").unwrap_or_default() as u64,
+        rtt_ms: number(s, "rtt-ms").unwrap_or_default(),
+    }
+}
+
+fn webrtc_stats(webrtcbin: &gst::Element) -> Option<WebRtcStats> {
+    let stats = webrtcbin_stats(webrtcbin)?;
+
+    let mut inbound: Vec<InboundRtpStats> = stats
+        .values()
+        .filter(|stat| stat.name() == "inbound-rtp")
+        .map(|stat| InboundRtpStats {
+            ssrc: number(stat, "ssrc").unwrap_or_default() as u32,
+            kind: kind(stat, &stats),
+            packets_received: number(stat, "packets-received").unwrap_or_default() as u64,
+            packets_lost: number(stat, "packets-lost").unwrap_or_default().max(0.0) as u64,
+            bytes_received: number(stat, "bytes-received").unwrap_or_default() as u64,
+            jitter: number(stat, "jitter").unwrap_or_default(),
+            bitrate_bps: None,
+        })
+        .collect();
+    inbound.sort_by_key(|track| track.ssrc);
+
+    // the decoders webrtcbin adds for each stream when ULPFEC is negotiated
+    let (mut fec_recovered, mut fec_unrecovered) = (0, 0);
+    if let Some(bin) = webrtcbin.downcast_ref::<gst::Bin>() {
+        for decoder in bin
+            .iterate_recurse()
+            .into_iter()
+            .flatten()
+            .filter(|elem| elem.type_().name() == "GstRtpUlpFecDec")
+        {
+            fec_recovered += decoder.property::<u32>("recovered") as u64;
+            fec_unrecovered += decoder.property::<u32>("unrecovered") as u64;
+        }
+    }
+
+    Some(WebRtcStats {
+        inbound,
+        fec_recovered,
+        fec_unrecovered,
+    })
+}
+
+/// Media kind of an inbound stream, from the stream itself or else the mime type of its codec
+fn kind(stat: &gst::StructureRef, stats: &HashMap<String, gst::Structure>) -> Option<String> {
+    if let Ok(kind) = stat.get::<String>("kind") {
+        return Some(kind);
+    }
+
+    let codec = stats.get(&stat.get::<String>("codec-id").ok()?)?;
+    let mime_type = codec.get::<String>("mime-type").ok()?;
+    mime_type.split_once('/').map(|(kind, _)| kind.to_string())
+}
+
+/// Reads a numeric field regardless of which integer/float type the element used for it
+fn number(s: &gst::StructureRef, field: &str) -> Option<f64> {
+    let value = s.value(field).ok()?;
+
+    value
+        .get::<i64>()
+        .map(|v| v as f64)
+        .or_else(|_| value.get::<u64>().map(|v| v as f64))
+        .or_else(|_| value.get::<i32>().map(|v| v as f64))
+        .or_else(|_| value.get::<u32>().map(|v| v as f64))
+        .or_else(|_| value.get::<f64>())
+        .ok()
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use gstreamer as gst;
+use tracing::{debug, warn};
+
+/// The queue in front of srtsink, its streaming thread is the one pushing into the SRT output
+const OUTPUT_QUEUE: &str = "output_queue";
+
+/// Scheduling applied to every streaming thread of a pipeline as it starts
+#[derive(Debug, Clone, Default)]
+pub struct ThreadOptions {
+    /// Nice value, higher is lower priority (-20..19)
+    pub nice: Option<i32>,
+    /// Nice value of the thread feeding the SRT output, instead of `nice`
+    pub output_nice: Option<i32>,
+    /// SCHED_FIFO priority of the thread feeding the SRT output (1..99)
+    pub output_rt_priority: Option<i32>,
+}
+
+/// Installs the bus sync handler that tracks the pipeline's streaming threads.
+/// STREAM_STATUS messages are delivered synchronously on the streaming thread itself,
+/// which makes this the one place where per-thread scheduling can be changed.
+/// Returns the number of currently running streaming threads.
+pub fn watch(pipeline: &gst::Pipeline, options: ThreadOptions) -> Arc<AtomicUsize> {
+    let count = Arc::new(AtomicUsize::new(0));
+    let count_clone = count.clone();
+
+    let bus = pipeline.bus().expect("pipeline without bus");
+    bus.set_sync_handler(move |_, msg| {
+        if let gst::MessageView::StreamStatus(status) = msg.view() {
+            let (status_type, owner) = status.get();
+
+            match status_type {
+                gst::StreamStatusType::Enter => {
+                    count_clone.fetch_add(1, Ordering::Relaxed);
+                    debug!("streaming thread started for {}", owner.name());
+
+                    if owner.name() == OUTPUT_QUEUE {
+                        if let Some(priority) = options.output_rt_priority {
+                            set_rt_priority(priority);
+                        } else if let Some(nice) = options.output_nice.or(options.nice) {
+                            set_nice(nice);
+                        }
+                    } else if let Some(nice) = options.nice {
+                        set_nice(nice);
+                    }
+                }
+                gst::StreamStatusType::Leave => {
+                    count_clone.fetch_sub(1, Ordering::Relaxed);
+                }
+                _ => (),
+            }
+        }
+
+        gst::BusSyncReply::Pass
+    });
+
+    count
+}
+
+#[cfg(target_os = "linux")]
+fn set_nice(nice: i32) {
+    // on linux the nice value is per thread, so target the calling thread's id rather than the process
+    let ret = unsafe {
+        let tid = libc::syscall(libc::SYS_gettid) as libc::id_t;
+        libc::setpriority(libc::PRIO_PROCESS, tid, nice)
+    };
+
+    if ret != 0 {
+        warn!(
+            "could not set nice {nice} on streaming thread: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_nice(nice: i32) {
+    warn!("setting nice {nice} on streaming threads is only supported on linux");
+}
+
+#[cfg(target_os = "linux")]
+fn set_rt_priority(priority: i32) {
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+    // returns the error rather than setting errno
+    let ret =
+        unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) };
+
+    if ret != 0 {
+        warn!(
+            "could not set SCHED_FIFO priority {priority} on the output thread: {}",
+            std::io::Error::from_raw_os_error(ret)
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_rt_priority(priority: i32) {
+    warn!("SCHED_FIFO priority {priority} on the output thread is only supported on linux");
+}
@@ -0,0 +1,663 @@
+use std::str::FromStr;
+
+const PACKET_SIZE: usize = 188;
+const SYNC_BYTE: u8 = 0x47;
+const PAT_PID: u16 = 0;
+const NULL_PID: u16 = 0x1fff;
+const PAT_TABLE_ID: u8 = 0x00;
+const PMT_TABLE_ID: u8 = 0x02;
+
+/// Where a `--ts-descriptor` goes in the PMT
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DescriptorTarget {
+    /// The program info loop, describing the whole service
+    Program,
+    /// The ES info loop of every audio stream
+    Audio,
+    /// The ES info loop of every video stream
+    Video,
+    /// The ES info loop of the stream with this PID
+    Pid(u16),
+}
+
+/// A descriptor added to the PMT the muxer writes, from `TAG:HEXDATA[@program|audio|video|PID]`,
+/// e.g. `0x05:43554549` for a registration descriptor with format identifier `CUEI`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TsDescriptor {
+    pub tag: u8,
+    pub data: Vec<u8>,
+    pub target: DescriptorTarget,
+}
+
+impl TsDescriptor {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![self.tag, self.data.len() as u8];
+        bytes.extend(&self.data);
+        bytes
+    }
+
+    fn applies_to(&self, stream_type: u8, pid: u16) -> bool {
+        match self.target {
+            DescriptorTarget::Program => false,
+            DescriptorTarget::Audio => is_audio(stream_type),
+            DescriptorTarget::Video => is_video(stream_type),
+            DescriptorTarget::Pid(target) => target == pid,
+        }
+    }
+}
+
+impl FromStr for TsDescriptor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (descriptor, target) = s.split_once('@').unwrap_or((s, "program"));
+        let (tag, data) = descriptor
+            .split_once(':')
+            .ok_or_else(|| format!("'{s}' is not TAG:HEXDATA[@program|audio|video|PID]"))?;
+
+        let tag = parse_number(tag)
+            .and_then(|tag| u8::try_from(tag).ok())
+            .ok_or_else(|| format!("invalid descriptor tag '{tag}'"))?;
+
+        if data.len() % 2 != 0 {
+            return Err(format!(
+                "descriptor data '{data}' is not an even number of hex digits"
+            ));
+        }
+        let data = (0..data.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&data[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| format!("descriptor data '{data}' is not hex"))?;
+        if data.len() > 255 {
+            return Err(String::from("descriptor data is longer than 255 bytes"));
+        }
+
+        let target = match target {
+            "program" => DescriptorTarget::Program,
+            "audio" => DescriptorTarget::Audio,
+            "video" => DescriptorTarget::Video,
+            pid => DescriptorTarget::Pid(
+                parse_number(pid)
+                    .and_then(|pid| u16::try_from(pid).ok())
+                    .filter(|pid| *pid < 0x1fff)
+                    .ok_or_else(|| {
+                        format!("invalid descriptor target '{pid}', expected program, audio, video or a PID")
+                    })?,
+            ),
+        };
+
+        Ok(Self { tag, data, target })
+    }
+}
+
+fn parse_number(s: &str) -> Option<u32> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn is_audio(stream_type: u8) -> bool {
+    // MPEG-1/2 audio, AAC (ADTS and LATM), AC-3, E-AC-3
+    matches!(stream_type, 0x03 | 0x04 | 0x0f | 0x11 | 0x81 | 0x87)
+}
+
+fn is_video(stream_type: u8) -> bool {
+    // MPEG-1/2 video, MPEG-4 part 2, H.264, H.265
+    matches!(stream_type, 0x01 | 0x02 | 0x10 | 0x1b | 0x24)
+}
+
+/// Adds descriptors to the PMTs in muxed transport stream data. The muxer repeats the PMT unchanged, so every
+/// repetition is rewritten the same way and the version number can stay as it is.
+#[derive(Debug, Clone, Default)]
+pub struct PmtRewriter {
+    descriptors: Vec<TsDescriptor>,
+    pmt_pids: Vec<u16>,
+}
+
+impl PmtRewriter {
+    pub fn new(descriptors: Vec<TsDescriptor>) -> Self {
+        Self {
+            descriptors,
+            pmt_pids: Vec::new(),
+        }
+    }
+
+    /// Rewrites the PMT packets in `data`, a whole number of 188 byte packets. Returns the number of
+    /// PMTs that were too large to fit their packet with the descriptors added, those are left as they are.
+    pub fn process(&mut self, data: &mut [u8]) -> usize {
+        let mut skipped = 0;
+
+        for packet in data.chunks_exact_mut(PACKET_SIZE) {
+            let Some((pid, section_start)) = section_start(packet) else {
+                continue;
+            };
+
+            if pid == PAT_PID {
+                if let Some(pids) = pmt_pids(&packet[section_start..]) {
+                    self.pmt_pids = pids;
+                }
+            } else if self.pmt_pids.contains(&pid) && !self.rewrite_pmt(packet, section_start) {
+                skipped += 1;
+            }
+        }
+
+        skipped
+    }
+
+    fn rewrite_pmt(&self, packet: &mut [u8], section_start: usize) -> bool {
+        let section = &packet[section_start..];
+        if section.len() < 16 || section[0] != PMT_TABLE_ID {
+            return true;
+        }
+        let section_length = (usize::from(section[1] & 0x0f) << 8) | usize::from(section[2]);
+        // a PMT continuing in the next packet is not rewritten
+        if 3 + section_length > section.len() || section_length < 13 {
+            return false;
+        }
+
+        let program_info_length = (usize::from(section[10] & 0x0f) << 8) | usize::from(section[11]);
+        let es_start = 12 + program_info_length;
+        let crc_start = 3 + section_length - 4;
+        if es_start > crc_start {
+            return true;
+        }
+
+        let mut program_info = section[12..es_start].to_vec();
+        for descriptor in &self.descriptors {
+            if descriptor.target == DescriptorTarget::Program {
+                program_info.extend(descriptor.encode());
+            }
+        }
+
+        let mut streams = Vec::new();
+        let mut pos = es_start;
+        while pos + 5 <= crc_start {
+            let stream_type = section[pos];
+            let pid = (u16::from(section[pos + 1] & 0x1f) << 8) | u16::from(section[pos + 2]);
+            let es_info_length =
+                (usize::from(section[pos + 3] & 0x0f) << 8) | usize::from(section[pos + 4]);
+            let es_end = (pos + 5 + es_info_length).min(crc_start);
+
+            let mut es_info = section[pos + 5..es_end].to_vec();
+            for descriptor in &self.descriptors {
+                if descriptor.applies_to(stream_type, pid) {
+                    es_info.extend(descriptor.encode());
+                }
+            }
+
+            streams.push(stream_type);
+            streams.extend(&section[pos + 1..pos + 3]);
+            streams.push(0xf0 | ((es_info.len() >> 8) as u8 & 0x0f));
+            streams.push(es_info.len() as u8);
+            streams.extend(es_info);
+
+            pos = es_end;
+        }
+
+        // section header up to and including the PCR PID, then the program info length
+        let mut rewritten = section[..10].to_vec();
+        rewritten.push(0xf0 | ((program_info.len() >> 8) as u8 & 0x0f));
+        rewritten.push(program_info.len() as u8);
+        rewritten.extend(program_info);
+        rewritten.extend(streams);
+
+        let new_length = rewritten.len() - 3 + 4;
+        if rewritten.len() + 4 > packet.len() - section_start || new_length > 1021 {
+            return false;
+        }
+        rewritten[1] = (section[1] & 0xf0) | ((new_length >> 8) as u8 & 0x0f);
+        rewritten[2] = new_length as u8;
+        let crc = crc32_mpeg2(&rewritten);
+        rewritten.extend(crc.to_be_bytes());
+
+        let end = section_start + rewritten.len();
+        packet[section_start..end].copy_from_slice(&rewritten);
+        packet[end..].fill(0xff);
+
+        true
+    }
+}
+
+/// Marks a discontinuity in muxed transport stream data. Before the next packet of every PID, an adaptation field
+/// only packet with the discontinuity indicator set is inserted, with the continuity counter restarting there so
+/// the following packets continue from it. A PID first seen after two PATs is left as it is.
+///
+/// The buffers with inserted packets grow beyond the 7 packet SRT payload, srtsink sends them in payload sized
+/// messages.
+#[derive(Debug, Clone, Default)]
+pub struct DiscontinuityMarker {
+    /// PATs seen since `mark`, None when not marking
+    marking: Option<usize>,
+    marked_pids: Vec<u16>,
+}
+
+impl DiscontinuityMarker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts marking the discontinuity on every PID, from the next packet on
+    pub fn mark(&mut self) {
+        self.marking = Some(0);
+        self.marked_pids.clear();
+    }
+
+    /// True when `process` would leave the data as it is
+    pub fn is_idle(&self) -> bool {
+        self.marking.is_none()
+    }
+
+    /// `data`, a whole number of 188 byte packets, with the discontinuity packets inserted. None when there was
+    /// nothing to insert.
+    pub fn process(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        let pats = self.marking.as_mut()?;
+
+        let mut out = Vec::new();
+        for (index, packet) in data.chunks_exact(PACKET_SIZE).enumerate() {
+            let first = match packet_pid(packet) {
+                Some(NULL_PID) | None => None,
+                Some(pid) => {
+                    if pid == PAT_PID {
+                        *pats += 1;
+                    }
+                    (*pats < 2 && !self.marked_pids.contains(&pid)).then_some(pid)
+                }
+            };
+
+            if let Some(pid) = first {
+                self.marked_pids.push(pid);
+                if out.is_empty() {
+                    out.reserve(data.len() + PACKET_SIZE);
+                    out.extend(&data[..index * PACKET_SIZE]);
+                }
+                out.extend(discontinuity_packet(pid, packet));
+            }
+            if !out.is_empty() {
+                out.extend(packet);
+            }
+        }
+
+        if *pats >= 2 {
+            self.marking = None;
+        }
+
+        (!out.is_empty()).then_some(out)
+    }
+}
+
+fn packet_pid(packet: &[u8]) -> Option<u16> {
+    (packet.len() == PACKET_SIZE && packet[0] == SYNC_BYTE)
+        .then(|| (u16::from(packet[1] & 0x1f) << 8) | u16::from(packet[2]))
+}
+
+/// An adaptation field only packet with the discontinuity indicator set, to go right before `next`
+fn discontinuity_packet(pid: u16, next: &[u8]) -> [u8; PACKET_SIZE] {
+    // the counter only moves on packets with payload, so it is set to continue into `next`
+    let next_cc = next[3] & 0x0f;
+    let has_payload = next[3] & 0x10 != 0;
+    let cc = if has_payload {
+        next_cc.wrapping_sub(1) & 0x0f
+    } else {
+        next_cc
+    };
+
+    let mut packet = [0xff; PACKET_SIZE];
+    packet[0] = SYNC_BYTE;
+    packet[1] = (pid >> 8) as u8 & 0x1f;
+    packet[2] = pid as u8;
+    packet[3] = 0x20 | cc; // adaptation field only
+    packet[4] = (PACKET_SIZE - 5) as u8; // adaptation field length
+    packet[5] = 0x80; // discontinuity indicator
+    packet
+}
+
+/// PID and offset of the section in a packet that starts a PSI section
+fn section_start(packet: &[u8]) -> Option<(u16, usize)> {
+    if packet.len() != PACKET_SIZE || packet[0] != SYNC_BYTE {
+        return None;
+    }
+
+    let payload_unit_start = packet[1] & 0x40 != 0;
+    if !payload_unit_start {
+        return None;
+    }
+
+    let pid = (u16::from(packet[1] & 0x1f) << 8) | u16::from(packet[2]);
+    let adaptation_field_control = (packet[3] >> 4) & 0x03;
+    let mut payload = 4;
+    if adaptation_field_control & 0x02 != 0 {
+        payload += 1 + usize::from(packet[4]);
+    }
+    if adaptation_field_control & 0x01 == 0 || payload >= PACKET_SIZE {
+        return None;
+    }
+
+    let pointer = usize::from(packet[payload]);
+    let start = payload + 1 + pointer;
+    (start < PACKET_SIZE).then_some((pid, start))
+}
+
+/// PMT PIDs listed in a PAT section
+fn pmt_pids(section: &[u8]) -> Option<Vec<u16>> {
+    if section.len() < 12 || section[0] != PAT_TABLE_ID {
+        return None;
+    }
+    let section_length = (usize::from(section[1] & 0x0f) << 8) | usize::from(section[2]);
+    let end = (3 + section_length).checked_sub(4)?.min(section.len());
+
+    let pids = section[8..end]
+        .chunks_exact(4)
+        .filter(|program| u16::from_be_bytes([program[0], program[1]]) != 0) // 0 is the NIT
+        .map(|program| (u16::from(program[2] & 0x1f) << 8) | u16::from(program[3]))
+        .collect();
+
+    Some(pids)
+}
+
+/// CRC-32/MPEG-2 of PSI sections
+fn crc32_mpeg2(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+
+    for &byte in data {
+        crc ^= u32::from(byte) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PMT_PID: u16 = 0x1000;
+    const VIDEO_PID: u16 = 0x100;
+    const AUDIO_PID: u16 = 0x101;
+
+    /// `section` with its length and CRC filled in, in a packet of its own
+    fn psi_packet(pid: u16, mut section: Vec<u8>) -> [u8; PACKET_SIZE] {
+        let section_length = section.len() - 3 + 4;
+        section[1] = 0xb0 | (section_length >> 8) as u8;
+        section[2] = section_length as u8;
+        let crc = crc32_mpeg2(&section);
+        section.extend(crc.to_be_bytes());
+
+        let mut packet = [0xff; PACKET_SIZE];
+        packet[..5].copy_from_slice(&[SYNC_BYTE, 0x40 | (pid >> 8) as u8, pid as u8, 0x10, 0]);
+        packet[5..5 + section.len()].copy_from_slice(&section);
+        packet
+    }
+
+    fn pat() -> [u8; PACKET_SIZE] {
+        let mut section = vec![PAT_TABLE_ID, 0, 0, 0x00, 0x01, 0xc1, 0, 0];
+        section.extend([0x00, 0x01, 0xe0 | (PMT_PID >> 8) as u8, PMT_PID as u8]);
+        psi_packet(PAT_PID, section)
+    }
+
+    /// H.264 video with a stream identifier descriptor, then AAC audio without descriptors
+    fn pmt() -> [u8; PACKET_SIZE] {
+        let mut section = vec![PMT_TABLE_ID, 0, 0, 0x00, 0x01, 0xc1, 0, 0];
+        section.extend([0xe0 | (VIDEO_PID >> 8) as u8, VIDEO_PID as u8, 0xf0, 0]);
+        section.extend([
+            0x1b,
+            0xe0 | (VIDEO_PID >> 8) as u8,
+            VIDEO_PID as u8,
+            0xf0,
+            3,
+        ]);
+        section.extend([0x52, 1, 0x01]);
+        section.extend([
+            0x0f,
+            0xe0 | (AUDIO_PID >> 8) as u8,
+            AUDIO_PID as u8,
+            0xf0,
+            0,
+        ]);
+        psi_packet(PMT_PID, section)
+    }
+
+    /// The PMT section in `packet`, checked to be whole and with a valid CRC
+    fn section(packet: &[u8]) -> &[u8] {
+        let (_, start) = section_start(packet).unwrap();
+        let section_length =
+            (usize::from(packet[start + 1] & 0x0f) << 8) | usize::from(packet[start + 2]);
+        let section = &packet[start..start + 3 + section_length];
+        assert_eq!(crc32_mpeg2(section), 0, "CRC of the rewritten section");
+        assert!(packet[start + section.len()..].iter().all(|&b| b == 0xff));
+        section
+    }
+
+    /// Stream type, PID and ES info of every stream in a PMT section
+    fn streams(section: &[u8]) -> Vec<(u8, u16, &[u8])> {
+        let program_info_length = (usize::from(section[10] & 0x0f) << 8) | usize::from(section[11]);
+        let mut pos = 12 + program_info_length;
+        let mut streams = Vec::new();
+        while pos < section.len() - 4 {
+            let pid = (u16::from(section[pos + 1] & 0x1f) << 8) | u16::from(section[pos + 2]);
+            let length =
+                (usize::from(section[pos + 3] & 0x0f) << 8) | usize::from(section[pos + 4]);
+            streams.push((section[pos], pid, &section[pos + 5..pos + 5 + length]));
+            pos += 5 + length;
+        }
+        assert_eq!(pos, section.len() - 4);
+        streams
+    }
+
+    /// A packet of `pid` with continuity counter `cc`, carrying payload or only an adaptation field
+    fn packet(pid: u16, cc: u8, payload: bool) -> [u8; PACKET_SIZE] {
+        let mut packet = [0xff; PACKET_SIZE];
+        packet[..4].copy_from_slice(&[
+            SYNC_BYTE,
+            (pid >> 8) as u8,
+            pid as u8,
+            if payload { 0x10 } else { 0x20 } | cc,
+        ]);
+        if !payload {
+            packet[4] = (PACKET_SIZE - 5) as u8;
+            packet[5] = 0;
+        }
+        packet
+    }
+
+    /// PID, continuity counter and whether it is a discontinuity packet, for every packet in `data`
+    fn packets(data: &[u8]) -> Vec<(u16, u8, bool)> {
+        data.chunks_exact(PACKET_SIZE)
+            .map(|packet| {
+                let discontinuity = packet[3] & 0x30 == 0x20 && packet[5] & 0x80 != 0;
+                (packet_pid(packet).unwrap(), packet[3] & 0x0f, discontinuity)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn crc32_mpeg2_check_value() {
+        assert_eq!(crc32_mpeg2(b"123456789"), 0x0376_e6e7);
+        // a section followed by its CRC checks to 0
+        assert_eq!(crc32_mpeg2(section(&pat())), 0);
+    }
+
+    #[test]
+    fn parses_descriptors() {
+        assert_eq!(
+            "0x05:43554549".parse(),
+            Ok(TsDescriptor {
+                tag: 0x05,
+                data: b"CUEI".to_vec(),
+                target: DescriptorTarget::Program
+            })
+        );
+        assert_eq!(
+            "10:656e6700@0x101"
+                .parse::<TsDescriptor>()
+                .map(|d| d.target),
+            Ok(DescriptorTarget::Pid(0x101))
+        );
+        assert!("0x05:435".parse::<TsDescriptor>().is_err());
+        assert!("0x100:00".parse::<TsDescriptor>().is_err());
+        assert!("0x05:00@0x1fff".parse::<TsDescriptor>().is_err());
+    }
+
+    #[test]
+    fn adds_descriptors_to_the_pmt() {
+        let descriptors = [
+            "0x05:43554549@program",
+            "0x0a:656e6700@audio",
+            "0x28:4d4028@video",
+            "0x80:01@0x100",
+        ];
+        let mut rewriter =
+            PmtRewriter::new(descriptors.iter().map(|d| d.parse().unwrap()).collect());
+
+        let mut data = [pat(), pmt()].concat();
+        let original = data.clone();
+        assert_eq!(rewriter.process(&mut data), 0);
+        assert_eq!(
+            data[..PACKET_SIZE],
+            original[..PACKET_SIZE],
+            "PAT left as it is"
+        );
+
+        let section = section(&data[PACKET_SIZE..]);
+        let original_section = self::section(&original[PACKET_SIZE..]);
+        // header and PCR PID kept, 6 + 6 + 5 + 3 bytes of descriptors added
+        assert_eq!(section[3..10], original_section[3..10]);
+        assert_eq!(section.len(), original_section.len() + 20);
+
+        let program_info_length = (usize::from(section[10] & 0x0f) << 8) | usize::from(section[11]);
+        assert_eq!(
+            section[12..12 + program_info_length],
+            [0x05, 4, b'C', b'U', b'E', b'I']
+        );
+
+        assert_eq!(
+            streams(section),
+            [
+                (
+                    0x1b,
+                    VIDEO_PID,
+                    &[0x52, 1, 0x01, 0x28, 3, 0x4d, 0x40, 0x28, 0x80, 1, 0x01][..]
+                ),
+                (0x0f, AUDIO_PID, &[0x0a, 4, b'e', b'n', b'g', 0][..]),
+            ]
+        );
+
+        // the repetitions are rewritten the same way
+        let mut repeated = original[PACKET_SIZE..].to_vec();
+        assert_eq!(rewriter.process(&mut repeated), 0);
+        assert_eq!(repeated, data[PACKET_SIZE..]);
+    }
+
+    #[test]
+    fn leaves_a_pmt_too_large_to_fit() {
+        let descriptor = TsDescriptor {
+            tag: 0xf0,
+            data: vec![0; 170],
+            target: DescriptorTarget::Program,
+        };
+        let mut rewriter = PmtRewriter::new(vec![descriptor]);
+
+        let mut data = [pat(), pmt()].concat();
+        let original = data.clone();
+        assert_eq!(rewriter.process(&mut data), 1);
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn ignores_pmts_before_the_pat() {
+        let mut rewriter = PmtRewriter::new(vec!["0x05:43554549".parse().unwrap()]);
+
+        let mut data = pmt().to_vec();
+        assert_eq!(rewriter.process(&mut data), 0);
+        assert_eq!(data, pmt());
+    }
+
+    #[test]
+    fn marks_a_discontinuity_on_every_pid() {
+        let mut marker = DiscontinuityMarker::new();
+        let data = [
+            packet(PAT_PID, 3, true),
+            packet(PMT_PID, 0, true),
+            packet(VIDEO_PID, 7, true),
+            packet(VIDEO_PID, 8, true),
+            packet(NULL_PID, 0, true),
+        ]
+        .concat();
+        assert!(marker.is_idle());
+        assert_eq!(marker.process(&data), None);
+
+        marker.mark();
+        let out = marker.process(&data).unwrap();
+        assert_eq!(
+            packets(&out),
+            [
+                (PAT_PID, 2, true),
+                (PAT_PID, 3, false),
+                (PMT_PID, 15, true),
+                (PMT_PID, 0, false),
+                (VIDEO_PID, 6, true),
+                (VIDEO_PID, 7, false),
+                (VIDEO_PID, 8, false),
+                (NULL_PID, 0, false),
+            ]
+        );
+        assert!(
+            out.chunks_exact(PACKET_SIZE)
+                .filter(|packet| packet[3] & 0x30 == 0x20)
+                .all(|packet| packet[4] == 183)
+        );
+
+        // only the PIDs not seen yet, with an adaptation field only packet keeping its counter
+        let data = [
+            packet(VIDEO_PID, 9, true),
+            packet(AUDIO_PID, 4, false),
+            packet(AUDIO_PID, 4, true),
+        ]
+        .concat();
+        assert_eq!(
+            packets(&marker.process(&data).unwrap()),
+            [
+                (VIDEO_PID, 9, false),
+                (AUDIO_PID, 4, true),
+                (AUDIO_PID, 4, false),
+                (AUDIO_PID, 4, false),
+            ]
+        );
+        assert!(!marker.is_idle());
+
+        // a PID first seen after the second PAT is left as it is
+        let data = [packet(PAT_PID, 4, true), packet(0x102, 0, true)].concat();
+        assert_eq!(marker.process(&data), None);
+        assert!(marker.is_idle());
+        assert_eq!(marker.process(&data), None);
+    }
+
+    #[test]
+    fn marks_again() {
+        let mut marker = DiscontinuityMarker::new();
+        let data = [packet(PAT_PID, 0, true), packet(VIDEO_PID, 0, true)].concat();
+
+        marker.mark();
+        assert_eq!(
+            marker.process(&data).map(|out| out.len()),
+            Some(4 * PACKET_SIZE)
+        );
+        marker.mark();
+        assert_eq!(
+            packets(&marker.process(&data).unwrap()),
+            [
+                (PAT_PID, 15, true),
+                (PAT_PID, 0, false),
+                (VIDEO_PID, 15, true),
+                (VIDEO_PID, 0, false),
+            ]
+        );
+    }
+}
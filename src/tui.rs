@@ -0,0 +1,402 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event as TermEvent, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::error::Error;
+use crate::gateway::{Gateway, Status};
+use crate::stats::{Snapshot, StatsSampler};
+
+/// How often the dashboard is redrawn
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+/// Log lines kept for the log pane
+const LOG_LINES: usize = 200;
+/// Lowest level shown on the audio meters
+const METER_FLOOR_DB: f64 = -60.0;
+
+static LOG: LazyLock<LogBuffer> = LazyLock::new(LogBuffer::default);
+
+/// Writer for `tracing_subscriber`, which keeps the log for the dashboard while it is shown
+pub fn log_writer() -> LogBuffer {
+    LOG.clone()
+}
+
+/// The process log. Written to stderr, except while a dashboard is shown, which keeps the last lines for its log
+/// pane instead.
+#[derive(Clone, Default)]
+pub struct LogBuffer {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    attached: Arc<AtomicBool>,
+}
+
+impl LogBuffer {
+    fn attach(&self) {
+        self.lines.lock().unwrap().clear();
+        self.attached.store(true, Ordering::SeqCst);
+    }
+
+    /// Back to stderr, with the lines the dashboard kept so they aren't lost with the screen
+    fn detach(&self) {
+        self.attached.store(false, Ordering::SeqCst);
+        let mut stderr = io::stderr();
+        for line in self.lines.lock().unwrap().drain(..) {
+            let _ = writeln!(stderr, "{line}");
+        }
+    }
+
+    fn tail(&self, count: usize) -> Vec<String> {
+        let lines = self.lines.lock().unwrap();
+        lines
+            .iter()
+            .skip(lines.len().saturating_sub(count))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.attached.load(Ordering::SeqCst) {
+            return io::stderr().write(buf);
+        }
+
+        let mut lines = self.lines.lock().unwrap();
+        for line in String::from_utf8_lossy(buf).lines() {
+            if lines.len() == LOG_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line.to_string());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Live view of a gateway in the terminal, for operators running it interactively. Takes over the terminal until
+/// dropped.
+pub struct Dashboard {
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Dashboard {
+    /// Shows the gateway `current` returns, which may change when the pipeline is restarted. q or ctrl-c call
+    /// `on_quit`, the terminal doesn't send SIGINT while the dashboard is shown.
+    pub fn start<G, Q>(current: G, on_quit: Q) -> Result<Self, Error>
+    where
+        G: Fn() -> Option<Gateway> + Send + 'static,
+        Q: Fn() + Send + 'static,
+    {
+        let terminal = ratatui::try_init()
+            .map_err(|err| Error::Config(format!("could not set up the terminal: {err}")))?;
+        LOG.attach();
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped_clone = stopped.clone();
+        let thread = std::thread::Builder::new()
+            .name("tui".into())
+            .spawn(move || {
+                run(terminal, &stopped_clone, current, on_quit);
+                ratatui::restore();
+            })
+            .expect("could not spawn tui thread");
+
+        Ok(Self {
+            stopped,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        LOG.detach();
+    }
+}
+
+fn run<G, Q>(mut terminal: DefaultTerminal, stopped: &AtomicBool, current: G, on_quit: Q)
+where
+    G: Fn() -> Option<Gateway>,
+    Q: Fn(),
+{
+    let started = Instant::now();
+    let mut rates = Rates::default();
+    let mut sampler = StatsSampler::default();
+
+    while !stopped.load(Ordering::SeqCst) {
+        let view = current().map(|gateway| View::collect(&gateway, &mut sampler, &mut rates));
+        let _ = terminal.draw(|frame| draw(frame, view.as_ref(), started.elapsed()));
+
+        if !event::poll(REFRESH_INTERVAL).unwrap_or(false) {
+            continue;
+        }
+        if let Ok(TermEvent::Key(key)) = event::read()
+            && key.kind == KeyEventKind::Press
+            && (key.code == KeyCode::Char('q')
+                || (key.code == KeyCode::Char('c')
+                    && key.modifiers.contains(KeyModifiers::CONTROL)))
+        {
+            on_quit();
+        }
+    }
+}
+
+/// Bitrates from the byte counters of consecutive snapshots
+#[derive(Default)]
+struct Rates {
+    previous: Option<(Instant, u64, u64)>,
+}
+
+impl Rates {
+    /// Input and output bits per second since the previous call, None on the first one
+    fn update(&mut self, snapshot: &Snapshot) -> (Option<f64>, Option<f64>) {
+        let received = snapshot.webrtc.as_ref().map_or(0, |webrtc| {
+            webrtc
+                .inbound
+                .iter()
+                .map(|track| track.bytes_received)
+                .sum()
+        });
+        let sent = snapshot.srt.as_ref().map_or(0, |srt| {
+            srt.sockets.iter().map(|socket| socket.bytes_sent).sum()
+        });
+
+        let now = Instant::now();
+        let rates = match self.previous {
+            Some((then, previous_received, previous_sent)) => {
+                let elapsed = now.duration_since(then).as_secs_f64().max(0.001);
+                let rate = |bytes: u64, previous: u64| {
+                    bytes.saturating_sub(previous) as f64 * 8.0 / elapsed
+                };
+                (
+                    Some(rate(received, previous_received)),
+                    Some(rate(sent, previous_sent)),
+                )
+            }
+            None => (None, None),
+        };
+        self.previous = Some((now, received, sent));
+        rates
+    }
+}
+
+struct View {
+    status: Status,
+    snapshot: Snapshot,
+    input_bps: Option<f64>,
+    output_bps: Option<f64>,
+    peaks: Vec<f64>,
+    whep_connected: Option<Duration>,
+    first_ts: Option<Duration>,
+}
+
+impl View {
+    fn collect(gateway: &Gateway, sampler: &mut StatsSampler, rates: &mut Rates) -> Self {
+        let snapshot = sampler.collect(gateway.pipeline());
+        let (input_bps, output_bps) = rates.update(&snapshot);
+
+        Self {
+            status: gateway.status(),
+            snapshot,
+            input_bps,
+            output_bps,
+            peaks: gateway.audio_peaks(),
+            whep_connected: gateway.startup().whep_connected(),
+            first_ts: gateway.startup().first_ts(),
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, view: Option<&View>, uptime: Duration) {
+    let meters = view.map_or(0, |view| view.peaks.len().max(1)) as u16;
+    let [state_area, stats_area, meters_area, log_area] = Layout::vertical([
+        Constraint::Length(4),
+        Constraint::Length(7),
+        Constraint::Length(meters + 2),
+        Constraint::Min(3),
+    ])
+    .areas(frame.area());
+
+    let title = format!(" whep-srt, up {}s, q to quit ", uptime.as_secs());
+    match view {
+        Some(view) => {
+            frame.render_widget(
+                state(view).block(Block::bordered().title(title)),
+                state_area,
+            );
+
+            let [input_area, output_area] =
+                Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .areas(stats_area);
+            frame.render_widget(
+                input(view).block(Block::bordered().title(" WHEP input ")),
+                input_area,
+            );
+            frame.render_widget(
+                output(view).block(Block::bordered().title(" SRT output ")),
+                output_area,
+            );
+
+            draw_meters(frame, &view.peaks, meters_area);
+        }
+        None => frame.render_widget(
+            Paragraph::new("starting").block(Block::bordered().title(title)),
+            state_area,
+        ),
+    }
+
+    let log = LOG.tail(log_area.height.saturating_sub(2) as usize);
+    frame.render_widget(
+        Paragraph::new(log.into_iter().map(Line::from).collect::<Vec<_>>())
+            .block(Block::bordered().title(" Log ")),
+        log_area,
+    );
+}
+
+fn state(view: &View) -> Paragraph<'static> {
+    let status = &view.status;
+    let state = match (status.running, status.paused) {
+        (false, _) => "stopped",
+        (true, Some(true)) => "paused, slate",
+        (true, Some(false)) => "paused",
+        (true, None) if status.suspended => "suspended, no callers",
+        (true, None) => "streaming",
+    };
+    let ms = |milestone: Option<Duration>| {
+        milestone.map_or(String::from("-"), |elapsed| {
+            format!("{}ms", elapsed.as_millis())
+        })
+    };
+
+    Paragraph::new(vec![
+        Line::from(format!(
+            "state: {state}, WHEP {}, SRT callers: {}, startup: connected {} first TS {}",
+            if status.whep_connected {
+                "connected"
+            } else {
+                "not connected"
+            },
+            status.srt_callers,
+            ms(view.whep_connected),
+            ms(view.first_ts),
+        )),
+        Line::from(format!(
+            "last error: {}",
+            status.last_error.as_deref().unwrap_or("-")
+        ))
+        .style(Style::default().fg(if status.last_error.is_some() {
+            Color::Red
+        } else {
+            Color::Reset
+        })),
+    ])
+}
+
+fn input(view: &View) -> Paragraph<'static> {
+    let Some(webrtc) = &view.snapshot.webrtc else {
+        return Paragraph::new("no WebRTC session");
+    };
+
+    let mut lines = vec![Line::from(format!(
+        "bitrate: {}, packets: {} lost: {}",
+        kbps(view.input_bps),
+        webrtc.packets_received(),
+        webrtc.packets_lost()
+    ))];
+    lines.extend(webrtc.inbound.iter().map(|track| {
+        Line::from(format!(
+            "{} {}: lost {} jitter {:.1}ms",
+            track.kind.as_deref().unwrap_or("track"),
+            track.ssrc,
+            track.packets_lost,
+            track.jitter * 1000.0
+        ))
+    }));
+    Paragraph::new(lines)
+}
+
+fn output(view: &View) -> Paragraph<'static> {
+    let Some(srt) = &view.snapshot.srt else {
+        return Paragraph::new("no SRT sink");
+    };
+
+    let sent = srt.packets_sent();
+    let lost: u64 = srt
+        .sockets
+        .iter()
+        .map(|socket| socket.packets_sent_lost)
+        .sum();
+    let pct = |count: u64| {
+        if sent == 0 {
+            0.0
+        } else {
+            count as f64 * 100.0 / sent as f64
+        }
+    };
+
+    Paragraph::new(vec![
+        Line::from(format!(
+            "bitrate: {}, callers: {}",
+            kbps(view.output_bps),
+            srt.sockets.len()
+        )),
+        Line::from(format!(
+            "RTT: {}",
+            srt.max_rtt_ms()
+                .map_or(String::from("-"), |rtt| format!("{rtt:.1}ms"))
+        )),
+        Line::from(format!(
+            "loss: {:.2}%, retransmitted: {:.2}%",
+            pct(lost),
+            pct(srt.packets_retransmitted())
+        )),
+    ])
+}
+
+fn draw_meters(frame: &mut Frame, peaks: &[f64], area: Rect) {
+    let block = Block::bordered().title(" Audio peaks ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    if peaks.is_empty() {
+        frame.render_widget(Paragraph::new("no mix, audio passthrough"), inner);
+        return;
+    }
+
+    let rows = Layout::vertical(vec![Constraint::Length(1); peaks.len()]).split(inner);
+    for (index, (&peak, row)) in peaks.iter().zip(rows.iter()).enumerate() {
+        let color = match peak {
+            peak if peak > -1.0 => Color::Red,
+            peak if peak > -9.0 => Color::Yellow,
+            _ => Color::Green,
+        };
+        let ratio = ((peak - METER_FLOOR_DB) / -METER_FLOOR_DB).clamp(0.0, 1.0);
+        frame.render_widget(
+            Gauge::default()
+                .gauge_style(Style::default().fg(color))
+                .ratio(ratio)
+                .label(format!("{} {peak:.1} dBFS", index + 1)),
+            *row,
+        );
+    }
+}
+
+fn kbps(bps: Option<f64>) -> String {
+    bps.map_or(String::from("-"), |bps| format!("{:.0} kbps", bps / 1000.0))
+}
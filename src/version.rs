@@ -0,0 +1,110 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use gst::prelude::*;
+use gstreamer::{self as gst, ElementFactory};
+
+use crate::error::Error;
+use crate::gateway;
+
+/// Elements of the pipeline whose plugins `version --full` reports, each followed by its substitutes
+const ELEMENTS: &[&str] = &[
+    "whepclientsrc",
+    "whepsrc",
+    "webrtcbin",
+    "decodebin",
+    "opusdec",
+    "liveadder",
+    "avenc_aac",
+    "mpegtsmux",
+    "srtsink",
+];
+
+/// The plugin providing an element
+#[derive(Debug, Clone)]
+pub struct PluginVersion {
+    pub name: String,
+    pub version: String,
+    /// Shared library the plugin was loaded from, None when it is linked in, like the gstrswebrtc plugins
+    pub path: Option<PathBuf>,
+}
+
+/// Output of the `version` command
+#[derive(Debug, Clone)]
+pub struct VersionReport {
+    pub version: &'static str,
+    /// With --full, GStreamer's version and the plugin of each element, None where it is missing
+    pub gstreamer: Option<String>,
+    pub elements: Vec<(&'static str, Option<PluginVersion>)>,
+}
+
+impl VersionReport {
+    pub fn collect(full: bool) -> Result<Self, Error> {
+        let mut report = Self {
+            version: env!("CARGO_PKG_VERSION"),
+            gstreamer: None,
+            elements: Vec::new(),
+        };
+        if !full {
+            return Ok(report);
+        }
+
+        gateway::init()?;
+        report.gstreamer = Some(gst::version_string().to_string());
+        report.elements = ELEMENTS
+            .iter()
+            .flat_map(|&element| {
+                std::iter::once(element).chain(gateway::substitutes(element).iter().copied())
+            })
+            .map(|element| (element, plugin(element)))
+            .collect();
+
+        Ok(report)
+    }
+}
+
+impl fmt::Display for VersionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "whep-srt {}", self.version)?;
+        if let Some(gstreamer) = &self.gstreamer {
+            write!(f, "\n{gstreamer}")?;
+        }
+
+        let width = self
+            .elements
+            .iter()
+            .map(|(element, _)| element.len())
+            .max()
+            .unwrap_or_default();
+        for (element, plugin) in &self.elements {
+            match plugin {
+                Some(plugin) => {
+                    let path = plugin
+                        .path
+                        .as_ref()
+                        .map_or(String::from("(built in)"), |path| {
+                            path.display().to_string()
+                        });
+                    write!(
+                        f,
+                        "\n{element:width$}  {} {}  {path}",
+                        plugin.name, plugin.version
+                    )?
+                }
+                None => write!(f, "\n{element:width$}  missing")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn plugin(element: &str) -> Option<PluginVersion> {
+    let plugin = ElementFactory::find(element)?.plugin()?;
+
+    Some(PluginVersion {
+        name: plugin.plugin_name().to_string(),
+        version: plugin.version().to_string(),
+        path: plugin.filename(),
+    })
+}
@@ -0,0 +1,519 @@
+//! The gateway's own WHEP signalling, for --ice-restart: the offer is POSTed to the endpoint, which answers with the
+//! Location of the session resource. An ICE restart PATCHes that resource with new ICE credentials, and the
+//! session ends with a DELETE on it.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use tracing::{debug, warn};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The characters ICE credentials are made of, RFC 8839 ice-char
+const ICE_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A session the WHEP endpoint created for an offer
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// Url of the session resource
+    pub url: String,
+}
+
+/// POSTs an SDP offer with all its ICE candidates to the WHEP endpoint. Returns the session and the SDP answer.
+pub fn subscribe(
+    endpoint: &str,
+    token: Option<&str>,
+    offer: &str,
+) -> Result<(Session, String), String> {
+    let response = authorize(ureq::post(endpoint), token)
+        .set("Content-Type", "application/sdp")
+        .send_string(offer)
+        .map_err(|err| failure("whep endpoint", err))?;
+
+    let location = response
+        .header("Location")
+        .ok_or_else(|| String::from("no Location for the session in the answer"))?;
+    let url = resolve(response.get_url(), location);
+    let answer = response.into_string().map_err(|err| err.to_string())?;
+
+    Ok((Session { url }, answer))
+}
+
+impl Session {
+    /// PATCHes the session with a restart offer as an sdpfrag (RFC 8840). Returns the endpoint's new ICE
+    /// credentials and candidates, in an sdpfrag as well.
+    pub fn restart_ice(&self, token: Option<&str>, sdpfrag: &str) -> Result<String, String> {
+        let response = authorize(ureq::patch(&self.url), token)
+            .set("Content-Type", "application/trickle-ice-sdpfrag")
+            // an ICE restart applies to whatever the session is at
+            .set("If-Match", "*")
+            .send_string(sdpfrag);
+
+        match response {
+            Ok(response) if response.status() == 200 => {
+                response.into_string().map_err(|err| err.to_string())
+            }
+            // 204 is the answer to trickled candidates, the restart was not taken as one
+            Ok(response) => Err(format!(
+                "whep session answered {} without ice credentials",
+                response.status()
+            )),
+            Err(ureq::Error::Status(status @ (405 | 501), _)) => Err(format!(
+                "whep session answered {status}, the server doesn't restart ice"
+            )),
+            Err(err) => Err(failure("whep session", err)),
+        }
+    }
+
+    /// Ends the session with a DELETE
+    pub fn leave(&self, token: Option<&str>) {
+        match authorize(ureq::delete(&self.url), token).call() {
+            Ok(_) => debug!("left the whep session at {}", self.url),
+            Err(err) => warn!(
+                "could not leave the whep session at {}: {}",
+                self.url,
+                failure("whep session", err)
+            ),
+        }
+    }
+}
+
+fn authorize(request: ureq::Request, token: Option<&str>) -> ureq::Request {
+    let request = request.timeout(TIMEOUT);
+    match token {
+        Some(token) => request.set("Authorization", &format!("Bearer {token}")),
+        None => request,
+    }
+}
+
+fn failure(what: &str, err: ureq::Error) -> String {
+    match err {
+        // the error response says why
+        ureq::Error::Status(status, response) => {
+            let body = response.into_string().unwrap_or_default();
+            format!("{what} answered {status}: {}", body.trim())
+        }
+        ureq::Error::Transport(transport) => transport.to_string(),
+    }
+}
+
+/// The url a Location header points to, relative to the url of the request
+fn resolve(base: &str, location: &str) -> String {
+    let scheme_end = base.find("://").map_or(0, |i| i + 3);
+    let base = base.split(['?', '#']).next().unwrap_or(base);
+
+    if location.contains("://") {
+        location.to_string()
+    } else if location.starts_with("//") {
+        format!("{}{location}", &base[..scheme_end.saturating_sub(2)])
+    } else if location.starts_with('/') {
+        let origin_end = base[scheme_end..]
+            .find('/')
+            .map_or(base.len(), |i| scheme_end + i);
+        format!("{}{location}", &base[..origin_end])
+    } else {
+        let directory_end = base[scheme_end..]
+            .rfind('/')
+            .map_or(base.len(), |i| scheme_end + i);
+        format!("{}/{location}", &base[..directory_end])
+    }
+}
+
+/// New random ICE credentials: a 4 character ufrag and a 24 character password, RFC 8839 asks for at least 22
+pub fn ice_credentials() -> (String, String) {
+    let random = RandomState::new();
+    let mut hasher = random.build_hasher();
+    let mut chars = std::iter::repeat_with(|| {
+        hasher.write_u8(0);
+        ICE_CHARS[(hasher.finish() % ICE_CHARS.len() as u64) as usize] as char
+    });
+
+    let ufrag = chars.by_ref().take(4).collect();
+    let pwd = chars.take(24).collect();
+    (ufrag, pwd)
+}
+
+/// `sdp` with the ICE credentials of every media replaced
+pub fn set_ice_credentials(sdp: &str, ufrag: &str, pwd: &str) -> String {
+    let mut lines = Vec::new();
+    for line in sdp.lines() {
+        if line.starts_with("a=ice-ufrag:") {
+            lines.push(format!("a=ice-ufrag:{ufrag}"));
+        } else if line.starts_with("a=ice-pwd:") {
+            lines.push(format!("a=ice-pwd:{pwd}"));
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+    sdp_text(&lines)
+}
+
+/// The sdpfrag of an ICE restart offer: its ICE credentials, and the mid and candidates of each media. Candidates
+/// come from `previous`, the local description before the restart, when ICE gathered none for the offer.
+pub fn sdpfrag(offer: &str, previous: &str) -> String {
+    let (mut ufrag, mut pwd) = (None, None);
+    let mut media: Vec<(String, String)> = Vec::new();
+    for line in offer.lines() {
+        if line.starts_with("m=") {
+            media.push((line.to_string(), String::new()));
+        } else if let Some(mid) = line.strip_prefix("a=mid:")
+            && let Some((_, media_mid)) = media.last_mut()
+        {
+            *media_mid = mid.to_string();
+        } else if line.starts_with("a=ice-ufrag:") {
+            ufrag.get_or_insert(line);
+        } else if line.starts_with("a=ice-pwd:") {
+            pwd.get_or_insert(line);
+        }
+    }
+
+    let mut gathered = candidates(offer);
+    if gathered.is_empty() {
+        gathered = candidates(previous);
+    }
+
+    let mut lines: Vec<String> = ufrag.into_iter().chain(pwd).map(String::from).collect();
+    for (m_line, mid) in &media {
+        lines.push(m_line.clone());
+        lines.push(format!("a=mid:{mid}"));
+        lines.extend(
+            gathered
+                .iter()
+                .filter(|(candidate_mid, _)| candidate_mid.as_deref() == Some(mid))
+                .map(|(_, candidate)| candidate.clone()),
+        );
+    }
+    sdp_text(&lines)
+}
+
+/// The a=candidate lines of an SDP or sdpfrag, with the mid of the media they are in. None for those ahead of the
+/// first media.
+fn candidates(sdp: &str) -> Vec<(Option<String>, String)> {
+    let mut mid = None;
+    let mut candidates = Vec::new();
+    for line in sdp.lines() {
+        if line.starts_with("m=") {
+            mid = Some(String::new());
+        } else if let Some(media_mid) = line.strip_prefix("a=mid:") {
+            mid = Some(media_mid.to_string());
+        } else if line.starts_with("a=candidate:") {
+            candidates.push((mid.clone(), line.to_string()));
+        }
+    }
+    candidates
+}
+
+/// The previous SDP answer with the ICE credentials and candidates of the endpoint's answer to an ICE restart. None
+/// when the sdpfrag has no new credentials.
+pub fn apply_sdpfrag(answer: &str, sdpfrag: &str) -> Option<String> {
+    let value = |attribute: &str| {
+        sdpfrag
+            .lines()
+            .find_map(|line| line.strip_prefix(attribute))
+            .map(String::from)
+    };
+    let ufrag = value("a=ice-ufrag:")?;
+    let pwd = value("a=ice-pwd:")?;
+    let candidates = candidates(sdpfrag);
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut media: Option<String> = None;
+    let mut first_media = true;
+    let mut end_media = |lines: &mut Vec<String>, mid: &str| {
+        // candidates sent ahead of any media are for the first, which carries the bundle
+        lines.extend(
+            candidates
+                .iter()
+                .filter(|(candidate_mid, _)| match candidate_mid {
+                    Some(candidate_mid) => candidate_mid == mid,
+                    None => first_media,
+                })
+                .map(|(_, candidate)| candidate.clone()),
+        );
+        first_media = false;
+    };
+
+    for line in answer.lines() {
+        if line.starts_with("m=") {
+            if let Some(mid) = &media {
+                end_media(&mut lines, mid);
+            }
+            media = Some(String::new());
+        } else if let Some(mid) = line.strip_prefix("a=mid:")
+            && media.is_some()
+        {
+            media = Some(mid.to_string());
+        }
+
+        if line.starts_with("a=candidate:") || line == "a=end-of-candidates" {
+            continue;
+        } else if line.starts_with("a=ice-ufrag:") {
+            lines.push(format!("a=ice-ufrag:{ufrag}"));
+        } else if line.starts_with("a=ice-pwd:") {
+            lines.push(format!("a=ice-pwd:{pwd}"));
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+    if let Some(mid) = &media {
+        end_media(&mut lines, mid);
+    }
+
+    Some(sdp_text(&lines))
+}
+
+fn sdp_text(lines: &[String]) -> String {
+    lines.iter().map(|line| format!("{line}\r\n")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sdp(lines: &[&str]) -> String {
+        lines.iter().map(|line| format!("{line}\r\n")).collect()
+    }
+
+    fn offer() -> String {
+        sdp(&[
+            "v=0",
+            "a=group:BUNDLE video0 audio1",
+            "m=video 9 UDP/TLS/RTP/SAVPF 102",
+            "a=ice-ufrag:old1",
+            "a=ice-pwd:oldoldoldoldoldoldoldold",
+            "a=mid:video0",
+            "a=candidate:1 1 UDP 2015363327 192.0.2.10 50000 typ host",
+            "a=candidate:2 1 UDP 1015021823 198.51.100.7 50001 typ srflx",
+            "m=audio 9 UDP/TLS/RTP/SAVPF 111",
+            "a=ice-ufrag:old1",
+            "a=ice-pwd:oldoldoldoldoldoldoldold",
+            "a=mid:audio1",
+        ])
+    }
+
+    #[test]
+    fn resolves_locations() {
+        let base = "https://origin.example.com/live/channel1/whep?token=x";
+        assert_eq!(
+            resolve(base, "https://edge.example.com/session/1"),
+            "https://edge.example.com/session/1"
+        );
+        assert_eq!(
+            resolve(base, "//edge.example.com/session/1"),
+            "https://edge.example.com/session/1"
+        );
+        assert_eq!(
+            resolve(base, "/whep/session/1"),
+            "https://origin.example.com/whep/session/1"
+        );
+        assert_eq!(
+            resolve(base, "session/1"),
+            "https://origin.example.com/live/channel1/session/1"
+        );
+        assert_eq!(
+            resolve("http://localhost:8889", "session/1"),
+            "http://localhost:8889/session/1"
+        );
+    }
+
+    #[test]
+    fn random_ice_credentials() {
+        let (ufrag, pwd) = ice_credentials();
+        assert_eq!(ufrag.len(), 4);
+        assert_eq!(pwd.len(), 24);
+        assert!(
+            ufrag
+                .bytes()
+                .chain(pwd.bytes())
+                .all(|c| ICE_CHARS.contains(&c))
+        );
+        assert_ne!(ice_credentials(), ice_credentials());
+    }
+
+    #[test]
+    fn replaces_ice_credentials() {
+        let offer = set_ice_credentials(&offer(), "new1", "newnewnewnewnewnewnewnew");
+        assert_eq!(offer.matches("a=ice-ufrag:new1\r\n").count(), 2);
+        assert_eq!(
+            offer
+                .matches("a=ice-pwd:newnewnewnewnewnewnewnew\r\n")
+                .count(),
+            2
+        );
+        assert!(!offer.contains("old"));
+        assert_eq!(offer.lines().count(), 12);
+    }
+
+    #[test]
+    fn restart_sdpfrag() {
+        let frag = sdp(&[
+            "a=ice-ufrag:old1",
+            "a=ice-pwd:oldoldoldoldoldoldoldold",
+            "m=video 9 UDP/TLS/RTP/SAVPF 102",
+            "a=mid:video0",
+            "a=candidate:1 1 UDP 2015363327 192.0.2.10 50000 typ host",
+            "a=candidate:2 1 UDP 1015021823 198.51.100.7 50001 typ srflx",
+            "m=audio 9 UDP/TLS/RTP/SAVPF 111",
+            "a=mid:audio1",
+        ]);
+        assert_eq!(sdpfrag(&offer(), ""), frag);
+
+        // ICE gathered nothing new for the restart
+        let restart: String = offer()
+            .lines()
+            .filter(|line| !line.starts_with("a=candidate:"))
+            .map(|line| format!("{line}\r\n"))
+            .collect();
+        assert_eq!(sdpfrag(&restart, &offer()), frag);
+    }
+
+    #[test]
+    fn applies_the_restart_answer() {
+        let answer = sdp(&[
+            "v=0",
+            "a=group:BUNDLE video0 audio1",
+            "m=video 9 UDP/TLS/RTP/SAVPF 102",
+            "a=ice-ufrag:srv1",
+            "a=ice-pwd:serverserverserverserver",
+            "a=mid:video0",
+            "a=candidate:1 1 udp 2130706431 203.0.113.1 40000 typ host",
+            "a=end-of-candidates",
+            "m=audio 9 UDP/TLS/RTP/SAVPF 111",
+            "a=ice-ufrag:srv1",
+            "a=ice-pwd:serverserverserverserver",
+            "a=mid:audio1",
+        ]);
+
+        let restarted = apply_sdpfrag(
+            &answer,
+            &sdp(&[
+                "a=ice-ufrag:srv2",
+                "a=ice-pwd:restartrestartrestartrest",
+                "m=video 9 UDP/TLS/RTP/SAVPF 102",
+                "a=mid:video0",
+                "a=candidate:3 1 udp 2130706431 203.0.113.2 40002 typ host",
+            ]),
+        );
+        assert_eq!(
+            restarted.as_deref(),
+            Some(
+                sdp(&[
+                    "v=0",
+                    "a=group:BUNDLE video0 audio1",
+                    "m=video 9 UDP/TLS/RTP/SAVPF 102",
+                    "a=ice-ufrag:srv2",
+                    "a=ice-pwd:restartrestartrestartrest",
+                    "a=mid:video0",
+                    "a=candidate:3 1 udp 2130706431 203.0.113.2 40002 typ host",
+                    "m=audio 9 UDP/TLS/RTP/SAVPF 111",
+                    "a=ice-ufrag:srv2",
+                    "a=ice-pwd:restartrestartrestartrest",
+                    "a=mid:audio1",
+                ])
+                .as_str()
+            )
+        );
+
+        // candidates without a media go to the first
+        let restarted = apply_sdpfrag(
+            &answer,
+            &sdp(&[
+                "a=ice-ufrag:srv2",
+                "a=ice-pwd:restartrestartrestartrest",
+                "a=candidate:3 1 udp 2130706431 203.0.113.2 40002 typ host",
+            ]),
+        )
+        .unwrap();
+        let candidate = restarted.find("a=candidate:3").unwrap();
+        assert!(candidate < restarted.find("m=audio").unwrap());
+
+        assert_eq!(apply_sdpfrag(&answer, ""), None);
+    }
+
+    #[test]
+    fn talks_to_the_session() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/whep", server.server_addr());
+
+        let origin = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for mut request in server.incoming_requests().take(4) {
+                let header = |name: &str| {
+                    request
+                        .headers()
+                        .iter()
+                        .find(|header| header.field.as_str().as_str().eq_ignore_ascii_case(name))
+                        .map(|header| header.value.to_string())
+                };
+                let summary = (
+                    request.method().to_string(),
+                    request.url().to_string(),
+                    header("Authorization"),
+                    header("Content-Type"),
+                    header("If-Match"),
+                );
+                let mut sent = String::new();
+                request.as_reader().read_to_string(&mut sent).unwrap();
+
+                let (status, body) = match (requests.len(), summary.0.as_str()) {
+                    (0, "POST") => (201, "answer"),
+                    (1, "PATCH") => (200, "a=ice-ufrag:srv2\r\n"),
+                    (2, "PATCH") => (405, ""),
+                    _ => (200, ""),
+                };
+                let mut response = tiny_http::Response::from_string(body).with_status_code(status);
+                if status == 201 {
+                    response.add_header(
+                        tiny_http::Header::from_bytes("Location", "/whep/session/1").unwrap(),
+                    );
+                }
+                request.respond(response).unwrap();
+                requests.push((summary, sent));
+            }
+            requests
+        });
+
+        let (session, answer) = subscribe(&endpoint, Some("secret"), "offer").unwrap();
+        assert_eq!(answer, "answer");
+        assert!(session.url.ends_with("/whep/session/1"));
+
+        assert_eq!(
+            session.restart_ice(Some("secret"), "a=ice-ufrag:new1\r\n"),
+            Ok(String::from("a=ice-ufrag:srv2\r\n"))
+        );
+        assert!(session.restart_ice(None, "").unwrap_err().contains("405"));
+        session.leave(Some("secret"));
+
+        let requests = origin.join().unwrap();
+        let bearer = Some(String::from("Bearer secret"));
+        assert_eq!(
+            requests[0].0,
+            (
+                String::from("POST"),
+                String::from("/whep"),
+                bearer.clone(),
+                Some(String::from("application/sdp")),
+                None,
+            )
+        );
+        assert_eq!(
+            requests[1].0,
+            (
+                String::from("PATCH"),
+                String::from("/whep/session/1"),
+                bearer.clone(),
+                Some(String::from("application/trickle-ice-sdpfrag")),
+                Some(String::from("*")),
+            )
+        );
+        assert_eq!(requests[0].1, "offer");
+        assert_eq!(requests[1].1, "a=ice-ufrag:new1\r\n");
+        assert_eq!(requests[2].0.2, None);
+        assert_eq!(
+            (requests[3].0.0.as_str(), requests[3].0.1.as_str()),
+            ("DELETE", "/whep/session/1")
+        );
+        assert_eq!(requests[3].0.2, bearer);
+    }
+}
@@ -0,0 +1,104 @@
+//! End to end run against the built-in mock WHEP endpoint: WHEP -> gateway -> SRT -> decode.
+//! Needs the GStreamer plugins the gateway itself needs (see README), no network access.
+
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use gst::prelude::*;
+use gstreamer as gst;
+
+use whep_srt::mock_whep::MockWhepServer;
+use whep_srt::{Args, Gateway, stats};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Decoded AAC buffers (~21ms each) to receive before the output counts as decodable
+const DECODED_BUFFERS: usize = 50;
+
+#[test]
+fn gateway_outputs_decodable_ts() {
+    let whep = MockWhepServer::bind("127.0.0.1:0").expect("could not start mock whep server");
+    let whep_url = whep.url();
+    let server = whep.clone();
+    let server_thread = std::thread::spawn(move || server.serve());
+
+    let srt_port = free_udp_port();
+    let args = Args::try_parse_from([
+        "whep-srt",
+        "--input-url",
+        &whep_url,
+        "--output-url",
+        &format!("srt://127.0.0.1:{srt_port}?mode=listener"),
+    ])
+    .unwrap();
+
+    let gateway = Gateway::new(args).expect("could not build gateway");
+    let runner = gateway.clone();
+    let runner = std::thread::spawn(move || runner.run());
+
+    wait_for("gateway to connect to the whep endpoint", || {
+        gateway.status().whep_connected
+    });
+
+    let receiver = gst::parse::launch(&format!(
+        "srtsrc uri=\"srt://127.0.0.1:{srt_port}?mode=caller\" ! tsdemux ! aacparse ! avdec_aac ! fakesink name=sink"
+    ))
+    .expect("could not create receiver pipeline")
+    .dynamic_cast::<gst::Pipeline>()
+    .unwrap();
+    let decoded = count_buffers(&receiver, "sink");
+
+    receiver.set_state(gst::State::Playing).unwrap();
+    wait_for("decoded audio from the srt output", || {
+        decoded.load(Ordering::Relaxed) >= DECODED_BUFFERS
+    });
+
+    // the mixer's silence source alone would also produce decodable output, so check that WebRTC media arrived too
+    let inbound = stats::collect(gateway.pipeline())
+        .webrtc
+        .map(|webrtc| webrtc.packets_received())
+        .unwrap_or_default();
+    assert!(inbound > 0, "no rtp packets received over whep");
+
+    receiver.set_state(gst::State::Null).unwrap();
+    gateway.stop();
+    runner.join().unwrap().expect("gateway ended with an error");
+
+    whep.shutdown();
+    server_thread.join().unwrap();
+}
+
+fn wait_for(what: &str, condition: impl Fn() -> bool) {
+    let started = Instant::now();
+
+    while !condition() {
+        assert!(started.elapsed() < TIMEOUT, "timed out waiting for {what}");
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+fn count_buffers(pipeline: &gst::Pipeline, sink: &str) -> Arc<AtomicUsize> {
+    let count = Arc::new(AtomicUsize::new(0));
+    let count_clone = count.clone();
+
+    pipeline
+        .by_name(sink)
+        .and_then(|sink| sink.static_pad("sink"))
+        .unwrap()
+        .add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+            count_clone.fetch_add(1, Ordering::Relaxed);
+            gst::PadProbeReturn::Ok
+        });
+
+    count
+}
+
+fn free_udp_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0")
+        .and_then(|socket| socket.local_addr())
+        .map(|address| address.port())
+        .expect("could not find a free port")
+}